@@ -0,0 +1,398 @@
+pub mod buffer {
+    use std::{borrow::Cow, ops::Range};
+
+    use ropey::{iter::Chars, Rope, RopeSlice};
+    use tree_sitter::{InputEdit, Point};
+
+    use crate::utility::utility::get_next_id;
+
+    /// An edit made to a [`Buffer`]: the bytes at `offset` that read `removed` now read
+    /// `inserted`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Change {
+        pub offset: usize,
+        pub removed: String,
+        pub inserted: String,
+    }
+
+    /// The text of a tab.
+    ///
+    /// A rope answers line and char index lookups and edits in O(log n). There is no flat
+    /// copy of the text: readers take slices, which borrow when they fall within one chunk of
+    /// the rope, or walk its chunks. Edits are recorded as [`Change`]s until
+    /// [`Buffer::take_changes`], which is how the undo history learns what was typed without
+    /// comparing whole texts. The syntax tree is kept up to date the same way through
+    /// [`Buffer::take_syntax_edits`].
+    pub struct Buffer {
+        id: usize,
+        /// Counts the edits, so that results computed from the text can tell they are stale
+        revision: usize,
+        rope: Rope,
+        changes: Vec<Change>,
+        syntax_edits: Vec<InputEdit>,
+    }
+
+    impl Buffer {
+        pub fn new(text: String) -> Self {
+            Self {
+                id: get_next_id(),
+                revision: 0,
+                rope: Rope::from_str(&text),
+                changes: Vec::new(),
+                syntax_edits: Vec::new(),
+            }
+        }
+
+        /// Tells buffers apart, e.g. after a tab loaded another file into a new buffer
+        pub fn get_id(&self) -> usize {
+            self.id
+        }
+
+        pub fn get_revision(&self) -> usize {
+            self.revision
+        }
+
+        pub fn len_bytes(&self) -> usize {
+            self.rope.len_bytes()
+        }
+
+        pub fn len_chars(&self) -> usize {
+            self.rope.len_chars()
+        }
+
+        /// Counts lines the way the editor does, splitting at `\n` only
+        pub fn len_lines(&self) -> usize {
+            self.rope.len_lines()
+        }
+
+        /// Chars on the 0-based `line`, leaving out its line break
+        pub fn line_len_chars(&self, line: usize) -> usize {
+            let line = self.rope.line(line);
+            let len = line.len_chars();
+            if len > 0 && line.char(len - 1) == '\n' {
+                len - 1
+            } else {
+                len
+            }
+        }
+
+        /// The text of the 0-based `line`, leaving out its line break
+        pub fn line(&self, line: usize) -> Cow<'_, str> {
+            match to_cow(self.rope.line(line)) {
+                Cow::Borrowed(text) => Cow::Borrowed(text.trim_end_matches('\n')),
+                Cow::Owned(mut text) => {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                    Cow::Owned(text)
+                }
+            }
+        }
+
+        /// Iterates over the lines, each with its line break
+        pub fn lines(&self) -> impl Iterator<Item = Cow<'_, str>> {
+            self.rope.lines().map(to_cow)
+        }
+
+        /// Iterates over the pieces the text is stored in, in order
+        pub fn chunks(&self) -> impl Iterator<Item = &str> {
+            self.rope.chunks()
+        }
+
+        /// The rest of the stored piece that `byte_idx` falls in, empty at the end of the text
+        pub fn chunk_at_byte(&self, byte_idx: usize) -> &str {
+            let (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(byte_idx);
+            &chunk[byte_idx - chunk_start..]
+        }
+
+        /// Iterates over the stored pieces of `byte_range`
+        pub fn chunks_in(&self, byte_range: Range<usize>) -> impl Iterator<Item = &str> {
+            self.rope.byte_slice(byte_range).chunks()
+        }
+
+        pub fn line_to_byte(&self, line: usize) -> usize {
+            self.rope.line_to_byte(line)
+        }
+
+        pub fn line_to_char(&self, line: usize) -> usize {
+            self.rope.line_to_char(line)
+        }
+
+        pub fn byte_to_line(&self, byte_idx: usize) -> usize {
+            self.rope.byte_to_line(byte_idx)
+        }
+
+        pub fn char_to_line(&self, char_idx: usize) -> usize {
+            self.rope.char_to_line(char_idx.min(self.len_chars()))
+        }
+
+        /// Converts a char index into a byte index, clamped to the end of the text
+        pub fn char_to_byte(&self, char_idx: usize) -> usize {
+            self.rope.char_to_byte(char_idx.min(self.len_chars()))
+        }
+
+        pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+            self.rope.byte_to_char(byte_idx)
+        }
+
+        pub fn is_char_boundary(&self, byte_idx: usize) -> bool {
+            byte_idx <= self.len_bytes()
+                && self.char_to_byte(self.byte_to_char(byte_idx)) == byte_idx
+        }
+
+        /// Iterates over the chars from `char_idx` on, or backwards with `prev`
+        pub fn chars_at(&self, char_idx: usize) -> Chars<'_> {
+            self.rope.chars_at(char_idx.min(self.len_chars()))
+        }
+
+        pub fn slice_chars(&self, char_range: Range<usize>) -> Cow<'_, str> {
+            let len_chars = self.len_chars();
+            to_cow(
+                self.rope
+                    .slice(char_range.start.min(len_chars)..char_range.end.min(len_chars)),
+            )
+        }
+
+        pub fn slice_bytes(&self, byte_range: Range<usize>) -> Cow<'_, str> {
+            to_cow(self.rope.byte_slice(byte_range))
+        }
+
+        pub fn replace_chars(&mut self, char_range: Range<usize>, text: &str) {
+            let byte_range = self.char_to_byte(char_range.start)..self.char_to_byte(char_range.end);
+            self.replace(byte_range, char_range, text);
+        }
+
+        pub fn replace_bytes(&mut self, byte_range: Range<usize>, text: &str) {
+            let char_range = self.byte_to_char(byte_range.start)..self.byte_to_char(byte_range.end);
+            self.replace(byte_range, char_range, text);
+        }
+
+        /// Replaces the whole text as one change, e.g. with its line endings converted. Only
+        /// the span between the parts that stay the same is edited.
+        pub fn set_text(&mut self, text: String) {
+            let prefix = self
+                .rope
+                .bytes()
+                .zip(text.bytes())
+                .take_while(|(old, new)| old == new)
+                .count();
+            let max_suffix = self.len_bytes().min(text.len()) - prefix;
+            let suffix = self
+                .rope
+                .bytes_at(self.len_bytes())
+                .reversed()
+                .zip(text.bytes().rev())
+                .take(max_suffix)
+                .take_while(|(old, new)| old == new)
+                .count();
+            // Both texts hold the same bytes around the edit, so their boundaries agree
+            let mut start = prefix;
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut suffix = suffix;
+            while !text.is_char_boundary(text.len() - suffix) {
+                suffix -= 1;
+            }
+            let old_end = self.len_bytes() - suffix;
+            self.replace_bytes(start..old_end, &text[start..text.len() - suffix]);
+        }
+
+        fn replace(&mut self, byte_range: Range<usize>, char_range: Range<usize>, text: &str) {
+            if byte_range.is_empty() && text.is_empty() {
+                return;
+            }
+            let start = self.get_point(byte_range.start);
+            let old_end = self.get_point(byte_range.end);
+            self.revision += 1;
+            let removed = self.slice_bytes(byte_range.clone()).into_owned();
+            self.rope.remove(char_range.clone());
+            self.rope.insert(char_range.start, text);
+            let new_end_byte = byte_range.start + text.len();
+            self.syntax_edits.push(InputEdit {
+                start_byte: byte_range.start,
+                old_end_byte: byte_range.end,
+                new_end_byte,
+                start_position: start,
+                old_end_position: old_end,
+                new_end_position: self.get_point(new_end_byte),
+            });
+            self.record(Change {
+                offset: byte_range.start,
+                removed,
+                inserted: text.to_string(),
+            });
+        }
+
+        /// Keeps `change`, joining it to the previous one when they touch, so that e.g.
+        /// typing over a selection is one change rather than a delete and an insert
+        fn record(&mut self, change: Change) {
+            if let Some(last) = self.changes.last_mut() {
+                if change.offset == last.offset + last.inserted.len() {
+                    last.removed.push_str(&change.removed);
+                    last.inserted.push_str(&change.inserted);
+                    return;
+                }
+                if change.offset + change.removed.len() == last.offset {
+                    last.removed.insert_str(0, &change.removed);
+                    last.inserted.insert_str(0, &change.inserted);
+                    last.offset = change.offset;
+                    return;
+                }
+            }
+            self.changes.push(change);
+        }
+
+        /// Returns the changes made since the last call, oldest first
+        pub fn take_changes(&mut self) -> Vec<Change> {
+            std::mem::take(&mut self.changes)
+        }
+
+        /// Returns the edits made since the last call, oldest first, as a syntax tree
+        /// needs them to be reparsed
+        pub fn take_syntax_edits(&mut self) -> Vec<InputEdit> {
+            std::mem::take(&mut self.syntax_edits)
+        }
+
+        /// The line and byte column of `byte_idx`, as of the rope's current text
+        fn get_point(&self, byte_idx: usize) -> Point {
+            let row = self.rope.byte_to_line(byte_idx);
+            Point::new(row, byte_idx - self.rope.line_to_byte(row))
+        }
+    }
+
+    impl std::fmt::Display for Buffer {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            self.chunks().try_for_each(|chunk| f.write_str(chunk))
+        }
+    }
+
+    /// Borrows the text of `slice` when it lies within one chunk and copies it otherwise
+    fn to_cow(slice: RopeSlice<'_>) -> Cow<'_, str> {
+        match slice.as_str() {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(slice.to_string()),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn lines_split_at_line_feeds_only() {
+            let buffer = Buffer::new("one\r\ntwo\rthree\n".into());
+
+            assert_eq!(buffer.len_lines(), 3);
+            assert_eq!(buffer.line_len_chars(0), 4);
+            assert_eq!(buffer.line_to_char(1), 5);
+            assert_eq!(buffer.char_to_line(6), 1);
+            assert_eq!(buffer.line_len_chars(2), 0);
+            assert_eq!(buffer.line(0), "one\r");
+            assert_eq!(buffer.line(1), "two\rthree");
+        }
+
+        #[test]
+        fn char_and_byte_indices_convert_both_ways() {
+            let buffer = Buffer::new("héllo wörld".into());
+
+            assert_eq!(buffer.char_to_byte(2), 3);
+            assert_eq!(buffer.byte_to_char(3), 2);
+            assert_eq!(buffer.char_to_byte(100), buffer.to_string().len());
+            assert_eq!(buffer.slice_chars(6..11), "wörld");
+        }
+
+        #[test]
+        fn edits_read_back_through_slices() {
+            let mut buffer = Buffer::new("héllo".into());
+
+            buffer.replace_chars(5..5, " wörld");
+            buffer.replace_chars(0..1, "");
+            buffer.replace_bytes(0..2, "E");
+
+            assert_eq!(buffer.to_string(), "Ello wörld");
+            assert_eq!(buffer.slice_bytes(5..11), "wörld");
+            assert_eq!(buffer.chunks_in(0..4).collect::<String>(), "Ello");
+            assert_eq!(buffer.line(0), "Ello wörld");
+        }
+
+        #[test]
+        fn set_text_records_only_the_span_that_changed() {
+            let mut buffer = Buffer::new("one\r\ntwö\r\nthree".into());
+
+            buffer.set_text("one\ntwö\nthree".into());
+
+            assert_eq!(buffer.to_string(), "one\ntwö\nthree");
+            assert_eq!(
+                buffer.take_changes(),
+                vec![Change {
+                    offset: 3,
+                    removed: "\r\ntwö\r".into(),
+                    inserted: "\ntwö".into(),
+                }]
+            );
+            buffer.set_text("one\ntwö\nthree".into());
+            assert!(buffer.take_changes().is_empty());
+        }
+
+        #[test]
+        fn typing_over_a_selection_is_one_change() {
+            let mut buffer = Buffer::new("a selected word".into());
+
+            buffer.replace_chars(2..10, "");
+            buffer.replace_chars(2..2, "x");
+            buffer.replace_chars(3..3, "y");
+
+            assert_eq!(
+                buffer.take_changes(),
+                vec![Change {
+                    offset: 2,
+                    removed: "selected".into(),
+                    inserted: "xy".into(),
+                }]
+            );
+            assert!(buffer.take_changes().is_empty());
+        }
+
+        #[test]
+        fn backspacing_is_one_change() {
+            let mut buffer = Buffer::new("abcd".into());
+
+            buffer.replace_chars(3..4, "");
+            buffer.replace_chars(2..3, "");
+
+            assert_eq!(
+                buffer.take_changes(),
+                vec![Change {
+                    offset: 2,
+                    removed: "cd".into(),
+                    inserted: "".into(),
+                }]
+            );
+        }
+
+        #[test]
+        fn syntax_edits_give_lines_and_byte_columns() {
+            let mut buffer = Buffer::new("fn main() {\n}".into());
+
+            buffer.replace_chars(11..11, "\n    let é = 1;");
+
+            let edits = buffer.take_syntax_edits();
+            assert_eq!(edits.len(), 1);
+            assert_eq!(edits[0].start_position, Point::new(0, 11));
+            assert_eq!(edits[0].old_end_position, Point::new(0, 11));
+            assert_eq!(edits[0].new_end_position, Point::new(1, 15));
+            assert_eq!(edits[0].new_end_byte, 27);
+        }
+
+        #[test]
+        fn separate_edits_are_separate_changes() {
+            let mut buffer = Buffer::new("one two".into());
+
+            buffer.replace_chars(7..7, "!");
+            buffer.replace_chars(3..3, "!");
+
+            assert_eq!(buffer.take_changes().len(), 2);
+        }
+    }
+}
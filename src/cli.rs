@@ -0,0 +1,164 @@
+pub mod cli {
+    use std::path::{Path, PathBuf};
+
+    pub const USAGE: &str =
+        "Usage: reditor [--wait] [--new-window] [FILE[:LINE[:COLUMN]] | DIR | -]...
+
+Opens each FILE in its own tab, jumping to LINE and COLUMN when given.
+A DIR opens every file directly inside it, and - reads standard input.
+If Reditor is already running, the files open as new tabs in its window.
+
+Options:
+  -w, --wait          Wait until the opened files are closed before exiting
+  -n, --new-window    Open a separate window instead of reusing a running one
+  -h, --help          Print this help";
+
+    /// A file named on the command line, with the 1-based position to jump to
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct FileArg {
+        pub path: PathBuf,
+        pub line: Option<usize>,
+        pub column: Option<usize>,
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Args {
+        pub files: Vec<FileArg>,
+        pub read_stdin: bool,
+        pub wait: bool,
+        pub new_window: bool,
+        pub show_help: bool,
+    }
+
+    pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut options_ended = false;
+        for arg in args {
+            match arg.as_str() {
+                "--" if !options_ended => options_ended = true,
+                "-" => parsed.read_stdin = true,
+                "-w" | "--wait" if !options_ended => parsed.wait = true,
+                "-n" | "--new-window" if !options_ended => parsed.new_window = true,
+                "-h" | "--help" if !options_ended => parsed.show_help = true,
+                _ if arg.starts_with('-') && !options_ended => {
+                    return Err(format!("Unknown option \"{}\"", arg));
+                }
+                _ => parsed.files.push(parse_file_arg(&arg)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Splits `path:line:column` or `path:line`, as printed by compilers and `grep -n`.
+    /// A path that exists as given is never split, so files with colons in their name still open.
+    pub fn parse_file_arg(arg: &str) -> FileArg {
+        let literal = FileArg {
+            path: PathBuf::from(arg),
+            line: None,
+            column: None,
+        };
+        if Path::new(arg).exists() {
+            return literal;
+        }
+
+        let mut numbers = Vec::new();
+        let mut rest = arg;
+        while numbers.len() < 2 {
+            let Some((head, tail)) = rest.rsplit_once(':') else {
+                break;
+            };
+            let Ok(number) = tail.parse::<usize>() else {
+                break;
+            };
+            if head.is_empty() || number == 0 {
+                break;
+            }
+            numbers.insert(0, number);
+            rest = head;
+        }
+
+        match numbers[..] {
+            [line] => FileArg {
+                path: PathBuf::from(rest),
+                line: Some(line),
+                column: None,
+            },
+            [line, column] => FileArg {
+                path: PathBuf::from(rest),
+                line: Some(line),
+                column: Some(column),
+            },
+            _ => literal,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn args(args: &[&str]) -> Result<Args, String> {
+            parse_args(args.iter().map(|arg| arg.to_string()))
+        }
+
+        #[test]
+        fn file_arg_with_line_and_column() {
+            let arg = parse_file_arg("/no/such/dir/main.rs:42:7");
+
+            assert_eq!(arg.path, PathBuf::from("/no/such/dir/main.rs"));
+            assert_eq!(arg.line, Some(42));
+            assert_eq!(arg.column, Some(7));
+        }
+
+        #[test]
+        fn file_arg_with_line_only() {
+            let arg = parse_file_arg("/no/such/dir/main.rs:42");
+
+            assert_eq!(arg.path, PathBuf::from("/no/such/dir/main.rs"));
+            assert_eq!(arg.line, Some(42));
+            assert_eq!(arg.column, None);
+        }
+
+        #[test]
+        fn file_arg_without_numbers_is_kept_whole() {
+            assert_eq!(
+                parse_file_arg("notes:todo").path,
+                PathBuf::from("notes:todo")
+            );
+            assert_eq!(
+                parse_file_arg("file.txt:0").path,
+                PathBuf::from("file.txt:0")
+            );
+            assert_eq!(parse_file_arg(":12").path, PathBuf::from(":12"));
+        }
+
+        #[test]
+        fn existing_file_with_colon_is_not_split() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("log:12");
+            std::fs::write(&path, b"text").unwrap();
+
+            let arg = parse_file_arg(path.to_str().unwrap());
+
+            assert_eq!(arg.path, path);
+            assert_eq!(arg.line, None);
+        }
+
+        #[test]
+        fn options_and_stdin_are_recognised() {
+            let parsed = args(&["--wait", "-n", "-", "a.txt", "--", "--b.txt"]).unwrap();
+
+            assert!(parsed.wait);
+            assert!(parsed.new_window);
+            assert!(parsed.read_stdin);
+            assert_eq!(parsed.files.len(), 2);
+            assert_eq!(parsed.files[1].path, PathBuf::from("--b.txt"));
+        }
+
+        #[test]
+        fn unknown_option_is_an_error() {
+            let err = args(&["--frobnicate"]).unwrap_err();
+
+            assert!(err.contains("--frobnicate"), "{}", err);
+        }
+    }
+}
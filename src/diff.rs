@@ -0,0 +1,177 @@
+pub mod diff {
+    use egui::{Color32, RichText, ScrollArea, Ui};
+
+    use crate::enums::enums::DiffKind;
+
+    /// Above this many line pairs a diff is too slow to compute on the UI thread
+    const MAX_DIFF_CELLS: usize = 4_000_000;
+
+    #[derive(Debug, PartialEq)]
+    pub struct DiffLine {
+        pub kind: DiffKind,
+        pub text: String,
+    }
+
+    /// Line by line diff from `old` to `new`, or `None` if the texts are too large to compare.
+    ///
+    /// Common leading and trailing lines are matched first, so the longest common subsequence
+    /// table only covers the part that actually changed.
+    pub fn diff_lines(old: &str, new: &str) -> Option<Vec<DiffLine>> {
+        let old_lines = old.split('\n').collect::<Vec<_>>();
+        let new_lines = new.split('\n').collect::<Vec<_>>();
+
+        let prefix = old_lines
+            .iter()
+            .zip(&new_lines)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let max_suffix = old_lines.len().min(new_lines.len()) - prefix;
+        let suffix = old_lines
+            .iter()
+            .rev()
+            .zip(new_lines.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+        let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+        if old_mid.len().saturating_mul(new_mid.len()) > MAX_DIFF_CELLS {
+            return None;
+        }
+
+        // lcs[i][j] is the common subsequence length of old_mid[i..] and new_mid[j..]
+        let width = new_mid.len() + 1;
+        let mut lcs = vec![0u32; (old_mid.len() + 1) * width];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let line = |kind, text: &str| DiffLine {
+            kind,
+            text: text.to_string(),
+        };
+        let mut lines = old_lines[..prefix]
+            .iter()
+            .map(|text| line(DiffKind::Same, text))
+            .collect::<Vec<_>>();
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() || j < new_mid.len() {
+            if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                lines.push(line(DiffKind::Same, old_mid[i]));
+                i += 1;
+                j += 1;
+            } else if i < old_mid.len()
+                && (j == new_mid.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                lines.push(line(DiffKind::Removed, old_mid[i]));
+                i += 1;
+            } else {
+                lines.push(line(DiffKind::Added, new_mid[j]));
+                j += 1;
+            }
+        }
+        lines.extend(
+            old_lines[old_lines.len() - suffix..]
+                .iter()
+                .map(|text| line(DiffKind::Same, text)),
+        );
+        Some(lines)
+    }
+
+    /// Counts the added and removed lines of a diff
+    pub fn count_changes(lines: &[DiffLine]) -> (usize, usize) {
+        let count = |kind| lines.iter().filter(|line| line.kind == kind).count();
+        (count(DiffKind::Added), count(DiffKind::Removed))
+    }
+
+    /// Shows the changed lines of a diff with a little context around them
+    pub fn show_diff(ui: &mut Ui, lines: &[DiffLine]) {
+        const CONTEXT: usize = 2;
+        let is_near_change = |i: usize| {
+            lines[i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(lines.len())]
+                .iter()
+                .any(|line| line.kind != DiffKind::Same)
+        };
+        ScrollArea::vertical()
+            .id_source("diff")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                let mut skipped = false;
+                for (i, line) in lines.iter().enumerate() {
+                    if !is_near_change(i) {
+                        skipped = true;
+                        continue;
+                    }
+                    if skipped {
+                        ui.monospace("…");
+                        skipped = false;
+                    }
+                    let (prefix, color) = match line.kind {
+                        DiffKind::Same => (" ", ui.visuals().text_color()),
+                        DiffKind::Removed => ("-", Color32::from_rgb(230, 90, 90)),
+                        DiffKind::Added => ("+", Color32::from_rgb(90, 200, 90)),
+                    };
+                    ui.label(
+                        RichText::new(format!("{} {}", prefix, line.text))
+                            .monospace()
+                            .color(color),
+                    );
+                }
+            });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn kinds(old: &str, new: &str) -> Vec<(DiffKind, String)> {
+            diff_lines(old, new)
+                .unwrap()
+                .into_iter()
+                .map(|line| (line.kind, line.text))
+                .collect()
+        }
+
+        #[test]
+        fn identical_texts_have_no_changes() {
+            let lines = diff_lines("a\nb", "a\nb").unwrap();
+
+            assert_eq!(count_changes(&lines), (0, 0));
+            assert_eq!(lines.len(), 2);
+        }
+
+        #[test]
+        fn changed_line_is_removed_then_added() {
+            assert_eq!(
+                kinds("a\nb\nc", "a\nB\nc"),
+                vec![
+                    (DiffKind::Same, "a".into()),
+                    (DiffKind::Removed, "b".into()),
+                    (DiffKind::Added, "B".into()),
+                    (DiffKind::Same, "c".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn insertions_and_deletions_keep_common_lines() {
+            let lines =
+                diff_lines("one\ntwo\nthree\nfour", "zero\none\nthree\nfour\nfive").unwrap();
+
+            assert_eq!(count_changes(&lines), (2, 1));
+            let same = lines
+                .iter()
+                .filter(|line| line.kind == DiffKind::Same)
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(same, vec!["one", "three", "four"]);
+        }
+    }
+}
@@ -1,27 +1,80 @@
 pub mod editor {
-    use std::cell::OnceCell;
+    use std::ops::Range;
+    use std::path::Path;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
     use eframe::App;
     use egui::CentralPanel;
     use egui::Color32;
-    use egui::Key;
-    use egui::Modifiers;
+    use egui::ScrollArea;
     use egui::TopBottomPanel;
+    use egui::ViewportCommand;
+    use egui::Window;
     use egui_dock::DockArea;
     use egui_dock::DockState;
+    use egui_dock::NodeIndex;
     use egui_dock::Style;
+    use egui_dock::Surface;
+    use egui_dock::SurfaceIndex;
+    use egui_dock::TabIndex;
 
-    use crate::tab::tab::MyTabViewer;
-    use crate::utility::utility::get_line_ending_format;
+    #[cfg(unix)]
+    use std::io::Write;
+    #[cfg(unix)]
+    use std::os::unix::net::{UnixListener, UnixStream};
+    #[cfg(unix)]
+    use std::sync::mpsc::{self, Receiver};
+
+    use crate::buffer::buffer::Buffer;
+    use crate::cli::cli::{Args, FileArg};
+    use crate::diff::diff::{count_changes, show_diff};
+    use crate::encoding::encoding::get_encodings;
+    use crate::file_io::file_io::list_files;
+    #[cfg(unix)]
+    use crate::instance::instance::{serve, OpenRequest};
+    use crate::keymap::keymap::{
+        get_keymap_path, has_choices, CommandChoice, CommandOption, Keymap,
+    };
+    use crate::palette::palette::{
+        CommandPalette, PalettePick, RecentCommands, RECENT_COMMANDS_KEY,
+    };
+    use crate::recovery::recovery::{
+        discard_snapshot, find_leftover_snapshots, get_recovery_root, LeftoverSnapshot,
+        RecoveryDir, AUTOSAVE_INTERVAL,
+    };
+    use crate::search::search::{Matcher, Replacement, SearchOptions};
+    use crate::session::session::{
+        RecentFiles, Session, TabSession, RECENT_FILES_KEY, SESSION_KEY,
+    };
+    use crate::tab::tab::{get_language_picker_id, MyTabViewer};
+    use crate::theme::theme::{get_themes_dir, Themes, THEME_KEY};
+    use crate::utility::utility::{
+        get_line_col_char_idx, get_match_position, get_next_word_idx, get_prev_word_idx,
+        parse_goto_target,
+    };
     use crate::{
-        enums::enums::{FindBarState, GoToState, ReplaceBarState},
+        enums::enums::{
+            BackupMode, Command, FindBarState, GoToState, LineEnding, ReplaceBarState,
+            UnsavedChangesAction, UnsavedChangesChoice,
+        },
         tab::tab::TextEditorTab,
     };
 
+    /// How many closed tabs "Reopen Closed Tab" can bring back
+    const MAX_CLOSED_TABS: usize = 20;
+
+    /// Matches are counted up to this many, so that a common query in a large file doesn't
+    /// scan all of it on every edit
+    const MAX_COUNTED_MATCHES: usize = 10_000;
+
+    /// How often open files are checked for changes made by other programs
+    const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
     pub struct StateManager {
         find_state: FindBarState,
         is_find_open: bool,
         pub find_val: String,
+        search_options: SearchOptions,
         replace_state: ReplaceBarState,
         is_replace_open: bool,
         is_replace_active: bool,
@@ -29,6 +82,17 @@ pub mod editor {
         goto_state: GoToState,
         is_goto_open: bool,
         pub goto_val: String,
+        unsaved_changes_action: Option<UnsavedChangesAction>,
+        is_close_confirmed: bool,
+        wait_tab_ids: Vec<usize>,
+        is_session_enabled: bool,
+        is_discarding_changes: bool,
+        is_save_all_requested: bool,
+        /// The last compiled find value, with the value and options it was compiled from
+        matcher: Option<(String, SearchOptions, Result<Matcher, String>)>,
+        /// The first matches of `matcher` in the buffer with the id and revision they were
+        /// found in, at most [`MAX_COUNTED_MATCHES`] and one more to tell that there are more
+        matches: Option<(usize, usize, Vec<Range<usize>>)>,
     }
 
     impl StateManager {
@@ -66,6 +130,48 @@ pub mod editor {
             self.find_val = find_val;
         }
 
+        pub fn get_search_options(&self) -> SearchOptions {
+            self.search_options
+        }
+
+        pub fn set_search_options(&mut self, search_options: SearchOptions) {
+            self.search_options = search_options;
+        }
+
+        /// Compiles the find value with the current search options. The pattern is only
+        /// compiled again once either of them changes.
+        pub fn get_matcher(&mut self) -> Result<Matcher, String> {
+            let is_stale = self.matcher.as_ref().is_none_or(|(find_val, options, _)| {
+                *find_val != self.find_val || *options != self.search_options
+            });
+            if is_stale {
+                let matcher = Matcher::new(&self.find_val, self.search_options);
+                self.matcher = Some((self.find_val.clone(), self.search_options, matcher));
+                self.matches = None;
+            }
+            self.matcher.as_ref().expect("compiled above").2.clone()
+        }
+
+        /// The byte ranges of the first [`MAX_COUNTED_MATCHES`] matches in `buffer`, in text
+        /// order, and whether there are more. They are only looked for again once the find
+        /// value, the search options or the buffer change.
+        pub fn get_matches(&mut self, buffer: &Buffer) -> Result<(&[Range<usize>], bool), String> {
+            let matcher = self.get_matcher()?;
+            let is_stale = self.matches.as_ref().is_none_or(|(id, revision, _)| {
+                *id != buffer.get_id() || *revision != buffer.get_revision()
+            });
+            if is_stale {
+                let matches = matcher
+                    .find_all(buffer)
+                    .take(MAX_COUNTED_MATCHES + 1)
+                    .collect();
+                self.matches = Some((buffer.get_id(), buffer.get_revision(), matches));
+            }
+            let matches = &self.matches.as_ref().expect("found above").2;
+            let counted = matches.len().min(MAX_COUNTED_MATCHES);
+            Ok((&matches[..counted], matches.len() > counted))
+        }
+
         pub fn get_replace_state(&self) -> ReplaceBarState {
             self.replace_state.clone()
         }
@@ -141,11 +247,54 @@ pub mod editor {
         pub fn set_goto_val(&mut self, goto_val: String) {
             self.goto_val = goto_val;
         }
-    }
 
-    struct KeyManager {
-        keys_pressed: Vec<Key>,
-        modifiers_pressed: Vec<Modifiers>,
+        pub fn get_unsaved_changes_action(&self) -> Option<UnsavedChangesAction> {
+            self.unsaved_changes_action
+        }
+
+        pub fn set_unsaved_changes_action(&mut self, action: Option<UnsavedChangesAction>) {
+            self.unsaved_changes_action = action;
+        }
+
+        pub fn get_is_close_confirmed(&self) -> bool {
+            self.is_close_confirmed
+        }
+
+        pub fn set_is_close_confirmed(&mut self, is_close_confirmed: bool) {
+            self.is_close_confirmed = is_close_confirmed;
+        }
+
+        pub fn get_wait_tab_ids(&self) -> &[usize] {
+            &self.wait_tab_ids
+        }
+
+        pub fn set_wait_tab_ids(&mut self, wait_tab_ids: Vec<usize>) {
+            self.wait_tab_ids = wait_tab_ids;
+        }
+
+        pub fn get_is_session_enabled(&self) -> bool {
+            self.is_session_enabled
+        }
+
+        pub fn set_is_session_enabled(&mut self, is_session_enabled: bool) {
+            self.is_session_enabled = is_session_enabled;
+        }
+
+        pub fn get_is_discarding_changes(&self) -> bool {
+            self.is_discarding_changes
+        }
+
+        pub fn set_is_discarding_changes(&mut self, is_discarding_changes: bool) {
+            self.is_discarding_changes = is_discarding_changes;
+        }
+
+        pub fn get_is_save_all_requested(&self) -> bool {
+            self.is_save_all_requested
+        }
+
+        pub fn set_is_save_all_requested(&mut self, is_save_all_requested: bool) {
+            self.is_save_all_requested = is_save_all_requested;
+        }
     }
 
     pub struct CursorIndexManager {
@@ -171,106 +320,950 @@ pub mod editor {
         }
     }
 
+    /// Key of the backup mode picked in the File menu in eframe's storage
+    pub const BACKUP_MODE_KEY: &str = "backup_mode";
+
+    pub struct Settings {
+        backup_mode: BackupMode,
+    }
+
+    impl Settings {
+        pub fn get_backup_mode(&self) -> BackupMode {
+            self.backup_mode
+        }
+
+        pub fn set_backup_mode(&mut self, backup_mode: BackupMode) {
+            self.backup_mode = backup_mode;
+        }
+    }
+
+    /// Everything the editor keeps besides its tabs. Commands that act on a tab take it as an
+    /// argument, so they run without a window, e.g. in tests.
     pub struct TextEditor {
-        pub dock_state: DockState<TextEditorTab>,
         pub state_manager: StateManager,
-        pub key_manager: KeyManager,
+        pub settings: Settings,
+        pub keymap: Keymap,
+        /// Problems found in the user's keymap file, shown until dismissed
+        pub keymap_problems: Vec<String>,
+        pub themes: Themes,
+        /// Problems found in the user's theme files, shown until dismissed
+        pub theme_problems: Vec<String>,
+        pub palette: CommandPalette,
         pub cursor_index_manager: CursorIndexManager,
-        pub row_size: f32,
+        /// Files forwarded by later launches, see [`TextEditor::serve_other_instances`]
+        #[cfg(unix)]
+        pub open_requests: Option<Receiver<OpenRequest>>,
+        /// Connections of `--wait` launches, with the ids of the tabs they opened
+        #[cfg(unix)]
+        pub waiters: Vec<(Vec<usize>, UnixStream)>,
+        pub recovery: Option<RecoveryDir>,
+        /// When open files were last compared with the disk, see [`TextEditorTab::check_disk`]
+        pub last_disk_check_at: Instant,
+        /// Snapshots of a crashed window, offered in the recovery dialog
+        pub leftover_snapshots: Vec<LeftoverSnapshot>,
+        pub recent_files: RecentFiles,
+        /// Most recently closed tab last
+        closed_tabs: Vec<TabSession>,
+        /// Tabs to add once the dock area is no longer being drawn, see [`TextEditor::open_tab`]
+        pending_tabs: Vec<TextEditorTab>,
     }
 
     impl TextEditor {
-        pub fn set_find_open(&mut self) {
-            if !self.state_manager.get_is_find_open() {
-                self.state_manager.set_is_find_open(
-                    self.key_manager.keys_pressed.contains(&Key::F)
-                        && self
-                            .key_manager
-                            .modifiers_pressed
-                            .contains(&Modifiers::CTRL),
-                );
+        pub fn new(recent_files: RecentFiles) -> Self {
+            Self {
+                state_manager: StateManager {
+                    find_state: FindBarState::NotFocused,
+                    is_find_open: false,
+                    find_val: "".to_string(),
+                    search_options: SearchOptions::default(),
+                    replace_state: ReplaceBarState::NotFocused,
+                    is_replace_open: false,
+                    is_replace_active: false,
+                    replace_val: "".to_string(),
+                    goto_state: GoToState::NotFocused,
+                    is_goto_open: false,
+                    goto_val: "".to_string(),
+                    unsaved_changes_action: None,
+                    is_close_confirmed: false,
+                    wait_tab_ids: Vec::new(),
+                    is_session_enabled: true,
+                    is_discarding_changes: false,
+                    is_save_all_requested: false,
+                    matcher: None,
+                    matches: None,
+                },
+                settings: Settings {
+                    backup_mode: BackupMode::None,
+                },
+                keymap: Keymap::default(),
+                keymap_problems: Vec::new(),
+                themes: Themes::default(),
+                theme_problems: Vec::new(),
+                palette: CommandPalette::default(),
+                cursor_index_manager: CursorIndexManager {
+                    start_idx: 0,
+                    end_idx: 0,
+                },
+                #[cfg(unix)]
+                open_requests: None,
+                #[cfg(unix)]
+                waiters: Vec::new(),
+                recovery: None,
+                last_disk_check_at: Instant::now(),
+                leftover_snapshots: Vec::new(),
+                recent_files,
+                closed_tabs: Vec::new(),
+                pending_tabs: Vec::new(),
             }
+        }
+
+        pub fn set_find_open(&mut self) {
+            self.state_manager.set_is_find_open(true);
             self.state_manager.set_find_state(FindBarState::Focused);
         }
 
         pub fn set_replace_open(&mut self) {
-            if !self.state_manager.get_is_find_open() {
-                self.state_manager.set_is_find_open(true);
-            }
+            self.state_manager.set_is_find_open(true);
             self.state_manager.set_find_state(FindBarState::NotFocused);
-            if !self.state_manager.get_is_replace_open() {
-                self.state_manager.set_is_replace_open(
-                    self.key_manager.keys_pressed.contains(&Key::R)
-                        && self
-                            .key_manager
-                            .modifiers_pressed
-                            .contains(&Modifiers::CTRL),
-                );
-            }
+            self.state_manager.set_is_replace_open(true);
             self.state_manager
                 .set_replace_state(ReplaceBarState::Focused);
         }
+
+        /// Runs `command`, as bound to keys or picked from a menu. Commands that act on a tab
+        /// run on `tab` and do nothing without one.
+        pub fn run_command(
+            &mut self,
+            command: Command,
+            tab: Option<&mut TextEditorTab>,
+            ctx: &egui::Context,
+        ) {
+            if has_choices(command) {
+                let choices = self.get_choices(command, tab.as_deref());
+                if !choices.is_empty() {
+                    self.palette.open_choices(command, choices);
+                }
+                return;
+            }
+            match command {
+                Command::NewTab => self.open_tab(TextEditorTab::new("Untitled".into(), "".into())),
+                // The other tabs can't be reached while the dock area is drawn
+                Command::SaveAll => self.state_manager.set_is_save_all_requested(true),
+                Command::ClearRecentFiles => self.recent_files.clear(),
+                Command::ReopenClosedTab => self.reopen_closed_tab(),
+                Command::CloseWindow => ctx.send_viewport_cmd(ViewportCommand::Close),
+                Command::Find => self.set_find_open(),
+                Command::Replace => self.set_replace_open(),
+                Command::GoTo => {
+                    self.state_manager.set_is_goto_open(true);
+                    self.state_manager.set_goto_state(GoToState::Focused);
+                }
+                Command::ShowCommandPalette => self.palette.open(),
+                Command::ChangeLanguage => {
+                    ctx.memory_mut(|mem| mem.open_popup(get_language_picker_id()));
+                }
+                Command::ToggleFullscreen => {
+                    let is_fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+                    ctx.send_viewport_cmd(ViewportCommand::Fullscreen(!is_fullscreen));
+                }
+                Command::ReloadThemes => {
+                    if let Some(dir) = get_themes_dir() {
+                        self.theme_problems = self.themes.load(&dir);
+                    }
+                }
+                _ => {
+                    if let Some(tab) = tab {
+                        tab.run_command(self, command, ctx);
+                    }
+                }
+            }
+        }
+
+        /// Lists the options of a command that has choices. It is empty when the command
+        /// can't run, e.g. Reopen with Encoding for a tab that has no file.
+        pub fn get_choices(
+            &self,
+            command: Command,
+            tab: Option<&TextEditorTab>,
+        ) -> Vec<CommandChoice> {
+            let encoding_choices = |tab: &TextEditorTab| {
+                get_encodings()
+                    .into_iter()
+                    .map(|encoding| CommandChoice {
+                        label: encoding.name().to_string(),
+                        option: CommandOption::Encoding(encoding),
+                        is_current: Some(tab.get_encoding() == encoding),
+                    })
+                    .collect()
+            };
+            match (command, tab) {
+                (Command::OpenRecent, _) => self
+                    .recent_files
+                    .get_paths()
+                    .iter()
+                    .map(|path| CommandChoice {
+                        label: path.clone(),
+                        option: CommandOption::RecentFile(path.clone()),
+                        is_current: None,
+                    })
+                    .collect(),
+                (Command::ReopenWithEncoding, Some(tab)) if !tab.get_file_path().is_empty() => {
+                    encoding_choices(tab)
+                }
+                (Command::SaveWithEncoding, Some(tab)) => encoding_choices(tab),
+                (Command::SetBackupMode, _) => {
+                    [BackupMode::None, BackupMode::Tilde, BackupMode::Timestamped]
+                        .into_iter()
+                        .map(|mode| CommandChoice {
+                            label: mode.to_string(),
+                            option: CommandOption::BackupMode(mode),
+                            is_current: Some(self.settings.get_backup_mode() == mode),
+                        })
+                        .collect()
+                }
+                (Command::ConvertLineEndings, Some(tab)) => {
+                    [LineEnding::Lf, LineEnding::Crlf, LineEnding::Cr]
+                        .into_iter()
+                        .map(|line_ending| CommandChoice {
+                            label: line_ending.to_string(),
+                            option: CommandOption::LineEnding(line_ending),
+                            is_current: Some(tab.get_line_ending() == line_ending),
+                        })
+                        .collect()
+                }
+                (Command::SelectTheme, _) => {
+                    let picked = self.themes.get_picked();
+                    let follow_system = CommandChoice {
+                        label: "Follow System".to_string(),
+                        option: CommandOption::Theme(None),
+                        is_current: Some(picked.is_none()),
+                    };
+                    std::iter::once(follow_system)
+                        .chain(self.themes.get_themes().iter().map(|theme| CommandChoice {
+                            label: theme.name.clone(),
+                            option: CommandOption::Theme(Some(theme.name.clone())),
+                            is_current: Some(picked == Some(theme.name.as_str())),
+                        }))
+                        .collect()
+                }
+                _ => Vec::new(),
+            }
+        }
+
+        /// Runs `command` with an option picked from its submenu or the command palette
+        pub fn run_choice(
+            &mut self,
+            command: Command,
+            option: CommandOption,
+            tab: Option<&mut TextEditorTab>,
+        ) {
+            match option {
+                CommandOption::RecentFile(path) => self.open_recent_file(&path, tab),
+                CommandOption::BackupMode(mode) => self.settings.set_backup_mode(mode),
+                CommandOption::Theme(name) => self.themes.pick(name),
+                _ => {
+                    if let Some(tab) = tab {
+                        tab.run_choice(self, command, option);
+                    }
+                }
+            }
+        }
+
+        /// Opens `path` in a new tab, or forgets it and reports the error in `tab`
+        fn open_recent_file(&mut self, path: &str, tab: Option<&mut TextEditorTab>) {
+            let mut recent_tab = TextEditorTab::new("Untitled".into(), "".into());
+            match recent_tab.open_file(Path::new(path), None) {
+                Ok(()) => {
+                    self.recent_files.add(path);
+                    self.open_tab(recent_tab);
+                }
+                Err(err) => {
+                    self.recent_files.remove(path);
+                    if let Some(tab) = tab {
+                        tab.report_error(Err(err));
+                    }
+                }
+            }
+        }
+
+        /// Finds the match at or after the find position in `tab` and moves the find
+        /// position onto it
+        pub fn find_next(&mut self, tab: &TextEditorTab) -> Result<Range<usize>, String> {
+            let matcher = self.state_manager.get_matcher()?;
+            let start_idx = self.cursor_index_manager.get_start_idx();
+            let range = get_next_word_idx(tab.get_buffer(), &matcher, start_idx)
+                .ok_or_else(|| format!("Cannot find \"{}\"", self.state_manager.get_find_val()))?;
+            self.set_find_range(range.clone());
+            Ok(range)
+        }
+
+        /// Finds the closest match ending before the find position in `tab` and moves the
+        /// find position onto it
+        pub fn find_prev(&mut self, tab: &TextEditorTab) -> Result<Range<usize>, String> {
+            let matcher = self.state_manager.get_matcher()?;
+            let start_idx = self.cursor_index_manager.get_start_idx();
+            let range = get_prev_word_idx(tab.get_buffer(), &matcher, start_idx)
+                .ok_or_else(|| format!("Cannot find \"{}\"", self.state_manager.get_find_val()))?;
+            self.set_find_range(range.clone());
+            Ok(range)
+        }
+
+        /// Replaces the match at the find position, if it is one, and selects the next match
+        pub fn replace_next(&mut self, tab: &mut TextEditorTab) -> Result<Range<usize>, String> {
+            let matcher = self.state_manager.get_matcher()?;
+            let selection =
+                self.cursor_index_manager.get_start_idx()..self.cursor_index_manager.get_end_idx();
+            let range = tab
+                .replace_next(&matcher, &self.state_manager.get_replace_val(), selection)
+                .ok_or_else(|| format!("Cannot find \"{}\"", self.state_manager.get_find_val()))?;
+            self.set_find_range(range.clone());
+            tab.select(range.clone());
+            Ok(range)
+        }
+
+        /// Lists the changes Replace All would make to `tab`, to be confirmed by the user
+        pub fn preview_replace_all(
+            &mut self,
+            tab: &TextEditorTab,
+        ) -> Result<Vec<Replacement>, String> {
+            let matcher = self.state_manager.get_matcher()?;
+            Ok(matcher.replacements(tab.get_buffer(), &self.state_manager.get_replace_val()))
+        }
+
+        /// Moves the cursor of `tab` to the Go To input and closes the Go To bar.
+        /// `+N` and `-N` count from `current_line`.
+        pub fn go_to(
+            &mut self,
+            tab: &mut TextEditorTab,
+            current_line: usize,
+        ) -> Result<usize, String> {
+            let (line, column) =
+                parse_goto_target(&self.state_manager.get_goto_val(), current_line)?;
+            let char_idx = get_line_col_char_idx(tab.get_buffer(), line, column)?;
+            tab.select(char_idx..char_idx);
+            self.close_goto();
+            Ok(char_idx)
+        }
+
+        pub fn close_goto(&mut self) {
+            self.state_manager.set_is_goto_open(false);
+            self.state_manager.set_goto_state(GoToState::NotFocused);
+            self.state_manager.set_goto_val("".into());
+        }
+
+        /// Describes where the find position is among the matches in `tab`, e.g. "2 of 5"
+        pub fn get_match_counter(&mut self, tab: &TextEditorTab) -> String {
+            let buffer = tab.get_buffer();
+            let start = buffer.char_to_byte(self.cursor_index_manager.get_start_idx());
+            let position = self
+                .state_manager
+                .get_matches(buffer)
+                .map(|(matches, has_more)| {
+                    let is_past_counted = matches.last().is_some_and(|last| last.start < start);
+                    (
+                        get_match_position(matches, start),
+                        has_more,
+                        is_past_counted,
+                    )
+                });
+            match position {
+                Ok(((_, 0), ..)) => "No results".to_string(),
+                Ok(((current, total), false, _)) => format!("{} of {}", current, total),
+                Ok((_, true, true)) => format!("{}+", MAX_COUNTED_MATCHES),
+                Ok(((current, total), true, false)) => format!("{} of {}+", current, total),
+                Err(_) if self.state_manager.find_val.is_empty() => "".into(),
+                Err(_) => "Invalid".into(),
+            }
+        }
+
+        fn set_find_range(&mut self, range: Range<usize>) {
+            self.cursor_index_manager.set_start_idx(range.start);
+            self.cursor_index_manager.set_end_idx(range.end);
+        }
+
+        /// Saves `tab` with the current backup mode, adding it to "Open Recent".
+        /// Returns `false` if the save was cancelled or failed.
+        pub fn save_tab(&mut self, tab: &mut TextEditorTab) -> bool {
+            let is_saved = tab.save(self.settings.get_backup_mode());
+            if is_saved {
+                self.recent_files.add(tab.get_file_path());
+            }
+            is_saved
+        }
+
+        /// Saves `tab` to a new location, adding it to "Open Recent"
+        pub fn save_tab_as(&mut self, tab: &mut TextEditorTab) -> bool {
+            let is_saved = tab.save_as(self.settings.get_backup_mode());
+            if is_saved {
+                self.recent_files.add(tab.get_file_path());
+            }
+            is_saved
+        }
+
+        /// Asks for a file and opens it in `tab`, adding it to "Open Recent"
+        pub fn open_with_dialog(&mut self, tab: &mut TextEditorTab) {
+            if tab.open_with_dialog() {
+                self.recent_files.add(tab.get_file_path());
+            }
+        }
+
+        /// Adds `tab` next to the focused one. Tabs opened while the dock area is drawn wait
+        /// until the end of the frame, as the tab being drawn lives in the same list.
+        pub fn open_tab(&mut self, tab: TextEditorTab) {
+            self.pending_tabs.push(tab);
+        }
+
+        /// Keeps a closed tab, unsaved text included, for "Reopen Closed Tab"
+        pub fn remember_closed_tab(&mut self, tab: &TextEditorTab) {
+            if let Some(tab_session) = tab.to_session(false) {
+                self.closed_tabs.push(tab_session);
+                if self.closed_tabs.len() > MAX_CLOSED_TABS {
+                    self.closed_tabs.remove(0);
+                }
+            }
+        }
+
+        pub fn can_reopen_closed_tab(&self) -> bool {
+            !self.closed_tabs.is_empty()
+        }
+
+        /// Reopens the most recently closed tab, skipping files that no longer exist
+        pub fn reopen_closed_tab(&mut self) {
+            while let Some(tab_session) = self.closed_tabs.pop() {
+                if let Some(tab) = TextEditorTab::from_session(&tab_session) {
+                    self.open_tab(tab);
+                    return;
+                }
+            }
+        }
+
+        /// Lets later launches open their files here instead of in a new window
+        #[cfg(unix)]
+        pub fn serve_other_instances(&mut self, ctx: &egui::Context, listener: UnixListener) {
+            let (sender, receiver) = mpsc::channel();
+            let ctx = ctx.clone();
+            serve(listener, sender, move || ctx.request_repaint());
+            self.open_requests = Some(receiver);
+        }
     }
 
-    pub static mut TEXT_EDITOR: OnceCell<TextEditor> = OnceCell::new();
+    pub struct TextEditorApp {
+        pub dock_state: DockState<TextEditorTab>,
+        pub editor: TextEditor,
+    }
 
-    pub struct TextEditorApp;
+    /// Reopens the tabs of a saved session in their old layout, skipping files that are gone
+    fn restore_dock_state(session: &Session) -> DockState<TextEditorTab> {
+        let mut dock_state = session
+            .dock_state
+            .filter_map_tabs(TextEditorTab::from_session);
+        // Dropping every tab of the main surface removes it, which DockState can't handle
+        if let Some(Surface::Main(_)) = dock_state.get_surface(SurfaceIndex::main()) {
+            // Surfaces may have shifted, so don't trust the saved focus
+            dock_state.set_focused_node_and_surface((SurfaceIndex::main(), NodeIndex::root()));
+            return dock_state;
+        }
+        DockState::new(
+            session
+                .dock_state
+                .iter_all_tabs()
+                .filter_map(|(_, tab)| TextEditorTab::from_session(tab))
+                .collect(),
+        )
+    }
+
+    /// Returns the focused tab, or the active tab of the main surface if none is focused
+    fn get_active_tab(dock_state: &mut DockState<TextEditorTab>) -> Option<&mut TextEditorTab> {
+        if dock_state.focused_leaf().is_some() {
+            return dock_state.find_active_focused().map(|(_, tab)| tab);
+        }
+        dock_state
+            .main_surface_mut()
+            .find_active()
+            .map(|(_, tab)| tab)
+    }
+
+    /// Lists `problems` found in the user's files until they are dismissed
+    fn problems_dialog(
+        ctx: &egui::Context,
+        title: &str,
+        intro: Option<String>,
+        problems: &mut Vec<String>,
+    ) {
+        if problems.is_empty() {
+            return;
+        }
+
+        Window::new(title)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .min_width(300.0)
+            .show(ctx, |ui| {
+                if let Some(intro) = intro {
+                    ui.label(intro);
+                }
+                for problem in problems.iter() {
+                    ui.label(problem);
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui
+                        .button("OK")
+                        .on_hover_cursor(egui::CursorIcon::PointingHand)
+                        .clicked()
+                    {
+                        problems.clear();
+                    }
+                });
+            });
+    }
+
+    /// Creates a tab for every file, directory entry and stdin named on the command line
+    fn open_tabs(files: &[FileArg], stdin: Option<Vec<u8>>) -> Vec<TextEditorTab> {
+        let mut tabs = Vec::new();
+        for file in files {
+            if file.path.is_dir() {
+                match list_files(&file.path) {
+                    Ok(paths) => {
+                        for path in paths {
+                            let mut tab = TextEditorTab::new("Untitled".into(), "".into());
+                            let result = tab.open_file(&path, None);
+                            tab.report_error(result);
+                            tabs.push(tab);
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+                continue;
+            }
+
+            let mut tab = TextEditorTab::new("Untitled".into(), "".into());
+            let result = tab.open_or_create_file(&file.path);
+            if tab.report_error(result) {
+                if let Some(line) = file.line {
+                    tab.go_to(line, file.column.unwrap_or(1));
+                }
+            }
+            tabs.push(tab);
+        }
+
+        if let Some(bytes) = stdin {
+            let mut tab = TextEditorTab::new("stdin".into(), "".into());
+            tab.load_stdin(&bytes);
+            tabs.push(tab);
+        }
+        tabs
+    }
 
     impl TextEditorApp {
-        pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        pub fn new(cc: &eframe::CreationContext<'_>, args: &Args, stdin: Option<Vec<u8>>) -> Self {
             // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-            // Restore app state using cc.storage (requires the "persistence" feature).
             // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
             // for e.g. egui::PaintCallback.
-            let status = format!("Ln 1 Col 1 | 100% | {} | UTF-8", get_line_ending_format());
-            let editor = unsafe {
-                TEXT_EDITOR.get_or_init(|| {
-                    let dock_state = DockState::new(vec![TextEditorTab::new(
-                        "Untitled".into(),
-                        "".into(),
-                        status,
-                    )]);
-                    TextEditor {
-                        dock_state,
-                        state_manager: StateManager {
-                            find_state: FindBarState::NotFocused,
-                            is_find_open: false,
-                            find_val: "".to_string(),
-                            replace_state: ReplaceBarState::NotFocused,
-                            is_replace_open: false,
-                            is_replace_active: false,
-                            replace_val: "".to_string(),
-                            goto_state: GoToState::NotFocused,
-                            is_goto_open: false,
-                            goto_val: "".to_string(),
-                        },
-                        key_manager: KeyManager {
-                            keys_pressed: Vec::new(),
-                            modifiers_pressed: Vec::new(),
-                        },
-                        cursor_index_manager: CursorIndexManager {
-                            start_idx: 0,
-                            end_idx: 0,
-                        },
-                        row_size: 16.0,
-                    }
+
+            // A window opened with --new-window leaves the main window's session alone
+            let is_session_enabled = !args.new_window;
+            let session = cc
+                .storage
+                .filter(|_| is_session_enabled)
+                .and_then(|storage| eframe::get_value::<Session>(storage, SESSION_KEY));
+
+            let tabs = open_tabs(&args.files, stdin);
+            let recent_files = cc
+                .storage
+                .and_then(|storage| eframe::get_value::<RecentFiles>(storage, RECENT_FILES_KEY))
+                .unwrap_or_default();
+            let mut editor = TextEditor::new(recent_files);
+            editor
+                .state_manager
+                .set_is_session_enabled(is_session_enabled);
+            if let Some(backup_mode) = cc
+                .storage
+                .and_then(|storage| eframe::get_value::<BackupMode>(storage, BACKUP_MODE_KEY))
+            {
+                editor.settings.set_backup_mode(backup_mode);
+            }
+            editor.palette.recent_commands = cc
+                .storage
+                .and_then(|storage| {
+                    eframe::get_value::<RecentCommands>(storage, RECENT_COMMANDS_KEY)
                 })
-            };
-            cc.egui_ctx.set_visuals(egui::Visuals::dark());
-            cc.egui_ctx.screen_rect().set_right(
+                .unwrap_or_default();
+            if let Some(path) = get_keymap_path() {
+                (editor.keymap, editor.keymap_problems) = Keymap::load(&path);
+            }
+            if let Some(dir) = get_themes_dir() {
+                editor.theme_problems = editor.themes.load(&dir);
+            }
+            editor.themes.pick(
+                cc.storage
+                    .and_then(|storage| eframe::get_value::<Option<String>>(storage, THEME_KEY))
+                    .flatten(),
+            );
+            for tab in &tabs {
+                editor.recent_files.add(tab.get_file_path());
+            }
+            if args.wait {
                 editor
-                    .dock_state
-                    .main_surface()
-                    .root_node()
-                    .unwrap()
+                    .state_manager
+                    .set_wait_tab_ids(tabs.iter().map(|tab| tab.get_id()).collect());
+            }
+            let mut dock_state = match session {
+                Some(session) => restore_dock_state(&session),
+                None => DockState::new(Vec::new()),
+            };
+            for tab in tabs {
+                dock_state.push_to_focused_leaf(tab);
+            }
+            if dock_state.iter_all_tabs().next().is_none() {
+                dock_state = DockState::new(vec![TextEditorTab::new("Untitled".into(), "".into())]);
+            }
+
+            // Look for leftovers before this window's own folder exists
+            let recovery_root = get_recovery_root();
+            editor.leftover_snapshots = recovery_root
+                .as_deref()
+                .map(find_leftover_snapshots)
+                .unwrap_or_default();
+            editor.recovery = recovery_root.and_then(|root| {
+                RecoveryDir::create(&root)
+                    .map_err(|err| eprintln!("Autosave is disabled: {}", err))
+                    .ok()
+            });
+
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+
+            Self { dock_state, editor }
+        }
+
+        pub fn find_tab_mut(&mut self, id: usize) -> Option<&mut TextEditorTab> {
+            self.dock_state
+                .iter_all_tabs_mut()
+                .map(|(_, tab)| tab)
+                .find(|tab| tab.get_id() == id)
+        }
+
+        pub fn remove_tab(&mut self, id: usize) {
+            let location = self
+                .dock_state
+                .iter_all_tabs()
+                .find(|(_, tab)| tab.get_id() == id)
+                .map(|(location, _)| location);
+            if let Some((surface, node)) = location {
+                let tab_index = self.dock_state[surface][node]
                     .tabs()
-                    .unwrap()[0]
-                    .status
-                    .len() as f32,
+                    .and_then(|tabs| tabs.iter().position(|tab| tab.get_id() == id));
+                if let Some(tab_index) = tab_index {
+                    let tab = self
+                        .dock_state
+                        .remove_tab((surface, node, TabIndex(tab_index)));
+                    if let Some(tab) = tab {
+                        self.editor.remember_closed_tab(&tab);
+                    }
+                }
+            }
+        }
+
+        pub fn get_dirty_tab_titles(&self) -> Vec<String> {
+            self.dock_state
+                .iter_all_tabs()
+                .filter(|(_, tab)| tab.is_dirty())
+                .map(|(_, tab)| tab.get_title())
+                .collect()
+        }
+
+        /// Returns the tab that keyboard commands should act on
+        pub fn active_tab_mut(&mut self) -> Option<&mut TextEditorTab> {
+            get_active_tab(&mut self.dock_state)
+        }
+
+        /// Opens the files forwarded by other launches and tells `--wait` launches when
+        /// their tabs have been closed
+        #[cfg(unix)]
+        fn handle_open_requests(&mut self, ctx: &egui::Context) {
+            let Some(open_requests) = &self.editor.open_requests else {
+                return;
+            };
+            let requests = open_requests.try_iter().collect::<Vec<_>>();
+            for request in requests {
+                let tabs = open_tabs(&request.files, request.stdin);
+                let tab_ids = tabs.iter().map(|tab| tab.get_id()).collect();
+                for tab in tabs {
+                    self.editor.recent_files.add(tab.get_file_path());
+                    self.dock_state.push_to_focused_leaf(tab);
+                }
+                if let Some(waiter) = request.waiter {
+                    self.editor.waiters.push((tab_ids, waiter));
+                }
+                ctx.send_viewport_cmd(ViewportCommand::Focus);
+            }
+
+            let dock_state = &self.dock_state;
+            self.editor.waiters.retain_mut(|(tab_ids, waiter)| {
+                let is_open = dock_state
+                    .iter_all_tabs()
+                    .any(|(_, tab)| tab_ids.contains(&tab.get_id()));
+                if !is_open {
+                    let _ = waiter.write_all(b"done\n");
+                }
+                is_open
+            });
+        }
+
+        /// Saves every tab, as asked for from the File menu while the dock was drawn
+        fn save_all(&mut self) {
+            for (_, tab) in self.dock_state.iter_all_tabs_mut() {
+                self.editor.save_tab(tab);
+            }
+        }
+
+        /// Asks whether to save before a pending close or open discards changes, then carries
+        /// out the action unless the user cancelled or saving failed
+        fn unsaved_changes_dialog(&mut self, ctx: &egui::Context) {
+            let Some(action) = self.editor.state_manager.get_unsaved_changes_action() else {
+                return;
+            };
+
+            let dirty_titles = match action {
+                UnsavedChangesAction::CloseTab(id) | UnsavedChangesAction::Open(id) => self
+                    .find_tab_mut(id)
+                    .map(|tab| vec![tab.get_title()])
+                    .unwrap_or_default(),
+                UnsavedChangesAction::CloseWindow => self.get_dirty_tab_titles(),
+            };
+            let message = if dirty_titles.len() == 1 {
+                format!(
+                    "Do you want to save the changes you made to \"{}\"?",
+                    dirty_titles[0]
+                )
+            } else {
+                format!(
+                    "{} files have unsaved changes:\n{}",
+                    dirty_titles.len(),
+                    dirty_titles.join("\n")
+                )
+            };
+
+            let mut choice = None;
+            Window::new("Unsaved changes")
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .min_width(250.0)
+                .show(ctx, |ui| {
+                    ui.label(message);
+                    ui.label("Your changes will be lost if you don't save them.");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                        if ui
+                            .button("Cancel")
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            choice = Some(UnsavedChangesChoice::Cancel);
+                        }
+                        if ui
+                            .button("Don't Save")
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            choice = Some(UnsavedChangesChoice::DontSave);
+                        }
+                        if ui
+                            .button(if dirty_titles.len() > 1 {
+                                "Save All"
+                            } else {
+                                "Save"
+                            })
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            choice = Some(UnsavedChangesChoice::Save);
+                        }
+                    });
+                });
+
+            let Some(choice) = choice else {
+                return;
+            };
+            self.editor.state_manager.set_unsaved_changes_action(None);
+            if choice == UnsavedChangesChoice::Cancel {
+                return;
+            }
+
+            let should_save = choice == UnsavedChangesChoice::Save;
+            let editor = &mut self.editor;
+            match action {
+                UnsavedChangesAction::CloseTab(id) => {
+                    let tab = self
+                        .dock_state
+                        .iter_all_tabs_mut()
+                        .map(|(_, tab)| tab)
+                        .find(|tab| tab.get_id() == id);
+                    if should_save && !tab.is_some_and(|tab| editor.save_tab(tab)) {
+                        return;
+                    }
+                    self.remove_tab(id);
+                }
+                UnsavedChangesAction::Open(id) => {
+                    let tab = self
+                        .dock_state
+                        .iter_all_tabs_mut()
+                        .map(|(_, tab)| tab)
+                        .find(|tab| tab.get_id() == id);
+                    if let Some(tab) = tab {
+                        if should_save && !editor.save_tab(tab) {
+                            return;
+                        }
+                        editor.open_with_dialog(tab);
+                    }
+                }
+                UnsavedChangesAction::CloseWindow => {
+                    if should_save {
+                        for (_, tab) in self.dock_state.iter_all_tabs_mut() {
+                            if tab.is_dirty() && !editor.save_tab(tab) {
+                                return;
+                            }
+                        }
+                    }
+                    editor.state_manager.set_is_discarding_changes(!should_save);
+                    editor.state_manager.set_is_close_confirmed(true);
+                    ctx.send_viewport_cmd(ViewportCommand::Close);
+                }
+            }
+        }
+
+        /// Offers the snapshots a crashed window left behind, showing how each one differs
+        /// from the file on disk
+        fn recovery_dialog(&mut self, ctx: &egui::Context) {
+            let editor = &mut self.editor;
+            if editor.leftover_snapshots.is_empty() {
+                return;
+            }
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            let mut restored = Vec::new();
+            let mut discarded = Vec::new();
+            Window::new("Recover unsaved changes")
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .min_width(400.0)
+                .show(ctx, |ui| {
+                    ui.label(
+                        "Reditor did not close properly. These unsaved changes were recovered:",
+                    );
+                    ui.separator();
+                    ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                        for (i, leftover) in editor.leftover_snapshots.iter_mut().enumerate() {
+                            let tab = &leftover.snapshot.tab;
+                            let text = tab.text.as_deref().unwrap_or_default();
+                            let is_same = leftover.disk_text.as_deref() == Some(text);
+                            let name = if tab.file_path.is_empty() {
+                                tab.title.clone()
+                            } else {
+                                tab.file_path.clone()
+                            };
+                            let minutes = now.saturating_sub(leftover.snapshot.saved_at) / 60;
+                            ui.strong(name);
+                            ui.label(format!("Autosaved {} minutes ago", minutes));
+
+                            let status = match (&leftover.disk_text, &leftover.diff) {
+                                _ if tab.file_path.is_empty() => "Never saved".to_string(),
+                                (None, _) => "The file no longer exists".to_string(),
+                                _ if is_same => "Same as the file on disk".to_string(),
+                                (Some(_), Some(lines)) => {
+                                    let (added, removed) = count_changes(lines);
+                                    format!(
+                                        "Differs from the file on disk: {} lines added, {} removed",
+                                        added, removed
+                                    )
+                                }
+                                (Some(_), None) => "Differs from the file on disk".to_string(),
+                            };
+                            ui.label(status);
+
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .button("Restore")
+                                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                    .clicked()
+                                {
+                                    restored.push(i);
+                                }
+                                if ui
+                                    .button("Discard")
+                                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                    .clicked()
+                                {
+                                    discarded.push(i);
+                                }
+                                if leftover.diff.is_some() {
+                                    ui.toggle_value(&mut leftover.show_diff, "Compare");
+                                }
+                            });
+                            if let (true, Some(lines)) = (leftover.show_diff, &leftover.diff) {
+                                show_diff(ui, lines);
+                            }
+                            ui.separator();
+                        }
+                    });
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                        if ui
+                            .button("Discard All")
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            discarded.extend(0..editor.leftover_snapshots.len());
+                        }
+                        if ui
+                            .button("Restore All")
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            restored.extend(0..editor.leftover_snapshots.len());
+                        }
+                    });
+                });
+
+            if restored.is_empty() && discarded.is_empty() {
+                return;
+            }
+            let leftovers = std::mem::take(&mut editor.leftover_snapshots);
+            for (i, leftover) in leftovers.into_iter().enumerate() {
+                if restored.contains(&i) {
+                    if let Some(tab) = TextEditorTab::from_session(&leftover.snapshot.tab) {
+                        self.dock_state.push_to_focused_leaf(tab);
+                    }
+                } else if !discarded.contains(&i) {
+                    editor.leftover_snapshots.push(leftover);
+                    continue;
+                }
+                discard_snapshot(&leftover.path);
+            }
+        }
+
+        /// Lists what was wrong with the keymap file, which was loaded without those bindings
+        fn keymap_problems_dialog(&mut self, ctx: &egui::Context) {
+            let intro = get_keymap_path().map(|path| format!("{} has problems:", path.display()));
+            problems_dialog(
+                ctx,
+                "Keymap problems",
+                intro,
+                &mut self.editor.keymap_problems,
             );
-            egui_extras::install_image_loaders(&cc.egui_ctx);
+        }
 
-            Self
+        fn theme_problems_dialog(&mut self, ctx: &egui::Context) {
+            let intro = Some("Some themes could not be loaded:".to_string());
+            problems_dialog(
+                ctx,
+                "Theme problems",
+                intro,
+                &mut self.editor.theme_problems,
+            );
         }
 
         fn ui(&mut self, ui: &mut egui::Ui) {
@@ -294,182 +1287,133 @@ pub mod editor {
             };
             style.tab_bar.bg_fill = Color32::TRANSPARENT;
 
-            DockArea::new(unsafe { &mut TEXT_EDITOR.get_mut().unwrap().dock_state })
+            DockArea::new(&mut self.dock_state)
                 .style(style)
                 .show_add_buttons(true)
-                .show_inside(ui, &mut MyTabViewer);
+                .show_inside(
+                    ui,
+                    &mut MyTabViewer {
+                        editor: &mut self.editor,
+                    },
+                );
         }
-    }
 
-    impl App for TextEditorApp {
-        fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        /// Runs one frame of the editor. Everything [`App::update`] does happens here, as it
+        /// needs nothing from the native window, so tests can drive a headless context.
+        pub fn show(&mut self, ctx: &egui::Context) {
+            if ctx.input(|i| i.viewport().close_requested())
+                && !self.editor.state_manager.get_is_close_confirmed()
+                && !self.get_dirty_tab_titles().is_empty()
+            {
+                ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+                self.editor
+                    .state_manager
+                    .set_unsaved_changes_action(Some(UnsavedChangesAction::CloseWindow));
+            }
+
+            self.editor.themes.apply(ctx);
+
+            #[cfg(unix)]
+            self.handle_open_requests(ctx);
+
+            let has_dirty_tabs = !self.get_dirty_tab_titles().is_empty();
+            if let Some(recovery) = &mut self.editor.recovery {
+                if recovery.is_autosave_due() {
+                    recovery.autosave(self.dock_state.iter_all_tabs().map(|(_, tab)| tab));
+                }
+                if has_dirty_tabs {
+                    ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+                }
+            }
+
+            if self.editor.last_disk_check_at.elapsed() >= DISK_CHECK_INTERVAL {
+                self.editor.last_disk_check_at = Instant::now();
+                for (_, tab) in self.dock_state.iter_all_tabs_mut() {
+                    tab.check_disk();
+                }
+            }
+            if self
+                .dock_state
+                .iter_all_tabs()
+                .any(|(_, tab)| !tab.get_file_path().is_empty())
+            {
+                ctx.request_repaint_after(DISK_CHECK_INTERVAL);
+            }
+
+            // With --wait the caller is blocked on us, so hand control back once its files are closed
+            let wait_tab_ids = self.editor.state_manager.get_wait_tab_ids();
+            if !wait_tab_ids.is_empty()
+                && !self
+                    .dock_state
+                    .iter_all_tabs()
+                    .any(|(_, tab)| wait_tab_ids.contains(&tab.get_id()))
+            {
+                self.editor.state_manager.set_wait_tab_ids(Vec::new());
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+            }
+
+            // Bound keys never reach the widgets, so TextEdit's own undoer can't fight the
+            // tab history over Ctrl+Z
+            let commands = ctx.input_mut(|i| self.editor.keymap.handle_events(&mut i.events));
+            let picked = self.editor.palette.show(ctx, &self.editor.keymap);
+            for command in commands {
+                let tab = get_active_tab(&mut self.dock_state);
+                self.editor.run_command(command, tab, ctx);
+            }
+            let tab = get_active_tab(&mut self.dock_state);
+            match picked {
+                Some(PalettePick::Command(command)) => self.editor.run_command(command, tab, ctx),
+                Some(PalettePick::Choice(command, option)) => {
+                    self.editor.run_choice(command, option, tab)
+                }
+                None => {}
+            }
+
             CentralPanel::default().show(ctx, |ui| {
                 let mut is_enter_pressed = false;
-                let events = ui.input(|i| i.raw.clone()).events.clone();
+                // Keys taken by the command palette are left out
+                let events = ui.input(|i| i.events.clone());
                 for event in &events {
-                    match event {
-                        egui::Event::Key {
-                            key,
-                            physical_key: _,
-                            pressed,
-                            repeat: _,
-                            modifiers,
-                        } => {
-                            if modifiers.ctrl {
-                                if *pressed {
-                                    unsafe {
-                                        TEXT_EDITOR
-                                            .get_mut()
-                                            .unwrap()
-                                            .key_manager
-                                            .modifiers_pressed
-                                            .push(Modifiers::CTRL)
-                                    };
-                                } else {
-                                    unsafe {
-                                        TEXT_EDITOR
-                                            .get_mut()
-                                            .unwrap()
-                                            .key_manager
-                                            .modifiers_pressed
-                                            .retain(|&x| x != Modifiers::CTRL)
-                                    };
-                                }
-                            }
-                            match key {
-                                Key::F => {
-                                    if *pressed {
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .key_manager
-                                                .keys_pressed
-                                                .push(Key::F)
-                                        };
-                                        unsafe { TEXT_EDITOR.get_mut().unwrap().set_find_open() };
-                                    } else {
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .key_manager
-                                                .keys_pressed
-                                                .retain(|&x| x != Key::F)
-                                        };
-                                    }
-                                }
-                                Key::R => {
-                                    if *pressed {
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .key_manager
-                                                .keys_pressed
-                                                .push(Key::R)
-                                        };
-                                        unsafe {
-                                            TEXT_EDITOR.get_mut().unwrap().set_replace_open()
-                                        };
-                                    } else {
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .key_manager
-                                                .keys_pressed
-                                                .retain(|&x| x != Key::R)
-                                        };
-                                    }
-                                }
-                                Key::Enter => {
-                                    if *pressed {
-                                        is_enter_pressed = true;
-                                    } else {
-                                        is_enter_pressed = false;
-                                    }
-                                }
-                                _ => {}
-                            }
+                    if let egui::Event::Key {
+                        key,
+                        physical_key: _,
+                        pressed,
+                        repeat: _,
+                        modifiers: _,
+                    } = event
+                    {
+                        // A quick tap can bring the press and release in the same frame
+                        if *key == egui::Key::Enter {
+                            is_enter_pressed |= *pressed;
                         }
-                        _ => {}
                     }
                 }
 
-                let status = unsafe {
-                    TEXT_EDITOR
-                        .get()
-                        .unwrap()
-                        .dock_state
-                        .main_surface()
-                        .root_node()
-                        .unwrap()
-                        .tabs()
-                        .unwrap()
-                }[0]
-                .status
-                .clone();
-
-                let find_bar_state = unsafe {
-                    TEXT_EDITOR
-                        .get_mut()
-                        .unwrap()
-                        .state_manager
-                        .get_find_state()
+                let status = match self.editor.keymap.get_pending_text(ctx) {
+                    Some(keys) => format!("{} was pressed, waiting for the next key...", keys),
+                    None => self
+                        .active_tab_mut()
+                        .map(|tab| tab.status.clone())
+                        .unwrap_or_default(),
                 };
 
-                let replace_bar_state = unsafe {
-                    TEXT_EDITOR
-                        .get_mut()
-                        .unwrap()
-                        .state_manager
-                        .get_replace_state()
-                };
+                let find_bar_state = self.editor.state_manager.get_find_state();
+                let replace_bar_state = self.editor.state_manager.get_replace_state();
 
                 match find_bar_state {
                     FindBarState::Finding => {
                         if !is_enter_pressed {
                             self.ui(ui);
                         } else {
-                            println!("Enter pressed find");
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .cursor_index_manager
-                                    .start_idx += TEXT_EDITOR
-                                    .get()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_find_val()
-                                    .len()
-                            };
+                            let cursor_index_manager = &mut self.editor.cursor_index_manager;
+                            let end_idx = cursor_index_manager.get_end_idx();
+                            cursor_index_manager.set_start_idx(end_idx);
                         }
                     }
                     FindBarState::NotFocused => match replace_bar_state {
                         ReplaceBarState::Replacing => {
                             if is_enter_pressed {
-                                println!("Enter pressed replace");
-                                unsafe {
-                                    TEXT_EDITOR
-                                        .get_mut()
-                                        .unwrap()
-                                        .cursor_index_manager
-                                        .start_idx += TEXT_EDITOR
-                                        .get()
-                                        .unwrap()
-                                        .state_manager
-                                        .get_replace_val()
-                                        .len()
-                                };
-                                unsafe {
-                                    TEXT_EDITOR
-                                        .get_mut()
-                                        .unwrap()
-                                        .state_manager
-                                        .set_is_replace_active(true)
-                                };
+                                self.editor.state_manager.set_is_replace_active(true);
                             } else {
                                 self.ui(ui);
                             }
@@ -482,9 +1426,295 @@ pub mod editor {
                 }
 
                 TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
-                    ui.label(status);
+                    ui.horizontal(|ui| {
+                        ui.label(status);
+                        if let Some(tab) = self.active_tab_mut() {
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| tab.show_language_picker(ui),
+                            );
+                        }
+                    });
                 });
             });
+
+            self.unsaved_changes_dialog(ctx);
+            self.recovery_dialog(ctx);
+            self.keymap_problems_dialog(ctx);
+            self.theme_problems_dialog(ctx);
+
+            if self.editor.state_manager.get_is_save_all_requested() {
+                self.editor.state_manager.set_is_save_all_requested(false);
+                self.save_all();
+            }
+            if !self.editor.pending_tabs.is_empty() {
+                for tab in std::mem::take(&mut self.editor.pending_tabs) {
+                    self.dock_state.push_to_focused_leaf(tab);
+                }
+                ctx.request_repaint();
+            }
+        }
+    }
+
+    impl App for TextEditorApp {
+        fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+            // Unsaved buffers are kept by the session or were discarded on purpose
+            if let Some(recovery) = self.editor.recovery.take() {
+                recovery.remove();
+            }
+        }
+
+        fn save(&mut self, storage: &mut dyn eframe::Storage) {
+            eframe::set_value(storage, RECENT_FILES_KEY, &self.editor.recent_files);
+            eframe::set_value(
+                storage,
+                RECENT_COMMANDS_KEY,
+                &self.editor.palette.recent_commands,
+            );
+            eframe::set_value(storage, THEME_KEY, &self.editor.themes.get_picked());
+            eframe::set_value(
+                storage,
+                BACKUP_MODE_KEY,
+                &self.editor.settings.get_backup_mode(),
+            );
+            if !self.editor.state_manager.get_is_session_enabled() {
+                return;
+            }
+            let discard_changes = self.editor.state_manager.get_is_discarding_changes();
+            let session = Session {
+                dock_state: self
+                    .dock_state
+                    .filter_map_tabs(|tab| tab.to_session(discard_changes)),
+            };
+            eframe::set_value(storage, SESSION_KEY, &session);
+        }
+
+        fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+            let system_theme = frame.info().system_theme;
+            self.editor
+                .themes
+                .set_system_dark(system_theme.map(|theme| theme == eframe::Theme::Dark));
+            self.show(ctx);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn editor_with(find_val: &str, replace_val: &str) -> TextEditor {
+            let mut editor = TextEditor::new(RecentFiles::default());
+            editor.state_manager.set_find_val(find_val.into());
+            editor.state_manager.set_replace_val(replace_val.into());
+            editor
+        }
+
+        #[test]
+        fn find_next_and_prev_walk_the_matches_and_wrap() {
+            let mut editor = editor_with("ab", "");
+            let tab = TextEditorTab::new("Untitled".into(), "ab cd ab".into());
+
+            assert_eq!(editor.find_next(&tab), Ok(0..2));
+            assert_eq!(editor.get_match_counter(&tab), "1 of 2");
+            editor.cursor_index_manager.set_start_idx(2);
+            assert_eq!(editor.find_next(&tab), Ok(6..8));
+            assert_eq!(editor.get_match_counter(&tab), "2 of 2");
+            editor.cursor_index_manager.set_start_idx(8);
+            assert_eq!(editor.find_next(&tab), Ok(0..2));
+
+            assert_eq!(editor.find_prev(&tab), Ok(6..8));
+            assert_eq!(editor.find_prev(&tab), Ok(0..2));
+        }
+
+        #[test]
+        fn matches_are_found_again_only_after_a_change() {
+            let mut editor = editor_with("ab", "");
+            let mut buffer = Buffer::new("ab ab".into());
+            let state_manager = &mut editor.state_manager;
+
+            assert_eq!(
+                state_manager.get_matches(&buffer),
+                Ok((&[0..2, 3..5][..], false))
+            );
+            let revision = buffer.get_revision();
+            buffer.replace_chars(0..0, "ab");
+            assert_ne!(buffer.get_revision(), revision);
+            assert_eq!(state_manager.get_matches(&buffer).unwrap().0.len(), 3);
+
+            state_manager.set_find_val("b".into());
+            assert_eq!(
+                state_manager.get_matches(&buffer),
+                Ok((&[1..2, 3..4, 6..7][..], false))
+            );
+            state_manager.set_search_options(SearchOptions {
+                use_regex: true,
+                ..SearchOptions::default()
+            });
+            state_manager.set_find_val("(".into());
+            assert!(state_manager.get_matches(&buffer).is_err());
+        }
+
+        #[test]
+        fn find_ignores_case_unless_match_case_is_on() {
+            let mut editor = editor_with("cat", "");
+            let tab = TextEditorTab::new("Untitled".into(), "Cat cAT cat".into());
+
+            assert_eq!(editor.get_match_counter(&tab), "1 of 3");
+
+            editor.state_manager.set_search_options(SearchOptions {
+                match_case: true,
+                ..SearchOptions::default()
+            });
+            assert_eq!(editor.find_next(&tab), Ok(8..11));
+            assert_eq!(editor.get_match_counter(&tab), "1 of 1");
+        }
+
+        #[test]
+        fn whole_word_skips_matches_inside_words() {
+            let mut editor = editor_with("cat", "");
+            editor.state_manager.set_search_options(SearchOptions {
+                whole_word: true,
+                ..SearchOptions::default()
+            });
+            let tab = TextEditorTab::new("Untitled".into(), "concat cats cat".into());
+
+            assert_eq!(editor.find_next(&tab), Ok(12..15));
+            assert_eq!(editor.get_match_counter(&tab), "1 of 1");
+        }
+
+        #[test]
+        fn match_counter_stops_counting_at_the_cap() {
+            let mut editor = editor_with("a", "");
+            let tab = TextEditorTab::new("Untitled".into(), "a".repeat(MAX_COUNTED_MATCHES + 5));
+
+            assert_eq!(editor.get_match_counter(&tab), "1 of 10000+");
+            editor
+                .cursor_index_manager
+                .set_start_idx(MAX_COUNTED_MATCHES + 2);
+            assert_eq!(editor.get_match_counter(&tab), "10000+");
+        }
+
+        #[test]
+        fn find_reports_missing_and_invalid_patterns() {
+            let tab = TextEditorTab::new("Untitled".into(), "text".into());
+
+            let mut editor = editor_with("xyz", "");
+            assert_eq!(editor.find_next(&tab), Err("Cannot find \"xyz\"".into()));
+            assert_eq!(editor.get_match_counter(&tab), "No results");
+
+            editor.state_manager.set_search_options(SearchOptions {
+                use_regex: true,
+                ..SearchOptions::default()
+            });
+            editor.state_manager.set_find_val("(".into());
+            assert!(editor.find_next(&tab).is_err());
+            assert_eq!(editor.get_match_counter(&tab), "Invalid");
+        }
+
+        #[test]
+        fn find_matches_within_lines_and_across_line_breaks() {
+            let mut editor = editor_with("^b", "");
+            editor.state_manager.set_search_options(SearchOptions {
+                use_regex: true,
+                ..SearchOptions::default()
+            });
+            let tab = TextEditorTab::new("Untitled".into(), "ab\nb\r\nb".into());
+
+            assert_eq!(editor.get_match_counter(&tab), "1 of 2");
+            // `$` only matches before a line feed
+            editor.state_manager.set_find_val("b$".into());
+            assert_eq!(editor.get_match_counter(&tab), "1 of 2");
+            editor.state_manager.set_find_val(r"b\s+b".into());
+            assert_eq!(editor.find_next(&tab), Ok(1..4));
+            assert_eq!(editor.get_match_counter(&tab), "1 of 1");
+        }
+
+        #[test]
+        fn replace_next_replaces_the_selected_match_and_moves_on() {
+            let mut editor = editor_with("cat", "dog");
+            let mut tab = TextEditorTab::new("Untitled".into(), "cat cat".into());
+
+            // The first step only selects the match the cursor is not on yet
+            editor.cursor_index_manager.set_start_idx(0);
+            assert_eq!(editor.replace_next(&mut tab), Ok(0..3));
+            assert_eq!(tab.get_text(), "cat cat");
+
+            assert_eq!(editor.replace_next(&mut tab), Ok(4..7));
+            assert_eq!(tab.get_text(), "dog cat");
+            assert!(tab.is_dirty());
+
+            // The last match is replaced even though nothing is left to select
+            assert_eq!(
+                editor.replace_next(&mut tab),
+                Err("Cannot find \"cat\"".into())
+            );
+            assert_eq!(tab.get_text(), "dog dog");
+        }
+
+        #[test]
+        fn replace_all_is_previewed_then_applied_as_one_undo_step() {
+            let mut editor = editor_with("a", "o");
+            let mut tab = TextEditorTab::new("Untitled".into(), "a\nba".into());
+
+            let replacements = editor.preview_replace_all(&tab).unwrap();
+            assert_eq!(replacements.len(), 2);
+            assert_eq!(replacements[1].line, 1);
+
+            tab.apply_replacements(&replacements);
+            assert_eq!(tab.get_text(), "o\nbo");
+            tab.undo();
+            assert_eq!(tab.get_text(), "a\nba");
+        }
+
+        #[test]
+        fn regex_replace_expands_capture_groups() {
+            let mut editor = editor_with(r"(\w+)=(?<value>\d+)", "${value}=$1");
+            editor.state_manager.set_search_options(SearchOptions {
+                use_regex: true,
+                ..SearchOptions::default()
+            });
+            let mut tab = TextEditorTab::new("Untitled".into(), "a=1\nb=22".into());
+
+            let replacements = editor.preview_replace_all(&tab).unwrap();
+            let previewed: Vec<_> = replacements.iter().map(|r| &r.replace_with[..]).collect();
+            assert_eq!(previewed, ["1=a", "22=b"]);
+
+            editor.cursor_index_manager.set_start_idx(0);
+            assert_eq!(editor.replace_next(&mut tab), Ok(0..3));
+            assert_eq!(editor.replace_next(&mut tab), Ok(4..8));
+            assert_eq!(tab.get_text(), "1=a\nb=22");
+        }
+
+        #[test]
+        fn literal_replace_keeps_dollar_signs_as_typed() {
+            let mut editor = editor_with("(a)", "$1");
+            let mut tab = TextEditorTab::new("Untitled".into(), "(a) (a)".into());
+
+            let replacements = editor.preview_replace_all(&tab).unwrap();
+            assert_eq!(replacements[0].replace_with, "$1");
+
+            editor.cursor_index_manager.set_start_idx(0);
+            assert_eq!(editor.replace_next(&mut tab), Ok(0..3));
+            assert_eq!(editor.replace_next(&mut tab), Ok(3..6));
+            assert_eq!(tab.get_text(), "$1 (a)");
+        }
+
+        #[test]
+        fn go_to_moves_the_cursor_and_closes_the_bar() {
+            let mut editor = editor_with("", "");
+            let mut tab = TextEditorTab::new("Untitled".into(), "one\ntwo\nthree".into());
+            editor.state_manager.set_is_goto_open(true);
+
+            editor.state_manager.set_goto_val("2:3".into());
+            assert_eq!(editor.go_to(&mut tab, 1), Ok(6));
+            assert!(!editor.state_manager.get_is_goto_open());
+            assert_eq!(editor.state_manager.get_goto_val(), "");
+
+            editor.state_manager.set_goto_val("+2".into());
+            assert_eq!(editor.go_to(&mut tab, 1), Ok(8));
+
+            editor.state_manager.set_goto_val("9".into());
+            assert!(editor.go_to(&mut tab, 1).is_err());
         }
     }
 }
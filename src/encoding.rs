@@ -0,0 +1,176 @@
+pub mod encoding {
+    use chardet::{charset2encoding, detect};
+    use encoding_rs::{
+        Encoding, BIG5, EUC_JP, EUC_KR, GB18030, GBK, ISO_8859_15, ISO_8859_2, KOI8_R, SHIFT_JIS,
+        UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252,
+    };
+
+    /// Encodings offered by the "Reopen with Encoding" and "Save with Encoding" menus
+    pub fn get_encodings() -> [&'static Encoding; 15] {
+        [
+            UTF_8,
+            UTF_16LE,
+            UTF_16BE,
+            WINDOWS_1252,
+            WINDOWS_1250,
+            ISO_8859_2,
+            ISO_8859_15,
+            WINDOWS_1251,
+            KOI8_R,
+            SHIFT_JIS,
+            EUC_JP,
+            GBK,
+            GB18030,
+            BIG5,
+            EUC_KR,
+        ]
+    }
+
+    pub struct DecodedText {
+        pub text: String,
+        pub encoding: &'static Encoding,
+        pub has_bom: bool,
+    }
+
+    /// Decodes file contents, honouring a UTF-8/UTF-16 BOM first, then `forced_encoding`,
+    /// then UTF-8 if the bytes are valid, and finally whatever chardet guesses
+    pub fn decode_bytes(bytes: &[u8], forced_encoding: Option<&'static Encoding>) -> DecodedText {
+        let bom = Encoding::for_bom(bytes);
+        let (encoding, has_bom) = match (bom, forced_encoding) {
+            (Some((bom_encoding, _)), None) => (bom_encoding, true),
+            (Some((bom_encoding, _)), Some(forced)) => (forced, forced == bom_encoding),
+            (None, Some(forced)) => (forced, false),
+            (None, None) => (detect_encoding(bytes), false),
+        };
+
+        let body = match bom {
+            Some((_, bom_len)) if has_bom => &bytes[bom_len..],
+            _ => bytes,
+        };
+        let (text, _) = encoding.decode_without_bom_handling(body);
+
+        DecodedText {
+            text: text.into_owned(),
+            encoding,
+            has_bom,
+        }
+    }
+
+    fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+        if std::str::from_utf8(bytes).is_ok() {
+            return UTF_8;
+        }
+        let charset = detect(bytes).0;
+        Encoding::for_label(charset2encoding(&charset).as_bytes()).unwrap_or(WINDOWS_1252)
+    }
+
+    /// Encodes `text` for writing to disk, failing if a character has no mapping in `encoding`
+    pub fn encode_text(
+        text: &str,
+        encoding: &'static Encoding,
+        has_bom: bool,
+    ) -> Result<Vec<u8>, String> {
+        // encoding_rs only decodes UTF-16, so encode it by hand
+        let mut bytes = Vec::with_capacity(text.len());
+        if encoding == UTF_16LE || encoding == UTF_16BE {
+            let is_le = encoding == UTF_16LE;
+            if has_bom {
+                bytes.extend_from_slice(if is_le { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+            }
+            for unit in text.encode_utf16() {
+                bytes.extend_from_slice(&if is_le {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                });
+            }
+            return Ok(bytes);
+        }
+
+        if has_bom && encoding == UTF_8 {
+            bytes.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+        }
+        let (encoded, _, had_errors) = encoding.encode(text);
+        if had_errors {
+            return Err(format!(
+                "Some characters cannot be represented in {}. Save with a different encoding such as UTF-8.",
+                encoding.name()
+            ));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+
+    /// Label shown in the status bar and encoding menus
+    pub fn get_encoding_label(encoding: &'static Encoding, has_bom: bool) -> String {
+        if has_bom {
+            format!("{} with BOM", encoding.name())
+        } else {
+            encoding.name().to_string()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn boms_give_the_encoding_and_are_written_back() {
+            for (encoding, bom) in [
+                (UTF_8, &[0xEF, 0xBB, 0xBF][..]),
+                (UTF_16LE, &[0xFF, 0xFE][..]),
+                (UTF_16BE, &[0xFE, 0xFF][..]),
+            ] {
+                let bytes = encode_text("héllo", encoding, true).unwrap();
+                assert!(bytes.starts_with(bom), "{}", encoding.name());
+
+                let decoded = decode_bytes(&bytes, None);
+                assert_eq!(decoded.text, "héllo");
+                assert_eq!(decoded.encoding, encoding);
+                assert!(decoded.has_bom);
+            }
+        }
+
+        #[test]
+        fn forced_encoding_overrides_detection() {
+            let decoded = decode_bytes("héllo".as_bytes(), Some(WINDOWS_1252));
+            assert_eq!(decoded.text, "hÃ©llo");
+            assert_eq!(decoded.encoding, WINDOWS_1252);
+            assert!(!decoded.has_bom);
+
+            // A BOM of another encoding is then kept as text
+            let decoded = decode_bytes(&[0xEF, 0xBB, 0xBF, b'a'], Some(WINDOWS_1252));
+            assert_eq!(decoded.text, "ï»¿a");
+            assert!(!decoded.has_bom);
+
+            let plain = decode_bytes("héllo".as_bytes(), None);
+            assert_eq!(plain.encoding, UTF_8);
+            assert!(!plain.has_bom);
+        }
+
+        #[test]
+        fn utf_16_is_encoded_by_hand_in_both_byte_orders() {
+            // A char outside the BMP needs a surrogate pair
+            let text = "a€😀";
+
+            assert_eq!(
+                encode_text(text, UTF_16LE, false).unwrap(),
+                [0x61, 0x00, 0xAC, 0x20, 0x3D, 0xD8, 0x00, 0xDE]
+            );
+            assert_eq!(
+                encode_text(text, UTF_16BE, false).unwrap(),
+                [0x00, 0x61, 0x20, 0xAC, 0xD8, 0x3D, 0xDE, 0x00]
+            );
+            let bytes = encode_text(text, UTF_16BE, false).unwrap();
+            assert_eq!(decode_bytes(&bytes, Some(UTF_16BE)).text, text);
+        }
+
+        #[test]
+        fn characters_missing_from_the_encoding_are_an_error() {
+            let err = encode_text("€ and 😀", WINDOWS_1252, false).unwrap_err();
+
+            assert!(err.contains("windows-1252"), "{}", err);
+            assert_eq!(encode_text("€", WINDOWS_1252, false), Ok(vec![0x80]));
+        }
+    }
+}
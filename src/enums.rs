@@ -47,6 +47,113 @@ pub mod enums {
     }
   }
 
+  #[derive(Clone, Copy, PartialEq)]
+  pub enum EditKind {
+    Insert,
+    Delete,
+    Replace,
+  }
+
+  #[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+  pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+    Mixed,
+  }
+
+  impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::Crlf => write!(f, "CRLF"),
+            LineEnding::Cr => write!(f, "CR"),
+            LineEnding::Mixed => write!(f, "Mixed"),
+        }
+    }
+  }
+
+  #[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+  pub enum BackupMode {
+    None,
+    Tilde,
+    Timestamped,
+  }
+
+  impl std::fmt::Display for BackupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BackupMode::None => write!(f, "No backup"),
+            BackupMode::Tilde => write!(f, "Keep file~ backup"),
+            BackupMode::Timestamped => write!(f, "Keep timestamped backups"),
+        }
+    }
+  }
+
+  /// What to do once the user has answered the unsaved changes prompt
+  #[derive(Clone, Copy, PartialEq)]
+  pub enum UnsavedChangesAction {
+    CloseTab(usize),
+    CloseWindow,
+    Open(usize),
+  }
+
+  #[derive(Clone, Copy, PartialEq)]
+  pub enum UnsavedChangesChoice {
+    Save,
+    DontSave,
+    Cancel,
+  }
+
+  /// How the file behind a dirty tab changed on disk, shown until the user decides what to keep
+  #[derive(Clone, Copy, PartialEq)]
+  pub enum ExternalChange {
+    Modified,
+    Deleted,
+  }
+
+  /// How a line of a [`crate::diff::diff::diff_lines`] result relates to the old text
+  #[derive(Clone, Copy, Debug, PartialEq)]
+  pub enum DiffKind {
+    Same,
+    Removed,
+    Added,
+  }
+
+  /// An action that can be bound to keys, see [`crate::keymap::keymap::COMMANDS`]
+  #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+  pub enum Command {
+    NewTab,
+    Open,
+    OpenRecent,
+    ClearRecentFiles,
+    ReopenWithEncoding,
+    Save,
+    SaveAs,
+    SaveWithEncoding,
+    SaveAll,
+    SetBackupMode,
+    CloseTab,
+    ReopenClosedTab,
+    CloseWindow,
+    Undo,
+    Redo,
+    Cut,
+    Find,
+    FindNext,
+    FindPrevious,
+    Replace,
+    ReplaceAll,
+    GoTo,
+    ConvertLineEndings,
+    SelectAll,
+    ChangeLanguage,
+    ToggleFullscreen,
+    SelectTheme,
+    ReloadThemes,
+    ShowCommandPalette,
+  }
+
   impl std::fmt::Display for FindBarState {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -0,0 +1,416 @@
+pub mod file_io {
+    use std::{
+        ffi::OsString,
+        fs,
+        io::{self, ErrorKind, Write},
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    #[cfg(unix)]
+    use std::os::unix::fs::MetadataExt;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::enums::enums::BackupMode;
+
+    pub fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+        if path.is_dir() {
+            return Err(format!(
+                "Cannot open \"{}\" because it is a folder. Choose a file instead.",
+                path.display()
+            ));
+        }
+        fs::read(path).map_err(|err| describe_io_error("open", path, &err))
+    }
+
+    /// Saves `bytes` to `path` without ever leaving a half-written file behind.
+    ///
+    /// The data goes to a temporary file in the same folder, which is synced and then renamed
+    /// over the target, keeping the target's permissions. Depending on `backup_mode` the
+    /// previous version is copied aside first. Special files such as devices are written in place.
+    pub fn write_file(path: &Path, bytes: &[u8], backup_mode: BackupMode) -> Result<(), String> {
+        let describe = |err: io::Error| describe_io_error("save", path, &err);
+
+        // Write through symlinks instead of replacing the link with a regular file
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let metadata = fs::metadata(&target).ok();
+        if let Some(metadata) = &metadata {
+            if !metadata.is_file() {
+                return fs::write(&target, bytes).map_err(describe);
+            }
+            if metadata.permissions().readonly() {
+                return Err(describe(io::Error::from(ErrorKind::PermissionDenied)));
+            }
+            if let Some(backup_path) = get_backup_path(&target, backup_mode) {
+                fs::copy(&target, &backup_path)
+                    .map_err(|err| describe_io_error("back up", &target, &err))?;
+            }
+        }
+
+        let folder = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(".reditor-")
+            .suffix(".tmp")
+            .tempfile_in(folder)
+            .map_err(describe)?;
+        temp_file.write_all(bytes).map_err(describe)?;
+        if let Some(metadata) = &metadata {
+            temp_file
+                .as_file()
+                .set_permissions(metadata.permissions())
+                .map_err(describe)?;
+        }
+        temp_file.as_file().sync_all().map_err(describe)?;
+        temp_file
+            .persist(&target)
+            .map_err(|err| describe(err.error))?;
+
+        // Make the rename itself durable
+        #[cfg(unix)]
+        if let Ok(folder) = fs::File::open(folder) {
+            let _ = folder.sync_all();
+        }
+        Ok(())
+    }
+
+    /// Where the previous version of `path` is kept, if backups are enabled
+    pub fn get_backup_path(path: &Path, backup_mode: BackupMode) -> Option<PathBuf> {
+        let file_name = path.file_name()?;
+        let mut backup_name = OsString::new();
+        match backup_mode {
+            BackupMode::None => return None,
+            BackupMode::Tilde => {
+                backup_name.push(file_name);
+                backup_name.push("~");
+            }
+            BackupMode::Timestamped => {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs());
+                backup_name.push(file_name);
+                backup_name.push(format!(".{}.bak", format_timestamp(secs)));
+            }
+        }
+        Some(path.with_file_name(backup_name))
+    }
+
+    /// Formats seconds since the Unix epoch as a UTC `YYYYMMDD-HHMMSS` string
+    fn format_timestamp(secs: u64) -> String {
+        let days = (secs / 86400) as i64;
+        let secs_of_day = secs % 86400;
+
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        format!(
+            "{:04}{:02}{:02}-{:02}{:02}{:02}",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )
+    }
+
+    /// What a file looked like on disk when a tab last read or wrote it
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct FileStamp {
+        modified: Option<SystemTime>,
+        len: u64,
+        #[cfg(unix)]
+        dev: u64,
+        #[cfg(unix)]
+        ino: u64,
+    }
+
+    /// Returns the current stamp of `path`, or `None` if it no longer exists
+    pub fn get_file_stamp(path: &Path) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            #[cfg(unix)]
+            dev: metadata.dev(),
+            #[cfg(unix)]
+            ino: metadata.ino(),
+        })
+    }
+
+    /// Looks for the file `stamp` was taken from under a new name in its old folder,
+    /// e.g. after `git mv` or a rename in a file manager
+    #[cfg(unix)]
+    pub fn find_renamed_file(path: &Path, stamp: &FileStamp) -> Option<PathBuf> {
+        let folder = path.parent()?;
+        fs::read_dir(folder)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| {
+                entry.metadata().is_ok_and(|metadata| {
+                    metadata.is_file() && metadata.dev() == stamp.dev && metadata.ino() == stamp.ino
+                })
+            })
+            .map(|entry| entry.path())
+    }
+
+    /// Lists the regular files directly inside `dir`, skipping hidden ones, sorted by name
+    pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let entries = fs::read_dir(dir).map_err(|err| describe_io_error("open", dir, &err))?;
+        let mut files = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Returns `path` as a string, failing for paths that aren't valid UTF-8
+    pub fn get_path_string(path: &Path) -> Result<String, String> {
+        path.to_str().map(|path| path.to_string()).ok_or_else(|| {
+            format!(
+                "The path \"{}\" contains characters Reditor cannot handle. Rename the file or choose another location.",
+                path.display()
+            )
+        })
+    }
+
+    pub fn get_file_name(path: &Path) -> Result<String, String> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("\"{}\" is not a file path.", path.display()))?;
+        get_path_string(Path::new(file_name))
+    }
+
+    /// Turns an I/O error into a message for the error dialog that says what to do next
+    pub fn describe_io_error(action: &str, path: &Path, err: &io::Error) -> String {
+        let path_str = path.display();
+        match err.kind() {
+            ErrorKind::NotFound => match path.parent() {
+                Some(parent) if action == "save" && !parent.as_os_str().is_empty() => format!(
+                    "Cannot save \"{}\" because the folder \"{}\" does not exist. Use Save As to choose another location.",
+                    path_str,
+                    parent.display()
+                ),
+                _ => format!(
+                    "Cannot {} \"{}\" because it does not exist.",
+                    action, path_str
+                ),
+            },
+            ErrorKind::StorageFull => format!(
+                "Cannot {} \"{}\" because the disk is full. Free up some space and try again.",
+                action, path_str
+            ),
+            ErrorKind::PermissionDenied => format!(
+                "Cannot {} \"{}\" because permission was denied. Check the file permissions or use another location.",
+                action, path_str
+            ),
+            _ => format!("Cannot {} \"{}\": {}", action, path_str, err),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn read_missing_file_reports_not_found() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("missing.txt");
+
+            let err = read_file(&path).unwrap_err();
+
+            assert!(err.contains("does not exist"), "{}", err);
+        }
+
+        #[test]
+        fn read_folder_reports_folder() {
+            let dir = tempfile::tempdir().unwrap();
+
+            let err = read_file(dir.path()).unwrap_err();
+
+            assert!(err.contains("is a folder"), "{}", err);
+        }
+
+        #[test]
+        fn write_into_missing_folder_names_the_folder() {
+            let dir = tempfile::tempdir().unwrap();
+            let folder = dir.path().join("missing");
+            let path = folder.join("file.txt");
+
+            let err = write_file(&path, b"text", BackupMode::None).unwrap_err();
+
+            assert!(err.contains("does not exist"), "{}", err);
+            assert!(err.contains(&folder.display().to_string()), "{}", err);
+            assert!(err.contains("Save As"), "{}", err);
+        }
+
+        #[test]
+        fn permission_denied_is_reported() {
+            let err = io::Error::from(ErrorKind::PermissionDenied);
+
+            let message = describe_io_error("save", Path::new("/etc/passwd"), &err);
+
+            assert!(message.contains("permission was denied"), "{}", message);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn write_to_read_only_file_is_reported() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("read_only.txt");
+            fs::write(&path, b"text").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+            let err = write_file(&path, b"new text", BackupMode::None).unwrap_err();
+
+            assert!(err.contains("permission was denied"), "{}", err);
+            assert_eq!(fs::read(&path).unwrap(), b"text");
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn write_to_full_disk_is_reported() {
+            let err = write_file(Path::new("/dev/full"), b"text", BackupMode::None).unwrap_err();
+
+            assert!(err.contains("disk is full"), "{}", err);
+        }
+
+        #[test]
+        fn write_replaces_contents_without_leaving_temp_files() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            fs::write(&path, b"old").unwrap();
+
+            write_file(&path, b"new", BackupMode::None).unwrap();
+
+            assert_eq!(fs::read(&path).unwrap(), b"new");
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn write_preserves_permissions() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("script.sh");
+            fs::write(&path, b"echo old").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+            write_file(&path, b"echo new", BackupMode::None).unwrap();
+
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+
+        #[test]
+        fn tilde_backup_keeps_previous_version() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            fs::write(&path, b"old").unwrap();
+
+            write_file(&path, b"new", BackupMode::Tilde).unwrap();
+
+            assert_eq!(fs::read(dir.path().join("file.txt~")).unwrap(), b"old");
+            assert_eq!(fs::read(&path).unwrap(), b"new");
+        }
+
+        #[test]
+        fn timestamped_backup_keeps_previous_version() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            fs::write(&path, b"old").unwrap();
+
+            write_file(&path, b"new", BackupMode::Timestamped).unwrap();
+
+            let backups = fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|entry| entry != &path)
+                .collect::<Vec<_>>();
+            assert_eq!(backups.len(), 1);
+            assert!(backups[0].to_str().unwrap().ends_with(".bak"));
+            assert_eq!(fs::read(&backups[0]).unwrap(), b"old");
+        }
+
+        #[test]
+        fn timestamp_is_formatted_as_utc_date_and_time() {
+            assert_eq!(format_timestamp(0), "19700101-000000");
+            assert_eq!(format_timestamp(1_709_210_096), "20240229-123456");
+        }
+
+        #[test]
+        fn stamp_changes_when_the_file_is_written() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            fs::write(&path, b"old").unwrap();
+            let stamp = get_file_stamp(&path).unwrap();
+
+            fs::write(&path, b"newer").unwrap();
+
+            assert_ne!(get_file_stamp(&path), Some(stamp));
+            fs::remove_file(&path).unwrap();
+            assert_eq!(get_file_stamp(&path), None);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn renamed_file_is_found_by_inode() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("old.txt");
+            let new_path = dir.path().join("new.txt");
+            fs::write(&path, b"text").unwrap();
+            fs::write(dir.path().join("other.txt"), b"text").unwrap();
+            let stamp = get_file_stamp(&path).unwrap();
+
+            fs::rename(&path, &new_path).unwrap();
+
+            assert_eq!(find_renamed_file(&path, &stamp), Some(new_path));
+        }
+
+        #[test]
+        fn list_files_skips_folders_and_hidden_files() {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("b.txt"), b"").unwrap();
+            fs::write(dir.path().join("a.txt"), b"").unwrap();
+            fs::write(dir.path().join(".hidden"), b"").unwrap();
+            fs::create_dir(dir.path().join("sub")).unwrap();
+
+            let files = list_files(dir.path()).unwrap();
+
+            assert_eq!(
+                files,
+                vec![dir.path().join("a.txt"), dir.path().join("b.txt")]
+            );
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn non_utf8_path_is_reported() {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+            let path = Path::new(OsStr::from_bytes(b"/tmp/\xff\xfe.txt"));
+
+            let err = get_path_string(path).unwrap_err();
+
+            assert!(err.contains("cannot handle"), "{}", err);
+            assert!(get_file_name(path).is_err());
+        }
+    }
+}
@@ -0,0 +1,477 @@
+pub mod harness {
+    use egui::{
+        accesskit::TreeUpdate, Context, Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect,
+        Vec2,
+    };
+    use egui_dock::DockState;
+
+    use crate::{
+        editor::editor::{TextEditor, TextEditorApp},
+        session::session::RecentFiles,
+        tab::tab::TextEditorTab,
+    };
+
+    /// Seconds between two frames, enough for double clicks not to merge
+    const FRAME_TIME: f64 = 1.0;
+
+    /// Empty frames run after each input
+    const SETTLE_FRAMES: usize = 3;
+
+    /// Drives [`TextEditorApp`] on a headless context with synthetic input, one frame at a
+    /// time. Widgets are found by their label in the accessibility tree of the last frame.
+    pub struct Harness {
+        pub app: TextEditorApp,
+        ctx: Context,
+        time: f64,
+        tree: Option<TreeUpdate>,
+    }
+
+    impl Harness {
+        /// Opens an editor with a single untitled tab holding `text`
+        pub fn new(text: &str) -> Self {
+            let ctx = Context::default();
+            ctx.enable_accesskit();
+            egui_extras::install_image_loaders(&ctx);
+            let tab = TextEditorTab::new("Untitled".into(), text.into());
+            let mut harness = Self {
+                app: TextEditorApp {
+                    dock_state: DockState::new(vec![tab]),
+                    editor: TextEditor::new(RecentFiles::default()),
+                },
+                ctx,
+                time: 0.0,
+                tree: None,
+            };
+            harness.run(Vec::new());
+            harness
+        }
+
+        /// Runs a frame with `events`, then a few without so that focus requests and state
+        /// set late in a frame take effect
+        pub fn run(&mut self, events: Vec<Event>) {
+            self.run_frame(events);
+            for _ in 0..SETTLE_FRAMES {
+                self.run_frame(Vec::new());
+            }
+        }
+
+        fn run_frame(&mut self, events: Vec<Event>) {
+            self.time += FRAME_TIME;
+            let input = RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(1024.0, 768.0))),
+                time: Some(self.time),
+                focused: true,
+                events,
+                ..RawInput::default()
+            };
+            let output = self.ctx.run(input, |ctx| self.app.show(ctx));
+            self.tree = output.platform_output.accesskit_update;
+        }
+
+        /// Presses and releases `key` while holding `modifiers`
+        pub fn key(&mut self, modifiers: Modifiers, key: Key) {
+            // Outside macOS the window reports Cmd as Ctrl, and Ctrl as both
+            let modifiers = if !cfg!(target_os = "macos") && (modifiers.command || modifiers.ctrl) {
+                modifiers | Modifiers::CTRL | Modifiers::COMMAND
+            } else {
+                modifiers
+            };
+            let event = |pressed| Event::Key {
+                key,
+                physical_key: None,
+                pressed,
+                repeat: false,
+                modifiers,
+            };
+            self.run(vec![event(true), event(false)]);
+        }
+
+        /// Types `text` into the focused widget
+        pub fn type_text(&mut self, text: &str) {
+            self.run(vec![Event::Text(text.into())]);
+        }
+
+        /// Clicks the first widget labelled `label`, panicking if the last frame did not show it
+        pub fn click(&mut self, label: &str) {
+            let bounds = self.find_widgets(label).first().copied();
+            self.click_at(label, bounds);
+        }
+
+        /// Clicks the last widget labelled `label`, e.g. the one in a window shown on top
+        pub fn click_last(&mut self, label: &str) {
+            let bounds = self.find_widgets(label).last().copied();
+            self.click_at(label, bounds);
+        }
+
+        fn click_at(&mut self, label: &str, bounds: Option<Rect>) {
+            let bounds = bounds.unwrap_or_else(|| panic!("No widget labelled {:?}", label));
+            let pos = bounds.center();
+            let button = |pressed| Event::PointerButton {
+                pos,
+                button: PointerButton::Primary,
+                pressed,
+                modifiers: Modifiers::NONE,
+            };
+            self.run_frame(vec![Event::PointerMoved(pos)]);
+            self.run(vec![button(true), button(false)]);
+        }
+
+        /// Returns where the first widget labelled `label` was drawn in the last frame
+        pub fn find_widget(&self, label: &str) -> Option<Rect> {
+            self.find_widgets(label).first().copied()
+        }
+
+        fn find_widgets(&self, label: &str) -> Vec<Rect> {
+            let Some(tree) = &self.tree else {
+                return Vec::new();
+            };
+            tree.nodes
+                .iter()
+                .filter(|(_, node)| node.name() == Some(label))
+                .filter_map(|(_, node)| node.bounds())
+                .map(|bounds| {
+                    Rect::from_min_max(
+                        Pos2::new(bounds.x0 as f32, bounds.y0 as f32),
+                        Pos2::new(bounds.x1 as f32, bounds.y1 as f32),
+                    )
+                })
+                .collect()
+        }
+
+        pub fn tab_count(&self) -> usize {
+            self.app.dock_state.iter_all_tabs().count()
+        }
+
+        pub fn tab(&mut self) -> &mut TextEditorTab {
+            self.app.active_tab_mut().expect("no active tab")
+        }
+
+        pub fn text(&mut self) -> String {
+            self.tab().get_text().to_string()
+        }
+
+        pub fn cursor(&mut self) -> std::ops::Range<usize> {
+            self.tab().get_cursor()
+        }
+
+        pub fn status(&mut self) -> String {
+            self.tab().status.clone()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::enums::enums::{Command, FindBarState, LineEnding, ReplaceBarState};
+        use crate::keymap::keymap::Keymap;
+
+        #[test]
+        fn typing_edits_the_focused_tab() {
+            let mut harness = Harness::new("");
+
+            harness.type_text("hello");
+
+            assert_eq!(harness.text(), "hello");
+            assert_eq!(harness.cursor(), 5..5);
+            assert!(harness.tab().is_dirty());
+        }
+
+        #[test]
+        fn enter_in_the_find_bar_selects_the_next_match() {
+            let mut harness = Harness::new("cat dog cat");
+
+            harness.key(Modifiers::COMMAND, Key::F);
+            assert!(harness.app.editor.state_manager.get_is_find_open());
+            harness.type_text("cat");
+            assert_eq!(harness.app.editor.state_manager.get_find_val(), "cat");
+            assert!(harness.find_widget("1 of 2").is_some());
+
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert!(matches!(
+                harness.app.editor.state_manager.get_find_state(),
+                FindBarState::Finding
+            ));
+            assert_eq!(harness.cursor(), 0..3);
+
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert_eq!(harness.cursor(), 8..11);
+            assert!(harness.find_widget("2 of 2").is_some());
+
+            // Wraps around to the first match, leaving the text alone
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert_eq!(harness.cursor(), 0..3);
+            assert_eq!(harness.text(), "cat dog cat");
+        }
+
+        #[test]
+        fn enter_in_the_replace_bar_replaces_match_by_match() {
+            let mut harness = Harness::new("cat dog cat");
+            harness.key(Modifiers::COMMAND, Key::F);
+            harness.type_text("cat");
+
+            harness.key(Modifiers::COMMAND, Key::R);
+            assert!(harness.app.editor.state_manager.get_is_replace_open());
+            harness.type_text("cow");
+            assert_eq!(harness.app.editor.state_manager.get_replace_val(), "cow");
+
+            // The first Enter selects a match, each following one replaces it
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert!(matches!(
+                harness.app.editor.state_manager.get_replace_state(),
+                ReplaceBarState::Replacing
+            ));
+            assert_eq!(harness.text(), "cat dog cat");
+            assert_eq!(harness.cursor(), 0..3);
+
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert_eq!(harness.text(), "cow dog cat");
+            assert_eq!(harness.cursor(), 8..11);
+
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert_eq!(harness.text(), "cow dog cow");
+            assert!(harness.tab().is_dirty());
+        }
+
+        #[test]
+        fn replace_all_asks_before_replacing() {
+            let mut harness = Harness::new("cat dog cat");
+            harness.key(Modifiers::COMMAND, Key::F);
+            harness.type_text("cat");
+            harness.key(Modifiers::COMMAND, Key::R);
+            harness.type_text("cow");
+
+            harness.click("Replace All");
+            assert!(harness.find_widget("2 replacement(s):").is_some());
+            assert_eq!(harness.text(), "cat dog cat");
+
+            // The preview window's own button comes after the replace bar's
+            harness.click_last("Replace All");
+            assert_eq!(harness.text(), "cow dog cow");
+        }
+
+        #[test]
+        fn go_to_from_the_edit_menu_moves_the_cursor() {
+            let mut harness = Harness::new("one\ntwo\nthree");
+
+            harness.click("Edit");
+            harness.click("Go To");
+            assert!(harness.app.editor.state_manager.get_is_goto_open());
+            harness.type_text("2:3");
+            harness.key(Modifiers::NONE, Key::Enter);
+
+            assert!(!harness.app.editor.state_manager.get_is_goto_open());
+            assert_eq!(harness.cursor(), 6..6);
+        }
+
+        #[test]
+        fn cut_from_the_edit_menu_takes_only_the_current_selection() {
+            let mut harness = Harness::new("one two");
+            harness.key(Modifiers::SHIFT, Key::End);
+            assert_eq!(harness.cursor(), 0..7);
+            harness.key(Modifiers::NONE, Key::ArrowRight);
+
+            harness.click("Edit");
+            harness.click("Cut");
+            assert_eq!(harness.text(), "one two");
+
+            harness.key(Modifiers::NONE, Key::Home);
+            harness.key(Modifiers::SHIFT, Key::ArrowRight);
+            harness.key(Modifiers::SHIFT, Key::ArrowRight);
+            harness.key(Modifiers::SHIFT, Key::ArrowRight);
+            harness.click("Edit");
+            harness.click("Cut");
+            assert_eq!(harness.text(), " two");
+            assert_eq!(harness.cursor(), 0..0);
+        }
+
+        #[test]
+        fn undo_from_the_edit_menu_reverts_typing() {
+            let mut harness = Harness::new("draft");
+            harness.key(Modifiers::NONE, Key::End);
+            harness.type_text("!");
+            assert_eq!(harness.text(), "draft!");
+
+            harness.click("Edit");
+            harness.click("Undo");
+
+            assert_eq!(harness.text(), "draft");
+        }
+
+        #[test]
+        fn tabs_are_opened_closed_and_reopened_from_the_keyboard() {
+            let mut harness = Harness::new("first");
+
+            harness.key(Modifiers::COMMAND, Key::T);
+            assert_eq!(harness.tab_count(), 2);
+            assert_eq!(harness.text(), "");
+
+            harness.key(Modifiers::COMMAND, Key::W);
+            assert_eq!(harness.tab_count(), 1);
+            assert_eq!(harness.text(), "first");
+
+            harness.key(Modifiers::COMMAND | Modifiers::SHIFT, Key::T);
+            assert_eq!(harness.tab_count(), 2);
+        }
+
+        #[test]
+        fn find_next_walks_the_matches_from_the_keyboard() {
+            let mut harness = Harness::new("cat dog cat");
+            harness.key(Modifiers::COMMAND, Key::F);
+            harness.type_text("cat");
+
+            harness.key(Modifiers::NONE, Key::F3);
+            assert_eq!(harness.cursor(), 0..3);
+            harness.key(Modifiers::NONE, Key::F3);
+            assert_eq!(harness.cursor(), 8..11);
+            harness.key(Modifiers::SHIFT, Key::F3);
+            assert_eq!(harness.cursor(), 0..3);
+        }
+
+        #[test]
+        fn command_palette_runs_the_picked_command_and_remembers_it() {
+            let mut harness = Harness::new("one\ntwo");
+
+            harness.key(Modifiers::COMMAND | Modifiers::SHIFT, Key::P);
+            assert!(harness.app.editor.palette.get_is_open());
+            harness.type_text("goto");
+            assert!(harness.find_widget("Go To").is_some());
+            harness.key(Modifiers::NONE, Key::Enter);
+
+            assert!(!harness.app.editor.palette.get_is_open());
+            assert!(harness.app.editor.state_manager.get_is_goto_open());
+            assert_eq!(
+                harness.app.editor.palette.recent_commands.get_commands(),
+                vec![Command::GoTo]
+            );
+            assert_eq!(harness.text(), "one\ntwo");
+        }
+
+        #[test]
+        fn bound_choice_command_asks_for_its_option_in_the_palette() {
+            let mut harness = Harness::new("one\ntwo");
+            harness.app.editor.keymap =
+                Keymap::from_toml("[bindings]\nconvert_line_endings = \"F7\"").0;
+
+            harness.click("Edit");
+            assert!(harness.find_widget("Convert Line Endings (F7)").is_some());
+            harness.key(Modifiers::NONE, Key::Escape);
+            harness.key(Modifiers::NONE, Key::F7);
+            assert!(harness.app.editor.palette.get_is_open());
+            harness.type_text("crlf");
+            harness.key(Modifiers::NONE, Key::Enter);
+
+            assert!(!harness.app.editor.palette.get_is_open());
+            assert!(harness.tab().get_line_ending() == LineEnding::Crlf);
+        }
+
+        #[test]
+        fn command_palette_gives_the_keyboard_back_when_closed() {
+            let mut harness = Harness::new("");
+            harness.key(Modifiers::COMMAND | Modifiers::SHIFT, Key::P);
+            harness.key(Modifiers::NONE, Key::ArrowDown);
+
+            harness.key(Modifiers::NONE, Key::Escape);
+
+            assert!(!harness.app.editor.palette.get_is_open());
+            harness.type_text("x");
+            assert_eq!(harness.text(), "x");
+        }
+
+        #[test]
+        fn language_is_detected_and_picked_from_the_status_bar() {
+            let mut harness = Harness::new("#!/usr/bin/env bash\necho hi");
+            assert!(harness.find_widget("Shell Script").is_some());
+
+            harness.key(Modifiers::COMMAND, Key::K);
+            harness.key(Modifiers::NONE, Key::M);
+            harness.click("Python");
+
+            assert_eq!(harness.tab().get_language(), "python");
+            assert!(harness.find_widget("Shell Script").is_none());
+            harness.click("Python");
+            harness.click("Auto Detect");
+            assert_eq!(harness.tab().get_language(), "shell");
+        }
+
+        #[test]
+        fn theme_follows_the_system_until_picked_from_the_view_menu() {
+            let mut harness = Harness::new("");
+            assert!(harness.ctx.style().visuals.dark_mode);
+            harness.app.editor.themes.set_system_dark(Some(false));
+            harness.run(Vec::new());
+            assert!(!harness.ctx.style().visuals.dark_mode);
+
+            harness.click("View");
+            harness.click("Color Theme");
+            harness.click("Dark");
+
+            assert_eq!(harness.app.editor.themes.get_picked(), Some("Dark"));
+            assert!(harness.ctx.style().visuals.dark_mode);
+        }
+
+        #[test]
+        fn status_bar_shows_line_ending_and_encoding() {
+            let mut harness = Harness::new("text");
+            let status = harness.status();
+
+            assert!(status.ends_with("| UTF-8"), "{}", status);
+            assert!(harness.find_widget(&status).is_some());
+        }
+
+        #[test]
+        fn editing_keys_move_the_cursor_and_delete() {
+            let mut harness = Harness::new("one\ntwo");
+
+            harness.key(Modifiers::NONE, Key::ArrowDown);
+            harness.key(Modifiers::NONE, Key::End);
+            harness.key(Modifiers::NONE, Key::Backspace);
+            assert_eq!(harness.text(), "one\ntw");
+
+            harness.key(Modifiers::SHIFT, Key::Home);
+            assert_eq!(harness.cursor(), 4..6);
+            harness.key(Modifiers::NONE, Key::Enter);
+            assert_eq!(harness.text(), "one\n\n");
+
+            harness.key(Modifiers::COMMAND, Key::A);
+            harness.type_text("x");
+            assert_eq!(harness.text(), "x");
+        }
+
+        #[test]
+        fn only_the_lines_in_view_are_laid_out() {
+            let text = (1..=100_000)
+                .map(|i| format!("line {}", i))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let mut harness = Harness::new(&text);
+            assert!(harness.tab().get_line_cache().len() < 100);
+
+            harness.key(Modifiers::COMMAND, Key::End);
+
+            assert!(harness.status().starts_with("Ln 100000 Col 12 |"));
+            assert!(harness.tab().get_line_cache().len() < 100);
+        }
+
+        #[test]
+        fn status_bar_follows_the_cursor() {
+            let mut harness = Harness::new("one\ntwö");
+
+            harness.click("Edit");
+            harness.click("Go To");
+            harness.type_text("2:3");
+            harness.key(Modifiers::NONE, Key::Enter);
+
+            assert!(
+                harness.status().starts_with("Ln 2 Col 3 |"),
+                "{}",
+                harness.status()
+            );
+            harness.type_text("!");
+            assert!(
+                harness.status().starts_with("Ln 2 Col 4 |"),
+                "{}",
+                harness.status()
+            );
+        }
+    }
+}
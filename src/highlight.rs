@@ -0,0 +1,402 @@
+pub mod highlight {
+    use std::ops::Range;
+    use std::sync::OnceLock;
+
+    use egui::Color32;
+    use tree_sitter::{Language, Node, Parser, Query, QueryCursor, StreamingIterator, Tree};
+    use tree_sitter_language::LanguageFn;
+
+    use crate::buffer::buffer::Buffer;
+
+    /// Files larger than this are shown without highlighting, as parsing them would hold up
+    /// the window for too long
+    const MAX_HIGHLIGHT_BYTES: usize = 4 * 1024 * 1024;
+
+    /// A tree-sitter grammar with the query that picks the nodes to highlight
+    pub struct Grammar {
+        /// Id of the language in [`LANGUAGES`](crate::language::language::LANGUAGES)
+        pub language_id: &'static str,
+        language: LanguageFn,
+        /// Highlight queries, more specific ones first since the first capture of a node wins
+        highlights: &'static [&'static str],
+        query: OnceLock<Query>,
+    }
+
+    impl Grammar {
+        const fn new(
+            language_id: &'static str,
+            language: LanguageFn,
+            highlights: &'static [&'static str],
+        ) -> Self {
+            Self {
+                language_id,
+                language,
+                highlights,
+                query: OnceLock::new(),
+            }
+        }
+
+        /// The highlight query, compiled the first time a file of the language is opened
+        fn get_query(&self) -> &Query {
+            self.query.get_or_init(|| {
+                Query::new(&Language::new(self.language), &self.highlights.concat()).unwrap_or_else(
+                    |err| panic!("Invalid {} highlight query: {}", self.language_id, err),
+                )
+            })
+        }
+    }
+
+    pub static GRAMMARS: [Grammar; 11] = [
+        Grammar::new(
+            "rust",
+            tree_sitter_rust::LANGUAGE,
+            &[tree_sitter_rust::HIGHLIGHTS_QUERY],
+        ),
+        Grammar::new(
+            "python",
+            tree_sitter_python::LANGUAGE,
+            &[tree_sitter_python::HIGHLIGHTS_QUERY],
+        ),
+        Grammar::new(
+            "toml",
+            tree_sitter_toml_ng::LANGUAGE,
+            &[tree_sitter_toml_ng::HIGHLIGHTS_QUERY],
+        ),
+        Grammar::new(
+            "json",
+            tree_sitter_json::LANGUAGE,
+            &[tree_sitter_json::HIGHLIGHTS_QUERY],
+        ),
+        Grammar::new(
+            "markdown",
+            tree_sitter_md::LANGUAGE,
+            &[tree_sitter_md::HIGHLIGHT_QUERY_BLOCK],
+        ),
+        Grammar::new(
+            "c",
+            tree_sitter_c::LANGUAGE,
+            &[tree_sitter_c::HIGHLIGHT_QUERY],
+        ),
+        Grammar::new(
+            "cpp",
+            tree_sitter_cpp::LANGUAGE,
+            &[
+                tree_sitter_cpp::HIGHLIGHT_QUERY,
+                tree_sitter_c::HIGHLIGHT_QUERY,
+            ],
+        ),
+        Grammar::new(
+            "javascript",
+            tree_sitter_javascript::LANGUAGE,
+            &[
+                tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+            ],
+        ),
+        Grammar::new(
+            "typescript",
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+            &[
+                tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+            ],
+        ),
+        Grammar::new(
+            "typescriptreact",
+            tree_sitter_typescript::LANGUAGE_TSX,
+            &[
+                tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+            ],
+        ),
+        Grammar::new(
+            "shell",
+            tree_sitter_bash::LANGUAGE,
+            &[tree_sitter_bash::HIGHLIGHT_QUERY],
+        ),
+    ];
+
+    /// Finds the grammar of the language with the id `language_id`
+    pub fn get_grammar(language_id: &str) -> Option<&'static Grammar> {
+        GRAMMARS
+            .iter()
+            .find(|grammar| grammar.language_id == language_id)
+    }
+
+    /// Colors for the captures of the highlight queries, such as `keyword` or
+    /// `function.method`. A capture without a color of its own takes the color of the
+    /// capture it is a part of, e.g. `function` for `function.method`.
+    #[derive(Clone, Hash, PartialEq)]
+    pub struct SyntaxTheme {
+        colors: Vec<(String, Color32)>,
+    }
+
+    impl SyntaxTheme {
+        fn from_colors(colors: &[(&str, Color32)]) -> Self {
+            Self {
+                colors: colors
+                    .iter()
+                    .map(|(capture, color)| (capture.to_string(), *color))
+                    .collect(),
+            }
+        }
+
+        pub fn dark() -> Self {
+            Self::from_colors(&[
+                ("attribute", Color32::from_rgb(197, 134, 192)),
+                ("comment", Color32::from_gray(120)),
+                ("constant", Color32::from_rgb(87, 165, 171)),
+                ("constructor", Color32::from_rgb(78, 201, 176)),
+                ("embedded", Color32::from_rgb(109, 147, 226)),
+                ("escape", Color32::from_rgb(215, 186, 125)),
+                ("function", Color32::from_rgb(220, 220, 170)),
+                ("keyword", Color32::from_rgb(255, 100, 100)),
+                ("label", Color32::from_rgb(197, 134, 192)),
+                ("number", Color32::from_rgb(87, 165, 171)),
+                ("boolean", Color32::from_rgb(87, 165, 171)),
+                ("operator", Color32::LIGHT_GRAY),
+                ("property", Color32::from_rgb(156, 220, 254)),
+                ("punctuation", Color32::LIGHT_GRAY),
+                ("string", Color32::from_rgb(109, 147, 226)),
+                ("string.escape", Color32::from_rgb(215, 186, 125)),
+                ("tag", Color32::from_rgb(255, 100, 100)),
+                ("text.title", Color32::from_rgb(255, 100, 100)),
+                ("text.literal", Color32::from_rgb(109, 147, 226)),
+                ("text.uri", Color32::from_rgb(87, 165, 171)),
+                ("type", Color32::from_rgb(78, 201, 176)),
+                ("variable.builtin", Color32::from_rgb(255, 100, 100)),
+            ])
+        }
+
+        pub fn light() -> Self {
+            Self::from_colors(&[
+                ("attribute", Color32::from_rgb(128, 60, 180)),
+                ("comment", Color32::GRAY),
+                ("constant", Color32::from_rgb(153, 134, 255)),
+                ("constructor", Color32::from_rgb(0, 128, 128)),
+                ("embedded", Color32::from_rgb(37, 140, 80)),
+                ("escape", Color32::from_rgb(180, 100, 0)),
+                ("function", Color32::from_rgb(150, 100, 0)),
+                ("keyword", Color32::from_rgb(235, 0, 0)),
+                ("label", Color32::from_rgb(128, 60, 180)),
+                ("number", Color32::from_rgb(153, 134, 255)),
+                ("boolean", Color32::from_rgb(153, 134, 255)),
+                ("operator", Color32::DARK_GRAY),
+                ("property", Color32::from_rgb(0, 80, 160)),
+                ("punctuation", Color32::DARK_GRAY),
+                ("string", Color32::from_rgb(37, 160, 85)),
+                ("string.escape", Color32::from_rgb(180, 100, 0)),
+                ("tag", Color32::from_rgb(235, 0, 0)),
+                ("text.title", Color32::from_rgb(235, 0, 0)),
+                ("text.literal", Color32::from_rgb(37, 160, 85)),
+                ("text.uri", Color32::from_rgb(153, 134, 255)),
+                ("type", Color32::from_rgb(0, 128, 128)),
+                ("variable.builtin", Color32::from_rgb(235, 0, 0)),
+            ])
+        }
+
+        /// Colors `capture` and the captures within it that have no color of their own
+        pub fn set_color(&mut self, capture: &str, color: Color32) {
+            match self.colors.iter_mut().find(|(name, _)| name == capture) {
+                Some((_, old_color)) => *old_color = color,
+                None => self.colors.push((capture.to_string(), color)),
+            }
+        }
+
+        pub fn get_color(&self, capture: &str) -> Option<Color32> {
+            let mut capture = capture;
+            loop {
+                if let Some((_, color)) = self.colors.iter().find(|(name, _)| name == capture) {
+                    return Some(*color);
+                }
+                capture = &capture[..capture.rfind('.')?];
+            }
+        }
+    }
+
+    /// Keeps the syntax tree of a tab's text up to date. The tree is reparsed incrementally
+    /// after edits, and highlighting only queries the part of it that is asked for.
+    pub struct Highlighter {
+        parser: Parser,
+        tree: Option<Tree>,
+        grammar: Option<&'static Grammar>,
+        language: String,
+        buffer_id: Option<usize>,
+    }
+
+    impl Highlighter {
+        pub fn new() -> Self {
+            Self {
+                parser: Parser::new(),
+                tree: None,
+                grammar: None,
+                language: String::new(),
+                buffer_id: None,
+            }
+        }
+
+        /// Brings the tree in line with `buffer`, parsing it anew when the buffer or its
+        /// `language` changed
+        pub fn update(&mut self, buffer: &mut Buffer, language: &str) {
+            let edits = buffer.take_syntax_edits();
+            if self.language != language || self.buffer_id != Some(buffer.get_id()) {
+                self.language = language.to_string();
+                self.buffer_id = Some(buffer.get_id());
+                self.tree = None;
+                self.grammar = get_grammar(language).filter(|grammar| {
+                    self.parser
+                        .set_language(&Language::new(grammar.language))
+                        .is_ok()
+                });
+            } else if edits.is_empty() {
+                return;
+            }
+
+            if self.grammar.is_none() || buffer.len_bytes() > MAX_HIGHLIGHT_BYTES {
+                self.tree = None;
+                return;
+            }
+            if let Some(tree) = &mut self.tree {
+                for edit in &edits {
+                    tree.edit(edit);
+                }
+            }
+            let buffer = &*buffer;
+            self.tree = self.parser.parse_with_options(
+                &mut |byte_idx, _| buffer.chunk_at_byte(byte_idx.min(buffer.len_bytes())),
+                self.tree.as_ref(),
+                None,
+            );
+        }
+
+        /// Colors the highlighted parts of `byte_range` of `buffer`. The ranges returned are
+        /// relative to the start of `byte_range`, in order and without overlaps.
+        pub fn get_colors(
+            &self,
+            buffer: &Buffer,
+            byte_range: Range<usize>,
+            theme: &SyntaxTheme,
+        ) -> Vec<(Range<usize>, Color32)> {
+            let (Some(grammar), Some(tree)) = (self.grammar, &self.tree) else {
+                return Vec::new();
+            };
+            let query = grammar.get_query();
+            let mut colors = vec![None; byte_range.len()];
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(byte_range.clone());
+            let text = |node: Node| buffer.chunks_in(node.byte_range()).map(str::as_bytes);
+            let mut captures = cursor.captures(query, tree.root_node(), text);
+            let mut last_node = None;
+            while let Some((found, capture_idx)) = captures.next() {
+                let capture = found.captures[*capture_idx];
+                // Nested nodes paint over the ones they are in, but a node keeps its first capture
+                if last_node == Some(capture.node.id()) {
+                    continue;
+                }
+                last_node = Some(capture.node.id());
+                let name = query.capture_names()[capture.index as usize];
+                let Some(color) = theme.get_color(name) else {
+                    continue;
+                };
+                let node_range = capture.node.byte_range();
+                let start = node_range.start.max(byte_range.start) - byte_range.start;
+                let end = node_range.end.min(byte_range.end) - byte_range.start;
+                if start < end {
+                    colors[start..end].fill(Some(color));
+                }
+            }
+
+            let mut runs: Vec<(Range<usize>, Color32)> = Vec::new();
+            for (idx, color) in colors.into_iter().enumerate() {
+                let Some(color) = color else {
+                    continue;
+                };
+                match runs.last_mut() {
+                    Some((range, last_color)) if range.end == idx && *last_color == color => {
+                        range.end += 1;
+                    }
+                    _ => runs.push((idx..idx + 1, color)),
+                }
+            }
+            // The tree lags behind edits made in this frame until the next update
+            runs.retain(|(range, _)| {
+                buffer.is_char_boundary(byte_range.start + range.start)
+                    && buffer.is_char_boundary(byte_range.start + range.end)
+            });
+            runs
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::language::language::{get_language_info, PLAIN_TEXT};
+
+        fn get_color_at(
+            highlighter: &Highlighter,
+            buffer: &Buffer,
+            byte_idx: usize,
+        ) -> Option<Color32> {
+            highlighter
+                .get_colors(buffer, 0..buffer.len_bytes(), &SyntaxTheme::dark())
+                .into_iter()
+                .find(|(range, _)| range.contains(&byte_idx))
+                .map(|(_, color)| color)
+        }
+
+        #[test]
+        fn every_highlight_query_compiles() {
+            for grammar in &GRAMMARS {
+                grammar.get_query();
+                assert!(get_language_info(grammar.language_id).is_some());
+            }
+        }
+
+        #[test]
+        fn captures_take_the_color_of_their_parent_capture() {
+            let theme = SyntaxTheme::dark();
+
+            assert_eq!(
+                theme.get_color("function.method"),
+                theme.get_color("function")
+            );
+            assert_eq!(theme.get_color("none"), None);
+        }
+
+        #[test]
+        fn edits_are_highlighted_after_reparsing() {
+            let mut buffer = Buffer::new("fn main() {}".into());
+            let mut highlighter = Highlighter::new();
+            highlighter.update(&mut buffer, "rust");
+            let keyword = SyntaxTheme::dark().get_color("keyword");
+            assert_eq!(get_color_at(&highlighter, &buffer, 0), keyword);
+
+            buffer.replace_chars(11..11, " let x = \"s\"; ");
+            highlighter.update(&mut buffer, "rust");
+
+            assert_eq!(get_color_at(&highlighter, &buffer, 12), keyword);
+            assert_eq!(
+                get_color_at(&highlighter, &buffer, 20),
+                SyntaxTheme::dark().get_color("string")
+            );
+        }
+
+        #[test]
+        fn colors_only_cover_the_range_asked_for() {
+            let mut buffer = Buffer::new("# Title\n\ntext".into());
+            let mut highlighter = Highlighter::new();
+            highlighter.update(&mut buffer, "markdown");
+
+            let colors = highlighter.get_colors(&buffer, 2..7, &SyntaxTheme::dark());
+
+            assert!(colors.iter().all(|(range, _)| range.end <= 5));
+            assert!(!colors.is_empty());
+
+            highlighter.update(&mut buffer, PLAIN_TEXT);
+            assert!(highlighter
+                .get_colors(&buffer, 0..7, &SyntaxTheme::dark())
+                .is_empty());
+        }
+    }
+}
@@ -0,0 +1,237 @@
+pub mod history {
+    use std::collections::VecDeque;
+    use std::ops::Range;
+    use std::time::{Duration, Instant};
+
+    use crate::buffer::buffer::{Buffer, Change};
+    use crate::enums::enums::EditKind;
+
+    const MAX_UNDO_ENTRIES: usize = 1000;
+    const MAX_UNDO_BYTES: usize = 16 * 1024 * 1024;
+    const GROUP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    /// One undo step: the changes committed together, in the order they were made
+    struct Edit {
+        changes: Vec<Change>,
+        kind: EditKind,
+    }
+
+    impl Edit {
+        fn new(changes: Vec<Change>) -> Self {
+            let kind = match changes.as_slice() {
+                [change] if change.removed.is_empty() => EditKind::Insert,
+                [change] if change.inserted.is_empty() => EditKind::Delete,
+                _ => EditKind::Replace,
+            };
+            Self { changes, kind }
+        }
+
+        fn size(&self) -> usize {
+            self.changes
+                .iter()
+                .map(|change| change.removed.len() + change.inserted.len())
+                .sum()
+        }
+
+        /// Tries to fold `next` into this edit so consecutive keystrokes undo together
+        fn merge(&mut self, next: &Edit) -> bool {
+            if self.kind != next.kind {
+                return false;
+            }
+            let ([this], [next]) = (self.changes.as_mut_slice(), next.changes.as_slice()) else {
+                return false;
+            };
+            match self.kind {
+                EditKind::Insert => {
+                    let starts_word = next.inserted.starts_with(char::is_whitespace)
+                        && !this.inserted.ends_with(char::is_whitespace);
+                    if next.offset != this.offset + this.inserted.len()
+                        || next.inserted.contains('\n')
+                        || starts_word
+                    {
+                        return false;
+                    }
+                    this.inserted.push_str(&next.inserted);
+                    true
+                }
+                EditKind::Delete => {
+                    if next.offset + next.removed.len() == this.offset {
+                        // Backspace
+                        this.removed.insert_str(0, &next.removed);
+                        this.offset = next.offset;
+                        true
+                    } else if next.offset == this.offset {
+                        // Delete
+                        this.removed.push_str(&next.removed);
+                        true
+                    } else {
+                        false
+                    }
+                }
+                EditKind::Replace => false,
+            }
+        }
+    }
+
+    /// Per-tab undo/redo stack, fed with the [`Change`]s a [`Buffer`] records so that typed
+    /// and programmatic edits alike are undone without the caller tracking ranges
+    pub struct History {
+        undo_stack: VecDeque<Edit>,
+        redo_stack: Vec<Edit>,
+        undo_bytes: usize,
+        last_edit_at: Option<Instant>,
+    }
+
+    impl History {
+        pub fn new() -> Self {
+            Self {
+                undo_stack: VecDeque::new(),
+                redo_stack: Vec::new(),
+                undo_bytes: 0,
+                last_edit_at: None,
+            }
+        }
+
+        /// Forgets all history, e.g. after a new file was loaded into the tab
+        pub fn reset(&mut self) {
+            *self = Self::new();
+        }
+
+        pub fn can_undo(&self) -> bool {
+            !self.undo_stack.is_empty()
+        }
+
+        pub fn can_redo(&self) -> bool {
+            !self.redo_stack.is_empty()
+        }
+
+        /// Records `changes` as an undo step.
+        /// Typed edits pass `mergeable` so consecutive keystrokes form one undo step.
+        pub fn commit(&mut self, changes: Vec<Change>, mergeable: bool) {
+            if changes.is_empty() {
+                return;
+            }
+            let edit = Edit::new(changes);
+            self.redo_stack.clear();
+
+            let now = Instant::now();
+            let in_group = mergeable
+                && self
+                    .last_edit_at
+                    .is_some_and(|at| now.duration_since(at) < GROUP_TIMEOUT);
+            self.last_edit_at = if mergeable { Some(now) } else { None };
+
+            if in_group {
+                if let Some(last) = self.undo_stack.back_mut() {
+                    let old_size = last.size();
+                    if last.merge(&edit) {
+                        self.undo_bytes = self.undo_bytes - old_size + last.size();
+                        self.trim();
+                        return;
+                    }
+                }
+            }
+
+            self.undo_bytes += edit.size();
+            self.undo_stack.push_back(edit);
+            self.trim();
+        }
+
+        /// Reverts the last edit group in `buffer`, returning the char range to select
+        pub fn undo(&mut self, buffer: &mut Buffer) -> Option<Range<usize>> {
+            let edit = self.undo_stack.pop_back()?;
+            self.undo_bytes -= edit.size();
+            self.last_edit_at = None;
+
+            for change in edit.changes.iter().rev() {
+                let end = change.offset + change.inserted.len();
+                buffer.replace_bytes(change.offset..end, &change.removed);
+            }
+            buffer.take_changes();
+
+            let first = &edit.changes[0];
+            let selection = char_range(buffer, first.offset, &first.removed);
+            self.redo_stack.push(edit);
+            Some(selection)
+        }
+
+        /// Re-applies the last undone edit group in `buffer`, returning the char range to select
+        pub fn redo(&mut self, buffer: &mut Buffer) -> Option<Range<usize>> {
+            let edit = self.redo_stack.pop()?;
+            self.last_edit_at = None;
+
+            for change in &edit.changes {
+                let end = change.offset + change.removed.len();
+                buffer.replace_bytes(change.offset..end, &change.inserted);
+            }
+            buffer.take_changes();
+
+            let first = &edit.changes[0];
+            let selection = char_range(buffer, first.offset, &first.inserted);
+            self.undo_bytes += edit.size();
+            self.undo_stack.push_back(edit);
+            self.trim();
+            Some(selection)
+        }
+
+        fn trim(&mut self) {
+            while self.undo_stack.len() > MAX_UNDO_ENTRIES
+                || (self.undo_bytes > MAX_UNDO_BYTES && self.undo_stack.len() > 1)
+            {
+                if let Some(edit) = self.undo_stack.pop_front() {
+                    self.undo_bytes -= edit.size();
+                }
+            }
+        }
+    }
+
+    /// The char range taken by `text` at byte `offset` of `buffer`
+    fn char_range(buffer: &Buffer, offset: usize, text: &str) -> Range<usize> {
+        let start = buffer.byte_to_char(offset);
+        start..start + text.chars().count()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn type_text(buffer: &mut Buffer, history: &mut History, text: &str, char_idx: usize) {
+            buffer.replace_chars(char_idx..char_idx, text);
+            history.commit(buffer.take_changes(), true);
+        }
+
+        #[test]
+        fn keystrokes_undo_as_words() {
+            let mut buffer = Buffer::new("".into());
+            let mut history = History::new();
+            for (i, c) in "hi there".chars().enumerate() {
+                type_text(&mut buffer, &mut history, &c.to_string(), i);
+            }
+
+            assert_eq!(history.undo(&mut buffer), Some(2..2));
+            assert_eq!(buffer.to_string(), "hi");
+            assert_eq!(history.undo(&mut buffer), Some(0..0));
+            assert_eq!(buffer.to_string(), "");
+            assert!(!history.can_undo());
+
+            assert_eq!(history.redo(&mut buffer), Some(0..2));
+            assert_eq!(buffer.to_string(), "hi");
+        }
+
+        #[test]
+        fn separate_changes_undo_together() {
+            let mut buffer = Buffer::new("one two".into());
+            let mut history = History::new();
+            buffer.replace_chars(7..7, "!");
+            buffer.replace_chars(3..3, "!");
+            history.commit(buffer.take_changes(), false);
+            assert_eq!(buffer.to_string(), "one! two!");
+
+            history.undo(&mut buffer);
+            assert_eq!(buffer.to_string(), "one two");
+
+            history.redo(&mut buffer);
+            assert_eq!(buffer.to_string(), "one! two!");
+        }
+    }
+}
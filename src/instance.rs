@@ -0,0 +1,217 @@
+pub mod instance {
+    use std::{
+        env,
+        ffi::OsString,
+        fs,
+        io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+        os::unix::{
+            ffi::{OsStrExt, OsStringExt},
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::{self, PathBuf},
+        sync::mpsc::Sender,
+        thread,
+    };
+
+    use crate::cli::cli::{Args, FileArg};
+
+    /// Files another launch of Reditor asked the running window to open
+    pub struct OpenRequest {
+        pub files: Vec<FileArg>,
+        pub stdin: Option<Vec<u8>>,
+        /// Kept open for `--wait` and answered once the request's tabs are all closed
+        pub waiter: Option<UnixStream>,
+    }
+
+    /// Socket the first window listens on, private to the current user
+    pub fn get_socket_path() -> PathBuf {
+        match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("reditor.sock"),
+            _ => {
+                let user = env::var("USER").unwrap_or_default();
+                env::temp_dir().join(format!("reditor-{}.sock", user))
+            }
+        }
+    }
+
+    /// Hands `args` to an already running window.
+    /// Returns `Ok(false)` if no window is listening, in which case this launch should open one.
+    pub fn forward(args: &Args, stdin: Option<&[u8]>) -> io::Result<bool> {
+        let mut stream = match UnixStream::connect(get_socket_path()) {
+            Ok(stream) => stream,
+            Err(_) => return Ok(false),
+        };
+
+        write_request(&mut stream, args, stdin)?;
+        if args.wait {
+            // The window writes back and hangs up once the forwarded tabs are closed
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply)?;
+        }
+        Ok(true)
+    }
+
+    /// Claims the socket for this window, replacing one left behind by a crashed instance
+    pub fn listen() -> io::Result<UnixListener> {
+        let socket_path = get_socket_path();
+        let listener = match UnixListener::bind(&socket_path) {
+            Err(err) if err.kind() == ErrorKind::AddrInUse => {
+                if UnixStream::connect(&socket_path).is_ok() {
+                    return Err(err);
+                }
+                fs::remove_file(&socket_path)?;
+                UnixListener::bind(&socket_path)
+            }
+            result => result,
+        }?;
+        // The temp dir fallback is shared, so keep other users from opening files in our window
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    /// Accepts requests on a background thread, passing each to `on_request`
+    pub fn serve(
+        listener: UnixListener,
+        requests: Sender<OpenRequest>,
+        on_request: impl Fn() + Send + 'static,
+    ) {
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                match read_request(stream) {
+                    Ok(request) => {
+                        if requests.send(request).is_err() {
+                            return;
+                        }
+                        on_request();
+                    }
+                    Err(err) => eprintln!("Ignoring a malformed open request: {}", err),
+                }
+            }
+        });
+    }
+
+    /// Removes the socket when this window stops listening
+    pub fn stop_listening() {
+        let _ = fs::remove_file(get_socket_path());
+    }
+
+    // A request is a "wait" line followed by length-prefixed records, so paths may contain
+    // any byte, and ends with an "end" record:
+    //
+    //     wait <0|1>\n
+    //     file <line> <column> <len>\n<path bytes>
+    //     stdin 0 0 <len>\n<text bytes>
+    //     end 0 0 0\n
+
+    fn write_request(stream: &mut impl Write, args: &Args, stdin: Option<&[u8]>) -> io::Result<()> {
+        writeln!(stream, "wait {}", u8::from(args.wait))?;
+        for file in &args.files {
+            // The window may run in another directory
+            let path = path::absolute(&file.path)?;
+            let path = path.as_os_str().as_bytes();
+            writeln!(
+                stream,
+                "file {} {} {}",
+                file.line.unwrap_or(0),
+                file.column.unwrap_or(0),
+                path.len()
+            )?;
+            stream.write_all(path)?;
+        }
+        if let Some(bytes) = stdin {
+            writeln!(stream, "stdin 0 0 {}", bytes.len())?;
+            stream.write_all(bytes)?;
+        }
+        writeln!(stream, "end 0 0 0")?;
+        stream.flush()
+    }
+
+    fn read_request(stream: UnixStream) -> io::Result<OpenRequest> {
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let wait = match line.trim_end() {
+            "wait 0" => false,
+            "wait 1" => true,
+            _ => return Err(invalid("missing wait header")),
+        };
+
+        let mut request = OpenRequest {
+            files: Vec::new(),
+            stdin: None,
+            waiter: None,
+        };
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [kind, line_no, column, len] = fields[..] else {
+                return Err(invalid("malformed record"));
+            };
+            let parse = |field: &str| field.parse::<usize>().map_err(|_| invalid("bad number"));
+            let (line_no, column, len) = (parse(line_no)?, parse(column)?, parse(len)?);
+
+            let mut bytes = Vec::new();
+            (&mut reader).take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            match kind {
+                "file" => request.files.push(FileArg {
+                    path: PathBuf::from(OsString::from_vec(bytes)),
+                    line: (line_no > 0).then_some(line_no),
+                    column: (column > 0).then_some(column),
+                }),
+                "stdin" => request.stdin = Some(bytes),
+                "end" => break,
+                _ => return Err(invalid("unknown record")),
+            }
+        }
+
+        if wait {
+            request.waiter = Some(stream);
+        }
+        Ok(request)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn request_round_trips_over_a_socket() {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            let args = Args {
+                files: vec![FileArg {
+                    path: PathBuf::from("/tmp/with space\nand newline.rs"),
+                    line: Some(42),
+                    column: None,
+                }],
+                read_stdin: true,
+                wait: true,
+                ..Args::default()
+            };
+
+            write_request(&mut client, &args, Some(b"piped text")).unwrap();
+            let request = read_request(server).unwrap();
+
+            assert_eq!(request.files, args.files);
+            assert_eq!(request.stdin.as_deref(), Some(&b"piped text"[..]));
+            assert!(request.waiter.is_some());
+        }
+
+        #[test]
+        fn malformed_request_is_rejected() {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            client.write_all(b"hello\n").unwrap();
+
+            assert!(read_request(server).is_err());
+        }
+    }
+}
@@ -0,0 +1,660 @@
+pub mod keymap {
+    use std::{collections::HashMap, fs, io::ErrorKind, path::Path, path::PathBuf};
+
+    use egui::{Event, Key, KeyboardShortcut, Modifiers};
+    use serde::Deserialize;
+
+    use encoding_rs::Encoding;
+
+    use crate::enums::enums::{BackupMode, Command, LineEnding};
+
+    /// A command's name in the keymap file, its title in menus and its default bindings
+    pub struct CommandInfo {
+        pub command: Command,
+        pub id: &'static str,
+        pub title: &'static str,
+        default_bindings: &'static [&'static str],
+    }
+
+    /// Every command, in menu order. `Cmd` is Command on macOS and Ctrl elsewhere.
+    pub const COMMANDS: &[CommandInfo] = &[
+        CommandInfo {
+            command: Command::NewTab,
+            id: "new_tab",
+            title: "New Tab",
+            default_bindings: &["Cmd+T"],
+        },
+        CommandInfo {
+            command: Command::Open,
+            id: "open",
+            title: "Open",
+            default_bindings: &["Cmd+O"],
+        },
+        CommandInfo {
+            command: Command::OpenRecent,
+            id: "open_recent",
+            title: "Open Recent",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::ClearRecentFiles,
+            id: "clear_recent_files",
+            title: "Clear Recent Files",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::ReopenWithEncoding,
+            id: "reopen_with_encoding",
+            title: "Reopen with Encoding",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::Save,
+            id: "save",
+            title: "Save",
+            default_bindings: &["Cmd+S"],
+        },
+        CommandInfo {
+            command: Command::SaveAs,
+            id: "save_as",
+            title: "Save As",
+            default_bindings: &["Cmd+Shift+S"],
+        },
+        CommandInfo {
+            command: Command::SaveWithEncoding,
+            id: "save_with_encoding",
+            title: "Save with Encoding",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::SaveAll,
+            id: "save_all",
+            title: "Save All",
+            default_bindings: &["Cmd+K S"],
+        },
+        CommandInfo {
+            command: Command::SetBackupMode,
+            id: "backup_mode",
+            title: "Backup Mode",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::CloseTab,
+            id: "close_tab",
+            title: "Close Tab",
+            default_bindings: &["Cmd+W"],
+        },
+        CommandInfo {
+            command: Command::ReopenClosedTab,
+            id: "reopen_closed_tab",
+            title: "Reopen Closed Tab",
+            default_bindings: &["Cmd+Shift+T"],
+        },
+        CommandInfo {
+            command: Command::CloseWindow,
+            id: "close_window",
+            title: "Close Window",
+            default_bindings: &["Cmd+Shift+W"],
+        },
+        CommandInfo {
+            command: Command::Undo,
+            id: "undo",
+            title: "Undo",
+            default_bindings: &["Cmd+Z"],
+        },
+        CommandInfo {
+            command: Command::Redo,
+            id: "redo",
+            title: "Redo",
+            default_bindings: &["Cmd+Shift+Z", "Cmd+Y"],
+        },
+        // The text area already cuts and selects all on its own shortcuts
+        CommandInfo {
+            command: Command::Cut,
+            id: "cut",
+            title: "Cut",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::Find,
+            id: "find",
+            title: "Find",
+            default_bindings: &["Cmd+F"],
+        },
+        CommandInfo {
+            command: Command::FindNext,
+            id: "find_next",
+            title: "Find Next",
+            default_bindings: &["F3"],
+        },
+        CommandInfo {
+            command: Command::FindPrevious,
+            id: "find_previous",
+            title: "Find Previous",
+            default_bindings: &["Shift+F3"],
+        },
+        CommandInfo {
+            command: Command::Replace,
+            id: "replace",
+            title: "Replace",
+            default_bindings: &["Cmd+R"],
+        },
+        CommandInfo {
+            command: Command::ReplaceAll,
+            id: "replace_all",
+            title: "Replace All",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::GoTo,
+            id: "go_to",
+            title: "Go To",
+            default_bindings: &["Cmd+G"],
+        },
+        CommandInfo {
+            command: Command::ConvertLineEndings,
+            id: "convert_line_endings",
+            title: "Convert Line Endings",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::SelectAll,
+            id: "select_all",
+            title: "Select All",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::ChangeLanguage,
+            id: "change_language",
+            title: "Change Language Mode",
+            default_bindings: &["Cmd+K M"],
+        },
+        CommandInfo {
+            command: Command::ToggleFullscreen,
+            id: "toggle_fullscreen",
+            title: "Toggle Fullscreen",
+            default_bindings: &["F11"],
+        },
+        CommandInfo {
+            command: Command::SelectTheme,
+            id: "color_theme",
+            title: "Color Theme",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::ReloadThemes,
+            id: "reload_themes",
+            title: "Reload Themes",
+            default_bindings: &[],
+        },
+        CommandInfo {
+            command: Command::ShowCommandPalette,
+            id: "command_palette",
+            title: "Command Palette",
+            default_bindings: &["Cmd+Shift+P"],
+        },
+    ];
+
+    pub fn get_command_info(command: Command) -> &'static CommandInfo {
+        COMMANDS
+            .iter()
+            .find(|info| info.command == command)
+            .expect("every command is registered")
+    }
+
+    /// Whether `command` asks which option to use, e.g. the encoding for Reopen with Encoding
+    pub fn has_choices(command: Command) -> bool {
+        matches!(
+            command,
+            Command::OpenRecent
+                | Command::ReopenWithEncoding
+                | Command::SaveWithEncoding
+                | Command::SetBackupMode
+                | Command::ConvertLineEndings
+                | Command::SelectTheme
+        )
+    }
+
+    /// An option picked for a command that has choices
+    #[derive(Clone, PartialEq)]
+    pub enum CommandOption {
+        RecentFile(String),
+        Encoding(&'static Encoding),
+        BackupMode(BackupMode),
+        LineEnding(LineEnding),
+        /// A theme by name, `None` to follow the system setting
+        Theme(Option<String>),
+    }
+
+    /// One option of a command, as listed in its submenu and in the command palette
+    pub struct CommandChoice {
+        pub label: String,
+        pub option: CommandOption,
+        /// Whether the option is the one in use, `None` for options that are actions
+        pub is_current: Option<bool>,
+    }
+
+    /// Keys pressed one after the other to run a command, e.g. `Ctrl+K Ctrl+S`
+    pub type Binding = Vec<KeyboardShortcut>;
+
+    /// Parses keys separated by spaces, each written as modifiers and a key joined by `+`
+    pub fn parse_binding(text: &str) -> Result<Binding, String> {
+        let binding = text
+            .split_whitespace()
+            .map(parse_shortcut)
+            .collect::<Result<Binding, String>>()?;
+        if binding.is_empty() {
+            return Err("Empty key binding".into());
+        }
+        Ok(binding)
+    }
+
+    fn parse_shortcut(text: &str) -> Result<KeyboardShortcut, String> {
+        let mut parts = text.split('+').collect::<Vec<_>>();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = Key::from_name(key_name)
+            .ok_or_else(|| format!("Unknown key \"{}\" in \"{}\"", key_name, text))?;
+        let mut modifiers = Modifiers::NONE;
+        for part in parts {
+            modifiers = modifiers
+                | match part.to_lowercase().as_str() {
+                    "cmd" | "command" | "cmdorctrl" => Modifiers::COMMAND,
+                    "ctrl" | "control" => Modifiers::CTRL,
+                    "shift" => Modifiers::SHIFT,
+                    "alt" | "option" => Modifiers::ALT,
+                    _ => return Err(format!("Unknown modifier \"{}\" in \"{}\"", part, text)),
+                };
+        }
+        Ok(KeyboardShortcut::new(modifiers, key))
+    }
+
+    /// Where users override the default bindings
+    pub fn get_keymap_path() -> Option<PathBuf> {
+        eframe::storage_dir("Reditor").map(|dir| dir.join("keymap.toml"))
+    }
+
+    /// The keymap file: a `[bindings]` table from command names to one binding or a list of
+    /// them, where an empty list unbinds the command
+    #[derive(Deserialize)]
+    struct KeymapFile {
+        #[serde(default)]
+        bindings: HashMap<String, BindingValue>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BindingValue {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    /// Maps key presses to commands, remembering the keys of a chord typed so far
+    pub struct Keymap {
+        bindings: Vec<(Binding, Command)>,
+        pending: Binding,
+    }
+
+    impl Default for Keymap {
+        fn default() -> Self {
+            Self::from_toml("").0
+        }
+    }
+
+    impl Keymap {
+        /// Reads the keymap file at `path`, falling back to the defaults for anything it
+        /// doesn't set. Returns the problems found along the way for the user to fix.
+        pub fn load(path: &Path) -> (Self, Vec<String>) {
+            match fs::read_to_string(path) {
+                Ok(text) => Self::from_toml(&text),
+                Err(err) if err.kind() == ErrorKind::NotFound => (Self::default(), Vec::new()),
+                Err(err) => (
+                    Self::default(),
+                    vec![format!("Cannot read {}: {}", path.display(), err)],
+                ),
+            }
+        }
+
+        /// Builds the keymap from the contents of a keymap file. Bindings that clash with an
+        /// earlier one are left out and reported, so user bindings win over the defaults.
+        pub fn from_toml(text: &str) -> (Self, Vec<String>) {
+            let mut problems = Vec::new();
+            let file = toml::from_str::<KeymapFile>(text).unwrap_or_else(|err| {
+                problems.push(format!("Invalid keymap file: {}", err));
+                KeymapFile {
+                    bindings: HashMap::new(),
+                }
+            });
+
+            let mut user_bindings = HashMap::new();
+            for (id, value) in file.bindings {
+                let Some(info) = COMMANDS.iter().find(|info| info.id == id) else {
+                    problems.push(format!("Unknown command \"{}\"", id));
+                    continue;
+                };
+                let texts = match value {
+                    BindingValue::One(text) => vec![text],
+                    BindingValue::Many(texts) => texts,
+                };
+                user_bindings.insert(info.command, texts);
+            }
+
+            let mut keymap = Self {
+                bindings: Vec::new(),
+                pending: Vec::new(),
+            };
+            // User bindings go first so the defaults they take over are the ones left out
+            let user_texts = COMMANDS.iter().filter_map(|info| {
+                user_bindings
+                    .get(&info.command)
+                    .map(|texts| (info, texts.iter().map(String::as_str).collect::<Vec<_>>()))
+            });
+            let default_texts = COMMANDS
+                .iter()
+                .filter(|info| !user_bindings.contains_key(&info.command))
+                .map(|info| (info, info.default_bindings.to_vec()));
+            for (info, texts) in user_texts.chain(default_texts) {
+                for text in texts {
+                    match parse_binding(text) {
+                        Ok(binding) => {
+                            if let Err(problem) = keymap.bind(binding, info) {
+                                problems.push(problem);
+                            }
+                        }
+                        Err(err) => problems.push(format!("{} of \"{}\": {}", text, info.id, err)),
+                    }
+                }
+            }
+            (keymap, problems)
+        }
+
+        /// Adds `binding` unless it equals an existing binding or one is a prefix of the
+        /// other, which would make the longer chord impossible to type
+        fn bind(&mut self, binding: Binding, info: &CommandInfo) -> Result<(), String> {
+            let clash = self.bindings.iter().find(|(other, _)| {
+                binding
+                    .iter()
+                    .zip(other)
+                    .all(|(a, b)| a.logical_key == b.logical_key && get_keys(a) == get_keys(b))
+            });
+            if let Some((other, command)) = clash {
+                return Err(format!(
+                    "\"{}\" for \"{}\" conflicts with \"{}\" for \"{}\" and is ignored",
+                    format_binding_text(&binding),
+                    info.id,
+                    format_binding_text(other),
+                    get_command_info(*command).id
+                ));
+            }
+            self.bindings.push((binding, info.command));
+            Ok(())
+        }
+
+        /// Takes the key presses that belong to bindings out of `events` and returns the
+        /// commands they complete. Text typed by a consumed key is dropped too.
+        pub fn handle_events(&mut self, events: &mut Vec<Event>) -> Vec<Command> {
+            let mut commands = Vec::new();
+            let mut is_key_consumed = false;
+            events.retain(|event| match event {
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => {
+                    let mut typed = self.pending.clone();
+                    typed.push(KeyboardShortcut::new(*modifiers, *key));
+                    is_key_consumed = true;
+                    if let Some(command) = self.find_command(&typed) {
+                        commands.push(command);
+                        self.pending.clear();
+                    } else if self.is_chord_prefix(&typed) {
+                        self.pending = typed;
+                    } else if !self.pending.is_empty() {
+                        // A chord that leads nowhere swallows its last key
+                        self.pending.clear();
+                    } else {
+                        is_key_consumed = false;
+                    }
+                    !is_key_consumed
+                }
+                Event::Text(_) => !is_key_consumed,
+                _ => true,
+            });
+            commands
+        }
+
+        /// The first keys of a chord typed so far, if any
+        pub fn get_pending_text(&self, ctx: &egui::Context) -> Option<String> {
+            (!self.pending.is_empty()).then(|| format_binding(&self.pending, ctx))
+        }
+
+        fn find_command(&self, typed: &[KeyboardShortcut]) -> Option<Command> {
+            self.bindings
+                .iter()
+                .find(|(binding, _)| binding.len() == typed.len() && matches(binding, typed))
+                .map(|(_, command)| *command)
+        }
+
+        fn is_chord_prefix(&self, typed: &[KeyboardShortcut]) -> bool {
+            self.bindings.iter().any(|(binding, _)| {
+                binding.len() > typed.len() && matches(&binding[..typed.len()], typed)
+            })
+        }
+
+        /// The first binding of `command`, as shown next to it in menus
+        pub fn get_shortcut_text(&self, command: Command, ctx: &egui::Context) -> String {
+            self.bindings
+                .iter()
+                .find(|(_, bound)| *bound == command)
+                .map(|(binding, _)| format_binding(binding, ctx))
+                .unwrap_or_default()
+        }
+    }
+
+    /// Writes keys the way the platform shows them, e.g. `Ctrl+K S` or `⌘K S`
+    fn format_binding(binding: &[KeyboardShortcut], ctx: &egui::Context) -> String {
+        binding
+            .iter()
+            .map(|shortcut| ctx.format_shortcut(shortcut))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Whether the keys in `typed` press the keys of `binding`, with Cmd standing for Ctrl
+    /// outside macOS
+    fn matches(binding: &[KeyboardShortcut], typed: &[KeyboardShortcut]) -> bool {
+        binding.iter().zip(typed).all(|(bound, pressed)| {
+            bound.logical_key == pressed.logical_key
+                && pressed.modifiers.matches_exact(bound.modifiers)
+        })
+    }
+
+    /// The modifiers actually held for `shortcut` on this platform, where Cmd is Ctrl
+    /// outside macOS
+    fn get_keys(shortcut: &KeyboardShortcut) -> Modifiers {
+        let mut modifiers = shortcut.modifiers;
+        if !cfg!(target_os = "macos") && modifiers.command {
+            modifiers.command = false;
+            modifiers.ctrl = true;
+        }
+        modifiers
+    }
+
+    /// Writes a binding the way the keymap file does, for messages about the file
+    fn format_binding_text(binding: &Binding) -> String {
+        binding
+            .iter()
+            .map(|shortcut| {
+                let mut parts = Vec::new();
+                if shortcut.modifiers.command {
+                    parts.push("Cmd");
+                }
+                if shortcut.modifiers.ctrl {
+                    parts.push("Ctrl");
+                }
+                if shortcut.modifiers.alt {
+                    parts.push("Alt");
+                }
+                if shortcut.modifiers.shift {
+                    parts.push("Shift");
+                }
+                parts.push(shortcut.logical_key.name());
+                parts.join("+")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn press(modifiers: Modifiers, key: Key) -> Event {
+            Event::Key {
+                key,
+                physical_key: None,
+                pressed: true,
+                repeat: false,
+                // Cmd is reported as Ctrl outside macOS
+                modifiers: if modifiers.command {
+                    modifiers | Modifiers::CTRL
+                } else {
+                    modifiers
+                },
+            }
+        }
+
+        #[test]
+        fn default_bindings_do_not_conflict() {
+            let (_, problems) = Keymap::from_toml("");
+
+            assert_eq!(problems, Vec::<String>::new());
+        }
+
+        #[test]
+        fn bindings_are_parsed() {
+            assert_eq!(
+                parse_binding("Ctrl+K Cmd+Shift+S").unwrap(),
+                vec![
+                    KeyboardShortcut::new(Modifiers::CTRL, Key::K),
+                    KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::S),
+                ]
+            );
+            assert!(parse_binding("Hyper+S").is_err());
+            assert!(parse_binding("Ctrl+Nope").is_err());
+            assert!(parse_binding(" ").is_err());
+        }
+
+        #[test]
+        fn shortcuts_run_their_command_and_are_consumed() {
+            let mut keymap = Keymap::default();
+            let mut events = vec![
+                press(Modifiers::COMMAND, Key::S),
+                press(Modifiers::NONE, Key::A),
+                Event::Text("a".into()),
+            ];
+
+            assert_eq!(keymap.handle_events(&mut events), vec![Command::Save]);
+            assert_eq!(events.len(), 2);
+        }
+
+        #[test]
+        fn extra_modifiers_pick_another_command() {
+            let mut keymap = Keymap::default();
+            let mut events = vec![press(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)];
+
+            assert_eq!(keymap.handle_events(&mut events), vec![Command::Redo]);
+        }
+
+        #[test]
+        fn chords_wait_for_their_last_key() {
+            let mut keymap = Keymap::default();
+            let mut events = vec![press(Modifiers::COMMAND, Key::K)];
+            assert!(keymap.handle_events(&mut events).is_empty());
+            let ctx = egui::Context::default();
+            assert!(keymap.get_pending_text(&ctx).is_some());
+
+            // The second key's text must not reach the text area
+            let mut events = vec![press(Modifiers::NONE, Key::S), Event::Text("s".into())];
+            assert_eq!(keymap.handle_events(&mut events), vec![Command::SaveAll]);
+            assert!(events.is_empty());
+            assert!(keymap.get_pending_text(&ctx).is_none());
+        }
+
+        #[test]
+        fn unfinished_chord_swallows_the_next_key() {
+            let mut keymap = Keymap::default();
+            let mut events = vec![
+                press(Modifiers::COMMAND, Key::K),
+                press(Modifiers::NONE, Key::X),
+                Event::Text("x".into()),
+                press(Modifiers::NONE, Key::Y),
+                Event::Text("y".into()),
+            ];
+
+            assert!(keymap.handle_events(&mut events).is_empty());
+            assert_eq!(events.len(), 2);
+        }
+
+        #[test]
+        fn user_bindings_replace_defaults_and_conflicts_are_reported() {
+            let (mut keymap, problems) = Keymap::from_toml(
+                r#"
+                [bindings]
+                save = "Ctrl+K Ctrl+S"
+                save_all = ["Ctrl+K"]
+                find = []
+                go_to = "Cmd+F"
+                frobnicate = "F1"
+                "#,
+            );
+
+            assert_eq!(problems.len(), 2, "{:?}", problems);
+            assert!(problems[0].contains("frobnicate"), "{:?}", problems);
+            assert!(problems[1].contains("save_all"), "{:?}", problems);
+
+            let mut events = vec![
+                press(Modifiers::CTRL, Key::K),
+                press(Modifiers::CTRL, Key::S),
+                press(Modifiers::COMMAND, Key::F),
+                press(Modifiers::COMMAND, Key::S),
+            ];
+            assert_eq!(
+                keymap.handle_events(&mut events),
+                vec![Command::Save, Command::GoTo]
+            );
+            assert_eq!(events.len(), 1);
+        }
+
+        #[cfg(not(target_os = "macos"))]
+        #[test]
+        fn shortcut_text_shows_every_key_of_a_chord() {
+            let keymap = Keymap::default();
+            let ctx = egui::Context::default();
+
+            assert_eq!(keymap.get_shortcut_text(Command::SaveAll, &ctx), "Ctrl+K S");
+            assert_eq!(
+                keymap.get_shortcut_text(Command::Redo, &ctx),
+                "Ctrl+Shift+Z"
+            );
+            assert_eq!(keymap.get_shortcut_text(Command::Cut, &ctx), "");
+        }
+
+        #[cfg(not(target_os = "macos"))]
+        #[test]
+        fn cmd_and_ctrl_conflict_outside_macos() {
+            let (_, problems) = Keymap::from_toml("[bindings]\nfind = \"Ctrl+S\"");
+
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+            assert!(problems[0].contains("save"), "{:?}", problems);
+        }
+
+        #[test]
+        fn invalid_file_falls_back_to_defaults() {
+            let (keymap, problems) = Keymap::from_toml("bindings = 3");
+
+            assert_eq!(problems.len(), 1);
+            assert_eq!(keymap.bindings.len(), Keymap::default().bindings.len());
+        }
+    }
+}
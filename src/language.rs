@@ -0,0 +1,462 @@
+pub mod language {
+    use std::path::Path;
+
+    /// Only the start of a file is looked at for a shebang or content heuristics
+    pub const SNIFF_BYTES: usize = 4096;
+    /// Like Vim, modelines are only looked for in the first and last few lines
+    pub const MODELINE_LINES: usize = 5;
+
+    /// Id of the language of files that are not highlighted
+    pub const PLAIN_TEXT: &str = "";
+
+    /// A language that can be picked for a tab, and what to look for to detect it
+    pub struct LanguageInfo {
+        /// Name of the language in modelines and sessions, e.g. `cpp`
+        pub id: &'static str,
+        /// Name shown in the status bar, e.g. `C++`
+        pub name: &'static str,
+        extensions: &'static [&'static str],
+        /// Whole file names, where `*` stands for any text, e.g. `Dockerfile.*`
+        file_names: &'static [&'static str],
+        /// Programs named on a shebang line, e.g. `python` for `#!/usr/bin/env python3`
+        interpreters: &'static [&'static str],
+        /// Other names modelines use for the language, e.g. `sh` or `shell-script`
+        aliases: &'static [&'static str],
+    }
+
+    /// Every language, in the order of the language picker
+    pub const LANGUAGES: &[LanguageInfo] = &[
+        LanguageInfo {
+            id: PLAIN_TEXT,
+            name: "Plain Text",
+            extensions: &["txt", "text"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &["text", "txt", "plaintext"],
+        },
+        LanguageInfo {
+            id: "c",
+            name: "C",
+            extensions: &["c", "h"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "cpp",
+            name: "C++",
+            extensions: &["cpp", "cc", "cxx", "c++", "hpp", "hh", "hxx", "h++", "ipp"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &["c++"],
+        },
+        LanguageInfo {
+            id: "css",
+            name: "CSS",
+            extensions: &["css"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "dockerfile",
+            name: "Dockerfile",
+            extensions: &["dockerfile"],
+            file_names: &[
+                "Dockerfile",
+                "Containerfile",
+                "Dockerfile.*",
+                "Containerfile.*",
+            ],
+            interpreters: &[],
+            aliases: &["docker"],
+        },
+        LanguageInfo {
+            id: "html",
+            name: "HTML",
+            extensions: &["html", "htm", "xhtml"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "javascript",
+            name: "JavaScript",
+            extensions: &["js", "mjs", "cjs", "jsx"],
+            file_names: &[],
+            interpreters: &["node", "nodejs"],
+            aliases: &["js", "javascriptreact", "jsx"],
+        },
+        LanguageInfo {
+            id: "json",
+            name: "JSON",
+            extensions: &["json", "jsonc", "geojson"],
+            file_names: &[".babelrc", ".eslintrc", ".jshintrc", "composer.lock"],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "makefile",
+            name: "Makefile",
+            extensions: &["mk", "mak", "make"],
+            file_names: &["Makefile", "makefile", "GNUmakefile", "Makefile.*"],
+            interpreters: &["make"],
+            aliases: &["make"],
+        },
+        LanguageInfo {
+            id: "markdown",
+            name: "Markdown",
+            extensions: &["md", "markdown", "mdown", "mkd"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &["md"],
+        },
+        LanguageInfo {
+            id: "python",
+            name: "Python",
+            extensions: &["py", "pyw", "pyi"],
+            file_names: &["SConstruct", "SConscript", ".pythonrc"],
+            interpreters: &["python", "pypy"],
+            aliases: &["py", "python3"],
+        },
+        LanguageInfo {
+            id: "rust",
+            name: "Rust",
+            extensions: &["rs"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &["rs"],
+        },
+        LanguageInfo {
+            id: "shell",
+            name: "Shell Script",
+            extensions: &["sh", "bash", "zsh", "ksh"],
+            file_names: &[
+                ".bashrc",
+                ".bash_profile",
+                ".bash_login",
+                ".bash_logout",
+                ".bash_aliases",
+                ".zshrc",
+                ".zshenv",
+                ".zprofile",
+                ".zlogin",
+                ".profile",
+                ".envrc",
+                "PKGBUILD",
+            ],
+            interpreters: &["sh", "bash", "zsh", "ksh", "dash", "ash"],
+            aliases: &["sh", "bash", "zsh", "shellscript", "shell-script"],
+        },
+        LanguageInfo {
+            id: "toml",
+            name: "TOML",
+            extensions: &["toml"],
+            file_names: &["Cargo.lock", "Pipfile", "poetry.lock"],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "typescript",
+            name: "TypeScript",
+            extensions: &["ts", "mts", "cts"],
+            file_names: &[],
+            interpreters: &["ts-node", "deno", "bun"],
+            aliases: &["ts"],
+        },
+        LanguageInfo {
+            id: "typescriptreact",
+            name: "TypeScript JSX",
+            extensions: &["tsx"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &["tsx"],
+        },
+        LanguageInfo {
+            id: "xml",
+            name: "XML",
+            extensions: &["xml", "xsd", "xsl", "xslt", "svg", "plist"],
+            file_names: &[],
+            interpreters: &[],
+            aliases: &[],
+        },
+        LanguageInfo {
+            id: "yaml",
+            name: "YAML",
+            extensions: &["yaml", "yml"],
+            file_names: &[".clang-format", ".clang-tidy"],
+            interpreters: &[],
+            aliases: &["yml"],
+        },
+    ];
+
+    /// A language and a test of whether a text looks like it
+    type Heuristic = (&'static str, fn(&str) -> bool);
+
+    /// Guesses at the language of files that none of the other signs name, in order
+    const HEURISTICS: &[Heuristic] = &[
+        ("xml", |text| text.starts_with("<?xml")),
+        ("html", |text| {
+            let start = text.get(..15).unwrap_or(text).to_ascii_lowercase();
+            start.starts_with("<!doctype html") || start.starts_with("<html")
+        }),
+        ("json", |text| {
+            let mut chars = text.chars().filter(|c| !c.is_whitespace());
+            chars.next() == Some('{') && matches!(chars.next(), Some('"' | '}'))
+        }),
+        ("yaml", |text| {
+            text.starts_with("---\n") || text.starts_with("%YAML")
+        }),
+        ("dockerfile", |text| {
+            let mut lines = text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'));
+            lines.next().is_some_and(|line| line.starts_with("FROM "))
+        }),
+        ("cpp", |text| {
+            has_line_starting_with(text, &["#include"])
+                && ["std::", "namespace ", "template <", "template<", "class "]
+                    .iter()
+                    .any(|sign| text.contains(sign))
+        }),
+        ("c", |text| has_line_starting_with(text, &["#include"])),
+        ("rust", |text| {
+            has_line_starting_with(text, &["fn main(", "use std::", "pub fn ", "#[derive("])
+        }),
+        ("python", |text| {
+            text.lines().any(|line| {
+                (line.starts_with("def ") && line.trim_end().ends_with(':'))
+                    || (line.starts_with("from ") && line.contains(" import "))
+                    || line.starts_with("if __name__ ==")
+            })
+        }),
+        ("toml", |text| {
+            text.lines().any(|line| {
+                let line = line.trim();
+                line.starts_with('[')
+                    && line.ends_with(']')
+                    && line
+                        .trim_matches(['[', ']'])
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "_-.\"".contains(c))
+            }) && text.lines().any(|line| line.contains(" = "))
+        }),
+        ("markdown", |text| {
+            text.lines()
+                .next()
+                .is_some_and(|line| line.starts_with("# "))
+                && (text.contains("\n```") || text.contains("\n## ") || text.contains("]("))
+        }),
+    ];
+
+    fn has_line_starting_with(text: &str, starts: &[&str]) -> bool {
+        text.lines()
+            .any(|line| starts.iter().any(|start| line.starts_with(start)))
+    }
+
+    pub fn get_language_info(id: &str) -> Option<&'static LanguageInfo> {
+        LANGUAGES.iter().find(|info| info.id == id)
+    }
+
+    /// The name of the language `id` for the status bar
+    pub fn get_language_name(id: &str) -> &'static str {
+        get_language_info(id).map_or("Plain Text", |info| info.name)
+    }
+
+    /// Finds a language by its id, name or one of the names modelines use for it
+    fn find_language(name: &str) -> Option<&'static str> {
+        let name = name.trim().to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|info| {
+                info.id == name
+                    || info.name.to_lowercase() == name
+                    || info.aliases.contains(&&*name)
+            })
+            .map(|info| info.id)
+    }
+
+    /// Returns the language of the file at `path` whose contents start with `head` and end
+    /// with `tail`, which may both be the whole text. A modeline wins over the file name,
+    /// which wins over a shebang line and then over guesses from the contents.
+    pub fn detect_language(path: Option<&Path>, head: &str, tail: &str) -> &'static str {
+        let mut end = SNIFF_BYTES.min(head.len());
+        while !head.is_char_boundary(end) {
+            end -= 1;
+        }
+        let head = &head[..end];
+        find_modeline_language(head, tail)
+            .or_else(|| path.and_then(find_path_language))
+            .or_else(|| find_shebang_language(head))
+            .or_else(|| {
+                HEURISTICS
+                    .iter()
+                    .find(|(_, matches)| matches(head))
+                    .map(|(id, _)| *id)
+            })
+            .unwrap_or(PLAIN_TEXT)
+    }
+
+    fn find_path_language(path: &Path) -> Option<&'static str> {
+        let file_name = path.file_name()?.to_str()?;
+        if let Some(info) = LANGUAGES.iter().find(|info| {
+            info.file_names
+                .iter()
+                .any(|pattern| matches_pattern(pattern, file_name))
+        }) {
+            return Some(info.id);
+        }
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|info| info.extensions.contains(&extension.as_str()))
+            .map(|info| info.id)
+    }
+
+    /// Matches `name` against `pattern`, in which one `*` stands for any text
+    fn matches_pattern(pattern: &str, name: &str) -> bool {
+        match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                name.len() >= prefix.len() + suffix.len()
+                    && name.starts_with(prefix)
+                    && name.ends_with(suffix)
+            }
+            None => pattern == name,
+        }
+    }
+
+    /// Reads the program of a `#!` line, looking past `env` and its options, and without
+    /// its version, e.g. `python` for `#!/usr/bin/env -S python3.12 -u`
+    fn find_shebang_language(text: &str) -> Option<&'static str> {
+        let line = text.lines().next()?.strip_prefix("#!")?;
+        let mut words = line.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        }
+        let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        LANGUAGES
+            .iter()
+            .find(|info| info.interpreters.contains(&program))
+            .map(|info| info.id)
+    }
+
+    /// Looks for a Vim (`vim: ft=rust`, `vim: set filetype=rust :`) or Emacs
+    /// (`-*- mode: rust -*-`) modeline near the start of `head` or the end of `tail`
+    fn find_modeline_language(head: &str, tail: &str) -> Option<&'static str> {
+        head.lines()
+            .take(MODELINE_LINES)
+            .chain(tail.lines().rev().take(MODELINE_LINES))
+            .find_map(|line| parse_vim_modeline(line).or_else(|| parse_emacs_modeline(line)))
+            .and_then(find_language)
+    }
+
+    fn parse_vim_modeline(line: &str) -> Option<&str> {
+        let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+            line.match_indices(marker)
+                .find(|(idx, _)| *idx == 0 || line[..*idx].ends_with(char::is_whitespace))
+                .map(|(idx, _)| idx + marker.len())
+        })?;
+        line[start..]
+            .split([' ', '\t', ':'])
+            .find_map(|option| {
+                let (name, value) = option.split_once('=')?;
+                matches!(name, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+            })
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse_emacs_modeline(line: &str) -> Option<&str> {
+        let (_, rest) = line.split_once("-*-")?;
+        let (variables, _) = rest.split_once("-*-")?;
+        if !variables.contains(':') {
+            return Some(variables.trim());
+        }
+        variables.split(';').find_map(|variable| {
+            let (name, value) = variable.split_once(':')?;
+            (name.trim().eq_ignore_ascii_case("mode")).then_some(value.trim())
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn detect(path: &str, text: &str) -> &'static str {
+            detect_language(Some(Path::new(path)), text, text)
+        }
+
+        fn guess(text: &str) -> &'static str {
+            detect_language(None, text, text)
+        }
+
+        #[test]
+        fn file_names_and_extensions_give_the_language() {
+            assert_eq!(detect("/project/main.rs", ""), "rust");
+            assert_eq!(detect("/project/README.MD", ""), "markdown");
+            assert_eq!(detect("/project/Makefile", ""), "makefile");
+            assert_eq!(detect("/project/Dockerfile.dev", ""), "dockerfile");
+            assert_eq!(detect("/home/me/.bashrc", ""), "shell");
+            assert_eq!(detect("/project/Cargo.lock", ""), "toml");
+            assert_eq!(detect("/project/notes", ""), PLAIN_TEXT);
+        }
+
+        #[test]
+        fn shebang_names_the_interpreter() {
+            assert_eq!(detect("/bin/tool", "#!/bin/bash\necho hi"), "shell");
+            assert_eq!(detect("/bin/tool", "#!/usr/bin/env python3.12\n"), "python");
+            assert_eq!(
+                detect("/bin/tool", "#!/usr/bin/env -S node --no-warnings\n"),
+                "javascript"
+            );
+            assert_eq!(detect("/bin/tool", "#!/usr/bin/perl\n"), PLAIN_TEXT);
+            // The extension is more specific than the interpreter
+            assert_eq!(detect("/bin/tool.py", "#!/bin/sh\n"), "python");
+        }
+
+        #[test]
+        fn modelines_win_over_the_file_name() {
+            assert_eq!(detect("/project/build.txt", "# vim: ft=python\n"), "python");
+            assert_eq!(
+                detect(
+                    "/project/a.conf",
+                    "one\ntwo\n/* vim: set filetype=cpp ts=4 : */"
+                ),
+                "cpp"
+            );
+            assert_eq!(
+                detect("/project/a", "# -*- mode: shell-script; -*-\n"),
+                "shell"
+            );
+            assert_eq!(detect("/project/a", "// -*- C++ -*-\n"), "cpp");
+            // Not a modeline
+            assert_eq!(
+                detect("/project/a.rs", "// see the novim: ft=python docs\n"),
+                "rust"
+            );
+        }
+
+        #[test]
+        fn contents_are_guessed_without_other_signs() {
+            assert_eq!(guess("{\n  \"key\": 1\n}"), "json");
+            assert_eq!(guess("<?xml version=\"1.0\"?>"), "xml");
+            assert_eq!(guess("# base\nFROM rust:1\nRUN make"), "dockerfile");
+            assert_eq!(guess("#include <vector>\nstd::vector<int> v;"), "cpp");
+            assert_eq!(guess("#include <stdio.h>\nint x;"), "c");
+            assert_eq!(guess("use std::fs;\n"), "rust");
+            assert_eq!(guess("def main():\n    pass"), "python");
+            assert_eq!(guess("[package]\nname = \"x\""), "toml");
+            assert_eq!(guess("just some words"), PLAIN_TEXT);
+        }
+
+        #[test]
+        fn languages_are_found_by_id_name_or_alias() {
+            assert_eq!(find_language("Rust"), Some("rust"));
+            assert_eq!(find_language("sh"), Some("shell"));
+            assert_eq!(find_language("text"), Some(PLAIN_TEXT));
+            assert_eq!(find_language("cobol"), None);
+            assert_eq!(get_language_name("cpp"), "C++");
+        }
+    }
+}
@@ -1,8 +1,34 @@
+// Every module wraps its items in a module of the same name
+#![allow(clippy::module_inception)]
+
+mod buffer;
+mod cli;
+mod diff;
 mod editor;
+mod encoding;
 mod enums;
+mod file_io;
+#[cfg(test)]
+mod harness;
+mod highlight;
+mod history;
+#[cfg(unix)]
+mod instance;
+mod keymap;
+mod language;
+mod palette;
+mod recovery;
+mod search;
+mod session;
 mod tab;
+mod theme;
 mod utility;
+mod view;
+
+use std::io::{self, Read};
+use std::process;
 
+use cli::cli::{parse_args, USAGE};
 use editor::editor::TextEditorApp;
 use eframe::{egui, run_native, NativeOptions};
 
@@ -11,6 +37,44 @@ use egui::ViewportBuilder;
 use utility::utility::get_next_id;
 
 fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if args.show_help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let stdin = if args.read_stdin {
+        let mut bytes = Vec::new();
+        if let Err(err) = io::stdin().read_to_end(&mut bytes) {
+            eprintln!("Cannot read standard input: {}", err);
+            process::exit(1);
+        }
+        Some(bytes)
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    let listener = if args.new_window {
+        None
+    } else {
+        match instance::instance::forward(&args, stdin.as_deref()) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => eprintln!("Cannot reach the running Reditor window: {}", err),
+        }
+        // Another window may claim the socket first; this one then simply runs on its own
+        instance::instance::listen().ok()
+    };
+    #[cfg(unix)]
+    let is_listening = listener.is_some();
+
     let win_option = NativeOptions {
         viewport: ViewportBuilder::default().with_min_inner_size(Vec2::new(700.0, 400.0)),
         vsync: NativeOptions::default().vsync,
@@ -19,7 +83,8 @@ fn main() {
         hardware_acceleration: NativeOptions::default().hardware_acceleration,
         stencil_buffer: NativeOptions::default().stencil_buffer,
         renderer: NativeOptions::default().renderer,
-        follow_system_theme: NativeOptions::default().follow_system_theme,
+        // The theme follows the system unless one is picked in the View menu
+        follow_system_theme: true,
         run_and_return: NativeOptions::default().run_and_return,
         event_loop_builder: NativeOptions::default().event_loop_builder,
         default_theme: NativeOptions::default().default_theme,
@@ -33,6 +98,19 @@ fn main() {
     let _ = run_native(
         "Reditor",
         win_option,
-        Box::new(|cc: &eframe::CreationContext<'_>| Ok(Box::new(TextEditorApp::new(cc)))),
+        Box::new(move |cc: &eframe::CreationContext<'_>| {
+            #[cfg_attr(not(unix), allow(unused_mut))]
+            let mut app = TextEditorApp::new(cc, &args, stdin);
+            #[cfg(unix)]
+            if let Some(listener) = listener {
+                app.editor.serve_other_instances(&cc.egui_ctx, listener);
+            }
+            Ok(Box::new(app))
+        }),
     );
+
+    #[cfg(unix)]
+    if is_listening {
+        instance::instance::stop_listening();
+    }
 }
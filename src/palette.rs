@@ -0,0 +1,406 @@
+pub mod palette {
+    use std::cmp::Reverse;
+
+    use egui::{Align2, Key, Modifiers, ScrollArea, TextEdit, Vec2, Window};
+    use serde::{Deserialize, Serialize};
+
+    use crate::enums::enums::Command;
+    use crate::keymap::keymap::{get_command_info, CommandChoice, CommandOption, Keymap, COMMANDS};
+
+    /// Key of the commands recently run from the palette in eframe's storage
+    pub const RECENT_COMMANDS_KEY: &str = "recent_commands";
+
+    const MAX_RECENT_COMMANDS: usize = 5;
+
+    /// Ids of the commands last run from the palette, newest first. Ids rather than
+    /// [`Command`]s are stored so that a renamed command is simply forgotten.
+    #[derive(Default, Serialize, Deserialize)]
+    pub struct RecentCommands {
+        ids: Vec<String>,
+    }
+
+    impl RecentCommands {
+        pub fn add(&mut self, command: Command) {
+            let id = get_command_info(command).id;
+            self.ids.retain(|recent| recent != id);
+            self.ids.insert(0, id.to_string());
+            self.ids.truncate(MAX_RECENT_COMMANDS);
+        }
+
+        pub fn get_commands(&self) -> Vec<Command> {
+            self.ids
+                .iter()
+                .filter_map(|id| COMMANDS.iter().find(|info| info.id == id))
+                .map(|info| info.command)
+                .collect()
+        }
+    }
+
+    /// Scores how well `query` matches `text` when its characters are looked for in order,
+    /// ignoring case and spaces. Runs of characters and word starts score higher, skipped
+    /// characters lower. Returns `None` if a character of the query can't be found.
+    pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+        let text = text.chars().collect::<Vec<_>>();
+        let mut score = 0;
+        let mut next_idx = 0;
+        let mut prev_idx = None;
+        for query_char in query.chars().filter(|c| !c.is_whitespace()) {
+            let idx = (next_idx..text.len())
+                .find(|&idx| text[idx].to_lowercase().eq(query_char.to_lowercase()))?;
+            score += 1;
+            if prev_idx.is_some_and(|prev_idx| prev_idx + 1 == idx) {
+                score += 5;
+            }
+            if idx == 0 || !text[idx - 1].is_alphanumeric() {
+                score += 8;
+            }
+            if prev_idx.is_some() {
+                score -= (idx - next_idx) as i32;
+            }
+            prev_idx = Some(idx);
+            next_idx = idx + 1;
+        }
+        Some(score)
+    }
+
+    /// A command listed in the palette
+    pub struct PaletteEntry {
+        pub command: Command,
+        pub is_recent: bool,
+    }
+
+    /// What the user picked in the palette
+    #[derive(Clone)]
+    pub enum PalettePick {
+        Command(Command),
+        /// An option of the command whose choices were listed
+        Choice(Command, CommandOption),
+    }
+
+    /// A row of the palette's list
+    struct PaletteRow {
+        title: String,
+        detail: String,
+        is_recent: bool,
+        pick: PalettePick,
+    }
+
+    /// A search box over every command, opened with Ctrl+Shift+P. It also lists the options
+    /// of a command that has choices once that command is run.
+    #[derive(Default)]
+    pub struct CommandPalette {
+        is_open: bool,
+        query: String,
+        selected: usize,
+        /// The command whose options are listed instead of the commands
+        choosing: Option<(Command, Vec<CommandChoice>)>,
+        pub recent_commands: RecentCommands,
+    }
+
+    impl CommandPalette {
+        pub fn open(&mut self) {
+            self.is_open = true;
+            self.query.clear();
+            self.selected = 0;
+            self.choosing = None;
+        }
+
+        /// Opens the palette on the options of `command`, with the current one selected
+        pub fn open_choices(&mut self, command: Command, choices: Vec<CommandChoice>) {
+            self.open();
+            self.selected = choices
+                .iter()
+                .position(|choice| choice.is_current == Some(true))
+                .unwrap_or(0);
+            self.choosing = Some((command, choices));
+        }
+
+        pub fn close(&mut self) {
+            self.is_open = false;
+        }
+
+        #[cfg(test)]
+        pub fn get_is_open(&self) -> bool {
+            self.is_open
+        }
+
+        /// The commands matching the query, best first. Recently run commands come first
+        /// among equal matches, and all of them when nothing has been typed yet.
+        pub fn get_entries(&self) -> Vec<PaletteEntry> {
+            let recent_commands = self.recent_commands.get_commands();
+            let mut entries = COMMANDS
+                .iter()
+                .filter(|info| info.command != Command::ShowCommandPalette)
+                .filter_map(|info| {
+                    let score = fuzzy_score(&self.query, info.title)?;
+                    let recency = recent_commands.iter().position(|&c| c == info.command);
+                    Some((info.command, score, recency))
+                })
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|&(_, score, recency)| {
+                (Reverse(score), recency.unwrap_or(MAX_RECENT_COMMANDS))
+            });
+            entries
+                .into_iter()
+                .map(|(command, _, recency)| PaletteEntry {
+                    command,
+                    is_recent: recency.is_some(),
+                })
+                .collect()
+        }
+
+        /// The options being chosen from that match the query, best first
+        pub fn get_choice_entries(&self) -> Vec<&CommandChoice> {
+            let Some((_, choices)) = &self.choosing else {
+                return Vec::new();
+            };
+            let mut entries = choices
+                .iter()
+                .filter_map(|choice| Some((choice, fuzzy_score(&self.query, &choice.label)?)))
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|&(_, score)| Reverse(score));
+            entries.into_iter().map(|(choice, _)| choice).collect()
+        }
+
+        fn get_rows(&self, ctx: &egui::Context, keymap: &Keymap) -> Vec<PaletteRow> {
+            match &self.choosing {
+                Some((command, _)) => self
+                    .get_choice_entries()
+                    .into_iter()
+                    .map(|choice| PaletteRow {
+                        title: choice.label.clone(),
+                        detail: if choice.is_current == Some(true) {
+                            "current".to_string()
+                        } else {
+                            String::new()
+                        },
+                        is_recent: false,
+                        pick: PalettePick::Choice(*command, choice.option.clone()),
+                    })
+                    .collect(),
+                None => self
+                    .get_entries()
+                    .into_iter()
+                    .map(|entry| PaletteRow {
+                        title: get_command_info(entry.command).title.to_string(),
+                        detail: keymap.get_shortcut_text(entry.command, ctx),
+                        is_recent: entry.is_recent,
+                        pick: PalettePick::Command(entry.command),
+                    })
+                    .collect(),
+            }
+        }
+
+        /// Draws the palette if it is open and returns what the user picked
+        pub fn show(&mut self, ctx: &egui::Context, keymap: &Keymap) -> Option<PalettePick> {
+            if !self.is_open {
+                return None;
+            }
+
+            // Taken before any widget sees them, so Enter doesn't also reach the find bar
+            let (is_up, is_down, is_enter, is_escape) = ctx.input_mut(|i| {
+                (
+                    i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                    i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                    i.consume_key(Modifiers::NONE, Key::Enter),
+                    i.consume_key(Modifiers::NONE, Key::Escape),
+                )
+            });
+            if is_escape {
+                self.close();
+                return None;
+            }
+
+            let mut picked = None;
+            let window = Window::new("Command Palette")
+                .title_bar(false)
+                .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 40.0))
+                .fixed_size(Vec2::new(400.0, 0.0))
+                .show(ctx, |ui| {
+                    let input_id = egui::Id::new("command_palette_input");
+                    // The text area asks for focus every frame, so take it back before drawing
+                    ui.memory_mut(|mem| mem.request_focus(input_id));
+                    let hint_text = match &self.choosing {
+                        Some((command, _)) => get_command_info(*command).title,
+                        None => "Type a command",
+                    };
+                    let response = ui.add(
+                        TextEdit::singleline(&mut self.query)
+                            .id(input_id)
+                            .hint_text(hint_text)
+                            .desired_width(f32::INFINITY),
+                    );
+                    if response.changed() {
+                        self.selected = 0;
+                    }
+
+                    let entries = self.get_rows(ui.ctx(), keymap);
+                    if is_down {
+                        self.selected += 1;
+                    }
+                    if is_up {
+                        self.selected = self.selected.saturating_sub(1);
+                    }
+                    self.selected = self.selected.min(entries.len().saturating_sub(1));
+                    if is_enter {
+                        picked = entries.get(self.selected).map(|entry| entry.pick.clone());
+                    }
+
+                    let has_sections = self.query.is_empty() && entries.iter().any(|e| e.is_recent);
+                    ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        for (i, entry) in entries.iter().enumerate() {
+                            let is_section_start =
+                                i == 0 || entries[i - 1].is_recent != entry.is_recent;
+                            if has_sections && is_section_start {
+                                ui.weak(if entry.is_recent {
+                                    "Recently used"
+                                } else {
+                                    "Other commands"
+                                });
+                            }
+                            let row = ui.horizontal(|ui| {
+                                let label = ui.selectable_label(i == self.selected, &entry.title);
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| ui.weak(&entry.detail),
+                                );
+                                label
+                            });
+                            let label = row.inner;
+                            if i == self.selected && (is_up || is_down) {
+                                label.scroll_to_me(None);
+                            }
+                            if label
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                picked = Some(entry.pick.clone());
+                            }
+                        }
+                        if entries.is_empty() {
+                            ui.weak(if self.choosing.is_some() {
+                                "No matching options"
+                            } else {
+                                "No matching commands"
+                            });
+                        }
+                    });
+                });
+
+            if let Some(pick) = &picked {
+                if let PalettePick::Command(command) = pick {
+                    self.recent_commands.add(*command);
+                }
+                self.close();
+            } else if window.is_some_and(|window| window.response.clicked_elsewhere()) {
+                self.close();
+            }
+            picked
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::enums::enums::LineEnding;
+
+        #[test]
+        fn fuzzy_score_matches_characters_in_order() {
+            assert!(fuzzy_score("gt", "Go To").is_some());
+            assert!(fuzzy_score("GO TO", "Go To").is_some());
+            assert!(fuzzy_score("tg", "Go To").is_none());
+            assert!(fuzzy_score("", "Save").is_some());
+        }
+
+        #[test]
+        fn fuzzy_score_prefers_word_starts_and_runs() {
+            assert!(fuzzy_score("fs", "Toggle Fullscreen") > fuzzy_score("fs", "Find Previous"));
+            assert!(fuzzy_score("sa", "Save As") > fuzzy_score("sa", "Select All"));
+        }
+
+        #[test]
+        fn best_matches_come_first() {
+            let palette = CommandPalette {
+                query: "save as".into(),
+                ..CommandPalette::default()
+            };
+
+            let entries = palette.get_entries();
+
+            assert_eq!(entries[0].command, Command::SaveAs);
+            assert!(entries
+                .iter()
+                .all(|entry| entry.command != Command::ShowCommandPalette));
+        }
+
+        #[test]
+        fn menu_only_actions_are_listed() {
+            let palette = CommandPalette {
+                query: "convert line endings".into(),
+                ..CommandPalette::default()
+            };
+
+            let entries = palette.get_entries();
+
+            assert_eq!(entries[0].command, Command::ConvertLineEndings);
+            let commands = CommandPalette::default()
+                .get_entries()
+                .into_iter()
+                .map(|entry| entry.command)
+                .collect::<Vec<_>>();
+            for command in [
+                Command::OpenRecent,
+                Command::ReopenWithEncoding,
+                Command::SaveWithEncoding,
+                Command::SetBackupMode,
+                Command::SelectTheme,
+            ] {
+                assert!(commands.contains(&command));
+            }
+        }
+
+        #[test]
+        fn choices_are_filtered_by_the_query() {
+            let mut palette = CommandPalette::default();
+            let choices = [LineEnding::Lf, LineEnding::Crlf, LineEnding::Cr]
+                .into_iter()
+                .map(|line_ending| CommandChoice {
+                    label: line_ending.to_string(),
+                    option: CommandOption::LineEnding(line_ending),
+                    is_current: Some(line_ending == LineEnding::Cr),
+                })
+                .collect();
+            palette.open_choices(Command::ConvertLineEndings, choices);
+            assert_eq!(palette.selected, 2);
+
+            palette.query = "crl".into();
+            let entries = palette.get_choice_entries();
+
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].label, "CRLF");
+        }
+
+        #[test]
+        fn recent_commands_are_listed_first() {
+            let mut palette = CommandPalette::default();
+            palette.recent_commands.add(Command::GoTo);
+            palette.recent_commands.add(Command::Redo);
+
+            let entries = palette.get_entries();
+
+            assert_eq!(entries[0].command, Command::Redo);
+            assert!(entries[0].is_recent);
+            assert_eq!(entries[1].command, Command::GoTo);
+            assert!(!entries[2].is_recent);
+        }
+
+        #[test]
+        fn recent_commands_forget_unknown_ids() {
+            let recent_commands = RecentCommands {
+                ids: vec!["renamed_command".into(), "save".into()],
+            };
+
+            assert_eq!(recent_commands.get_commands(), vec![Command::Save]);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+pub mod recovery {
+    use std::{
+        collections::{hash_map::DefaultHasher, HashMap},
+        fs::{self, File},
+        hash::{Hash, Hasher},
+        io,
+        path::{Path, PathBuf},
+        process,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        diff::diff::{diff_lines, DiffLine},
+        encoding::encoding::decode_bytes,
+        enums::enums::{BackupMode, LineEnding},
+        file_io::file_io::{read_file, write_file},
+        session::session::TabSession,
+        tab::tab::TextEditorTab,
+        utility::utility::convert_line_endings,
+    };
+
+    pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Held locked for as long as the window owning the folder runs
+    const LOCK_FILE_NAME: &str = "lock";
+
+    /// Unsaved contents of a dirty tab, written every [`AUTOSAVE_INTERVAL`]
+    #[derive(Serialize, Deserialize)]
+    pub struct Snapshot {
+        pub saved_at: u64,
+        pub tab: TabSession,
+    }
+
+    /// A snapshot left behind by a window that crashed or was killed
+    pub struct LeftoverSnapshot {
+        pub path: PathBuf,
+        pub snapshot: Snapshot,
+        /// The file as it is on disk now, with line breaks normalized like the snapshot's text
+        pub disk_text: Option<String>,
+        /// Changes from the file on disk to the snapshot, if they differ and can be compared
+        pub diff: Option<Vec<DiffLine>>,
+        pub show_diff: bool,
+    }
+
+    /// Folder holding the snapshots of every window, one subfolder each
+    pub fn get_recovery_root() -> Option<PathBuf> {
+        eframe::storage_dir("Reditor").map(|dir| dir.join("recovery"))
+    }
+
+    /// This window's recovery folder
+    pub struct RecoveryDir {
+        dir: PathBuf,
+        _lock: File,
+        snapshot_hashes: HashMap<usize, u64>,
+        last_autosave_at: Instant,
+    }
+
+    impl RecoveryDir {
+        pub fn create(root: &Path) -> io::Result<Self> {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            let dir = root.join(format!("{}-{}", secs, process::id()));
+            fs::create_dir_all(&dir)?;
+            let lock = File::create(dir.join(LOCK_FILE_NAME))?;
+            lock.lock()?;
+            Ok(Self {
+                dir,
+                _lock: lock,
+                snapshot_hashes: HashMap::new(),
+                last_autosave_at: Instant::now(),
+            })
+        }
+
+        pub fn is_autosave_due(&self) -> bool {
+            self.last_autosave_at.elapsed() >= AUTOSAVE_INTERVAL
+        }
+
+        /// Snapshots dirty tabs that changed since the last autosave and deletes the snapshots
+        /// of tabs that have since been saved or closed
+        pub fn autosave<'a>(&mut self, tabs: impl Iterator<Item = &'a TextEditorTab>) {
+            self.last_autosave_at = Instant::now();
+            let mut hashes = HashMap::new();
+            for tab in tabs.filter(|tab| tab.is_dirty()) {
+                let mut hasher = DefaultHasher::new();
+                for chunk in tab.get_buffer().chunks() {
+                    hasher.write(chunk.as_bytes());
+                }
+                tab.get_file_path().hash(&mut hasher);
+                let hash = hasher.finish();
+                hashes.insert(tab.get_id(), hash);
+                if self.snapshot_hashes.get(&tab.get_id()) == Some(&hash) {
+                    continue;
+                }
+
+                let Some(tab_session) = tab.to_session(false) else {
+                    continue;
+                };
+                let snapshot = Snapshot {
+                    saved_at: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |duration| duration.as_secs()),
+                    tab: tab_session,
+                };
+                if let Err(err) = self.write_snapshot(tab.get_id(), &snapshot) {
+                    eprintln!("{}", err);
+                    hashes.remove(&tab.get_id());
+                }
+            }
+
+            for id in self.snapshot_hashes.keys() {
+                if !hashes.contains_key(id) {
+                    let _ = fs::remove_file(self.get_snapshot_path(*id));
+                }
+            }
+            self.snapshot_hashes = hashes;
+        }
+
+        /// Deletes the folder once the window closed normally and nothing needs recovering
+        pub fn remove(self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+
+        fn get_snapshot_path(&self, id: usize) -> PathBuf {
+            self.dir.join(format!("{}.ron", id))
+        }
+
+        fn write_snapshot(&self, id: usize, snapshot: &Snapshot) -> Result<(), String> {
+            let contents = ron::to_string(snapshot)
+                .map_err(|err| format!("Cannot create a recovery snapshot: {}", err))?;
+            write_file(
+                &self.get_snapshot_path(id),
+                contents.as_bytes(),
+                BackupMode::None,
+            )
+        }
+    }
+
+    /// Collects the snapshots of windows that are no longer running, removing their folders
+    /// when nothing is left in them
+    pub fn find_leftover_snapshots(root: &Path) -> Vec<LeftoverSnapshot> {
+        let Ok(entries) = fs::read_dir(root) else {
+            return Vec::new();
+        };
+
+        let mut leftovers = Vec::new();
+        for dir in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            // A window that is still running holds its lock
+            let Ok(lock) = File::open(dir.join(LOCK_FILE_NAME)) else {
+                continue;
+            };
+            if lock.try_lock().is_err() {
+                continue;
+            }
+
+            let Ok(snapshot_entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            let snapshot_paths = snapshot_entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"));
+            for path in snapshot_paths {
+                match read_snapshot(&path) {
+                    Some(snapshot) => {
+                        let disk_text = read_disk_text(&snapshot.tab);
+                        let text = snapshot.tab.text.as_deref().unwrap_or_default();
+                        let diff = disk_text
+                            .as_deref()
+                            .filter(|disk_text| *disk_text != text)
+                            .and_then(|disk_text| diff_lines(disk_text, text));
+                        leftovers.push(LeftoverSnapshot {
+                            path,
+                            snapshot,
+                            disk_text,
+                            diff,
+                            show_diff: false,
+                        });
+                    }
+                    None => {
+                        let _ = fs::remove_file(&path);
+                    }
+                }
+            }
+            remove_if_empty(&dir);
+        }
+        leftovers.sort_by_key(|leftover| leftover.snapshot.saved_at);
+        leftovers
+    }
+
+    /// Deletes a leftover snapshot that was restored or discarded
+    pub fn discard_snapshot(path: &Path) {
+        let _ = fs::remove_file(path);
+        if let Some(dir) = path.parent() {
+            remove_if_empty(dir);
+        }
+    }
+
+    fn read_snapshot(path: &Path) -> Option<Snapshot> {
+        let contents = fs::read_to_string(path).ok()?;
+        ron::from_str(&contents).ok()
+    }
+
+    fn read_disk_text(tab: &TabSession) -> Option<String> {
+        if tab.file_path.is_empty() {
+            return None;
+        }
+        let bytes = read_file(Path::new(&tab.file_path)).ok()?;
+        let text = decode_bytes(&bytes, None).text;
+        Some(match tab.line_ending {
+            LineEnding::Mixed => text,
+            _ => convert_line_endings(&text, LineEnding::Lf),
+        })
+    }
+
+    fn remove_if_empty(dir: &Path) {
+        let has_snapshots = fs::read_dir(dir).is_ok_and(|mut entries| {
+            entries.any(|entry| entry.is_ok_and(|entry| entry.file_name() != LOCK_FILE_NAME))
+        });
+        if !has_snapshots {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn snapshots_of_a_dead_window_are_found_and_discarded() {
+            let root = tempfile::tempdir().unwrap();
+            let mut recovery = RecoveryDir::create(root.path()).unwrap();
+            let mut tab = TextEditorTab::new("Untitled".into(), "".into());
+            tab.load_stdin(b"unsaved work");
+            recovery.autosave([&tab].into_iter());
+
+            // Still running, so nothing to recover yet
+            assert!(find_leftover_snapshots(root.path()).is_empty());
+
+            drop(recovery);
+            let leftovers = find_leftover_snapshots(root.path());
+            assert_eq!(leftovers.len(), 1);
+            assert_eq!(
+                leftovers[0].snapshot.tab.text.as_deref(),
+                Some("unsaved work")
+            );
+
+            discard_snapshot(&leftovers[0].path);
+            assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+        }
+
+        #[test]
+        fn saved_tabs_lose_their_snapshot() {
+            let root = tempfile::tempdir().unwrap();
+            let mut recovery = RecoveryDir::create(root.path()).unwrap();
+            let mut tab = TextEditorTab::new("Untitled".into(), "".into());
+            tab.load_stdin(b"unsaved work");
+            recovery.autosave([&tab].into_iter());
+
+            recovery.autosave(std::iter::empty());
+
+            let dir = fs::read_dir(root.path()).unwrap().next().unwrap().unwrap();
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        }
+    }
+}
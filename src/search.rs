@@ -0,0 +1,330 @@
+pub mod search {
+    use std::{borrow::Cow, ops::Range};
+
+    use regex::{Regex, RegexBuilder};
+    use regex_syntax::hir::{Class, Hir, HirKind};
+    use regex_syntax::ParserBuilder;
+
+    use crate::buffer::buffer::Buffer;
+
+    #[derive(Clone, Copy, Default, PartialEq)]
+    pub struct SearchOptions {
+        pub use_regex: bool,
+        pub match_case: bool,
+        pub whole_word: bool,
+    }
+
+    /// One pending change of a replace operation, as shown in the Replace All preview
+    pub struct Replacement {
+        pub range: Range<usize>,
+        pub line: usize,
+        pub found: String,
+        pub replace_with: String,
+    }
+
+    /// Compiled search pattern shared by find next, find previous and replace.
+    ///
+    /// Literal searches are escaped and compiled like any other pattern, so every mode goes
+    /// through the same regex engine and returns byte ranges into the searched buffer. The
+    /// buffer is searched a line at a time, borrowing each line from the rope, unless the
+    /// pattern can match a line break; only then is the text read as one piece.
+    #[derive(Clone)]
+    pub struct Matcher {
+        regex: Regex,
+        expand_captures: bool,
+        whole_word: bool,
+        spans_lines: bool,
+    }
+
+    impl Matcher {
+        pub fn new(pattern: &str, options: SearchOptions) -> Result<Self, String> {
+            if pattern.is_empty() {
+                return Err("Nothing to find".into());
+            }
+
+            let source = if options.use_regex {
+                pattern.to_string()
+            } else {
+                regex::escape(pattern)
+            };
+
+            let regex = RegexBuilder::new(&source)
+                .case_insensitive(!options.match_case)
+                .multi_line(true)
+                .build()
+                .map_err(|err| format!("Invalid regular expression: {}", err))?;
+            // The same pattern parsed again, to see what it can match
+            let spans_lines = ParserBuilder::new()
+                .case_insensitive(!options.match_case)
+                .multi_line(true)
+                .build()
+                .parse(&source)
+                .map_or(true, |hir| can_match_line_break(&hir));
+            Ok(Self {
+                regex,
+                expand_captures: options.use_regex,
+                whole_word: options.whole_word,
+                spans_lines,
+            })
+        }
+
+        /// Finds the first non-empty match at or after byte `from`, wrapping to the start
+        pub fn find_next(&self, buffer: &Buffer, from: usize) -> Option<Range<usize>> {
+            let from = from.min(buffer.len_bytes());
+            let line_start = buffer.line_to_byte(buffer.byte_to_line(from));
+            self.pieces(buffer, line_start..buffer.len_bytes())
+                .find_map(|(offset, text)| {
+                    let found = self.find_at(&text, from.saturating_sub(offset))?;
+                    Some(offset + found.start..offset + found.end)
+                })
+                .or_else(|| self.find_all(buffer).next())
+        }
+
+        /// Finds the last non-empty match ending at or before byte `before`, wrapping to the end
+        pub fn find_prev(&self, buffer: &Buffer, before: usize) -> Option<Range<usize>> {
+            let mut last = None;
+            let mut last_before = None;
+            for m in self.find_all(buffer) {
+                if m.end <= before {
+                    last_before = Some(m.clone());
+                }
+                last = Some(m);
+            }
+            last_before.or(last)
+        }
+
+        /// Returns the match starting exactly at byte `start`, if there is one
+        pub fn match_at(&self, buffer: &Buffer, start: usize) -> Option<Range<usize>> {
+            let (offset, text) = self.piece_at(buffer, start);
+            self.find_at(&text, start - offset)
+                .filter(|m| m.start == start - offset)
+                .map(|m| offset + m.start..offset + m.end)
+        }
+
+        /// Builds the replacement for the match at `range`, expanding `$1`/`${name}` in regex mode
+        pub fn expand(&self, buffer: &Buffer, range: Range<usize>, replace_with: &str) -> String {
+            if !self.expand_captures {
+                return replace_with.to_string();
+            }
+            let (offset, text) = self.piece_at(buffer, range.start);
+            let mut expanded = String::new();
+            if let Some(captures) = self.regex.captures_at(&text, range.start - offset) {
+                captures.expand(replace_with, &mut expanded);
+            }
+            expanded
+        }
+
+        /// Lists every change Replace All would make, in text order
+        pub fn replacements(&self, buffer: &Buffer, replace_with: &str) -> Vec<Replacement> {
+            let mut replacements = Vec::new();
+            for (offset, text) in self.pieces(buffer, 0..buffer.len_bytes()) {
+                for found in self.find_iter(&text) {
+                    let replace_with = if self.expand_captures {
+                        let mut expanded = String::new();
+                        if let Some(captures) = self.regex.captures_at(&text, found.start) {
+                            captures.expand(replace_with, &mut expanded);
+                        }
+                        expanded
+                    } else {
+                        replace_with.to_string()
+                    };
+                    replacements.push(Replacement {
+                        range: offset + found.start..offset + found.end,
+                        line: buffer.byte_to_line(offset + found.start),
+                        found: text[found].to_string(),
+                        replace_with,
+                    });
+                }
+            }
+            replacements
+        }
+
+        pub fn find_all<'a>(
+            &'a self,
+            buffer: &'a Buffer,
+        ) -> impl Iterator<Item = Range<usize>> + 'a {
+            self.find_in(buffer, 0..buffer.len_bytes())
+        }
+
+        /// Finds the matches in the lines that `byte_range` starts and ends in
+        pub fn find_in<'a>(
+            &'a self,
+            buffer: &'a Buffer,
+            byte_range: Range<usize>,
+        ) -> impl Iterator<Item = Range<usize>> + 'a {
+            self.pieces(buffer, byte_range)
+                .flat_map(move |(offset, text)| {
+                    self.find_iter(&text)
+                        .map(|m| offset + m.start..offset + m.end)
+                        .collect::<Vec<_>>()
+                })
+        }
+
+        /// Finds the first non-empty match in `text` that starts at or after byte `from`. In
+        /// whole-word mode a match touching a word character on either side is passed over,
+        /// unless its own character on that side is not part of a word, e.g. `.foo` in `a.foo`.
+        fn find_at(&self, text: &str, mut from: usize) -> Option<Range<usize>> {
+            while from <= text.len() {
+                let found = self.regex.find_at(text, from)?;
+                if !found.is_empty() && (!self.whole_word || is_whole_word(text, found.range())) {
+                    return Some(found.range());
+                }
+                // Look again from the next char, which may start an overlapping match
+                from = found.start()
+                    + text[found.start()..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+            }
+            None
+        }
+
+        /// Iterates over the non-overlapping matches in `text`, in order
+        fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+            let mut from = 0;
+            std::iter::from_fn(move || {
+                let found = self.find_at(text, from)?;
+                from = found.end;
+                Some(found)
+            })
+        }
+
+        /// The text a search of `byte_range` looks at, with the byte offset of each piece:
+        /// whole lines, or the range itself when the pattern can match a line break
+        fn pieces<'a>(
+            &self,
+            buffer: &'a Buffer,
+            byte_range: Range<usize>,
+        ) -> Box<dyn Iterator<Item = (usize, Cow<'a, str>)> + 'a> {
+            if self.spans_lines {
+                let text = buffer.slice_bytes(byte_range.clone());
+                return Box::new(std::iter::once((byte_range.start, text)));
+            }
+            let first_line = buffer.byte_to_line(byte_range.start);
+            let last_line = buffer.byte_to_line(byte_range.end);
+            Box::new(
+                (first_line..=last_line)
+                    .map(move |line| (buffer.line_to_byte(line), buffer.line(line))),
+            )
+        }
+
+        /// The piece of text that a match starting at byte `start` lies in
+        fn piece_at<'a>(&self, buffer: &'a Buffer, start: usize) -> (usize, Cow<'a, str>) {
+            if self.spans_lines {
+                return (0, buffer.slice_bytes(0..buffer.len_bytes()));
+            }
+            let line = buffer.byte_to_line(start);
+            (buffer.line_to_byte(line), buffer.line(line))
+        }
+    }
+
+    /// Whether a match of `hir` can contain a line feed, however the pattern spells it
+    fn can_match_line_break(hir: &Hir) -> bool {
+        match hir.kind() {
+            HirKind::Empty | HirKind::Look(_) => false,
+            HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+            HirKind::Class(Class::Unicode(class)) => class
+                .ranges()
+                .iter()
+                .any(|range| range.start() <= '\n' && '\n' <= range.end()),
+            HirKind::Class(Class::Bytes(class)) => class
+                .ranges()
+                .iter()
+                .any(|range| range.start() <= b'\n' && b'\n' <= range.end()),
+            HirKind::Repetition(repetition) => can_match_line_break(&repetition.sub),
+            HirKind::Capture(capture) => can_match_line_break(&capture.sub),
+            HirKind::Concat(hirs) | HirKind::Alternation(hirs) => {
+                hirs.iter().any(can_match_line_break)
+            }
+        }
+    }
+
+    /// Whether the match at `range` of `text` is not part of a longer word on either side
+    fn is_whole_word(text: &str, range: Range<usize>) -> bool {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let found = &text[range.clone()];
+        let is_open_before = !found.starts_with(is_word) || !text[..range.start].ends_with(is_word);
+        let is_open_after = !found.ends_with(is_word) || !text[range.end..].starts_with(is_word);
+        is_open_before && is_open_after
+    }
+
+    /// Applies `replacements` (as returned by [`Matcher::replacements`]) to `buffer`,
+    /// returning what the span from the start of the first to the end of the last match
+    /// becomes
+    pub fn apply_replacements(buffer: &Buffer, replacements: &[Replacement]) -> String {
+        let Some(first) = replacements.first() else {
+            return String::new();
+        };
+        let mut result = String::new();
+        let mut last_end = first.range.start;
+        for replacement in replacements {
+            result.extend(buffer.chunks_in(last_end..replacement.range.start));
+            result.push_str(&replacement.replace_with);
+            last_end = replacement.range.end;
+        }
+        result
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn find_all(pattern: &str, options: SearchOptions, text: &str) -> Vec<Range<usize>> {
+            let buffer = Buffer::new(text.into());
+            Matcher::new(pattern, options)
+                .unwrap()
+                .find_all(&buffer)
+                .collect()
+        }
+
+        #[test]
+        fn whole_word_needs_a_boundary_only_next_to_word_characters() {
+            let options = SearchOptions {
+                whole_word: true,
+                ..SearchOptions::default()
+            };
+
+            assert_eq!(find_all(".foo", options, "a .foo b"), vec![2..6]);
+            assert_eq!(find_all("foo(", options, "x = foo(1)"), vec![4..8]);
+            assert_eq!(find_all("->", options, "a->b"), vec![1..3]);
+            assert_eq!(find_all("cat", options, "concat cat_ cat."), vec![12..15]);
+            assert_eq!(find_all("a-a", options, "xa-a-a"), vec![3..6]);
+        }
+
+        #[test]
+        fn patterns_that_can_match_a_line_break_search_across_lines() {
+            let regex = SearchOptions {
+                use_regex: true,
+                ..SearchOptions::default()
+            };
+            let text = "a\nb";
+
+            for pattern in [
+                r"a\nb",
+                r"(?s)a.b",
+                r"a\x0Ab",
+                r"a\x{a}b",
+                r"a[[:space:]]b",
+                r"a\sb",
+                r"a[^x]b",
+                r"a[\x00-\x7f]b",
+            ] {
+                assert_eq!(find_all(pattern, regex, text), vec![0..3], "{}", pattern);
+            }
+            assert_eq!(find_all("a\nb", SearchOptions::default(), text), vec![0..3]);
+            assert!(find_all(r"a.b", regex, text).is_empty());
+            assert_eq!(find_all(r"^b$", regex, text), vec![2..3]);
+        }
+
+        #[test]
+        fn find_next_starts_at_the_given_byte() {
+            let buffer = Buffer::new("aaa\naa".into());
+            let matcher = Matcher::new("aa", SearchOptions::default()).unwrap();
+
+            assert_eq!(matcher.find_next(&buffer, 0), Some(0..2));
+            assert_eq!(matcher.find_next(&buffer, 1), Some(1..3));
+            assert_eq!(matcher.find_next(&buffer, 2), Some(4..6));
+            assert_eq!(matcher.find_next(&buffer, 5), Some(0..2));
+        }
+    }
+}
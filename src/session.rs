@@ -0,0 +1,102 @@
+pub mod session {
+    use std::ops::Range;
+
+    use egui_dock::DockState;
+    use serde::{Deserialize, Serialize};
+
+    use crate::enums::enums::LineEnding;
+    use crate::file_io::file_io::FileStamp;
+
+    /// Key of the session in eframe's storage
+    pub const SESSION_KEY: &str = "session";
+
+    /// What is needed to bring a tab back after a restart.
+    /// `text` is only kept for untitled and dirty tabs, clean files are read from disk again.
+    #[derive(Serialize, Deserialize)]
+    pub struct TabSession {
+        pub file_path: String,
+        pub title: String,
+        pub text: Option<String>,
+        pub encoding: String,
+        pub has_bom: bool,
+        pub line_ending: LineEnding,
+        pub cursor: Range<usize>,
+        pub scroll_offset: [f32; 2],
+        /// The language picked by the user, if any
+        #[serde(default)]
+        pub language: Option<String>,
+        /// The file as the tab last read or wrote it, to tell on restore whether it changed
+        /// while the editor was closed
+        #[serde(default)]
+        pub disk_stamp: Option<FileStamp>,
+    }
+
+    /// The open tabs and how they are split and docked
+    #[derive(Serialize, Deserialize)]
+    pub struct Session {
+        pub dock_state: DockState<TabSession>,
+    }
+
+    /// Key of the "Open Recent" list in eframe's storage
+    pub const RECENT_FILES_KEY: &str = "recent_files";
+
+    const MAX_RECENT_FILES: usize = 10;
+
+    /// Most recently opened or saved paths, newest first
+    #[derive(Default, Serialize, Deserialize)]
+    pub struct RecentFiles {
+        paths: Vec<String>,
+    }
+
+    impl RecentFiles {
+        pub fn add(&mut self, path: &str) {
+            if path.is_empty() {
+                return;
+            }
+            self.remove(path);
+            self.paths.insert(0, path.to_string());
+            self.paths.truncate(MAX_RECENT_FILES);
+        }
+
+        pub fn remove(&mut self, path: &str) {
+            self.paths.retain(|recent| recent != path);
+        }
+
+        pub fn clear(&mut self) {
+            self.paths.clear();
+        }
+
+        pub fn get_paths(&self) -> &[String] {
+            &self.paths
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn recent_files_move_to_the_front_without_duplicates() {
+            let mut recent_files = RecentFiles::default();
+            recent_files.add("/a.txt");
+            recent_files.add("/b.txt");
+            recent_files.add("/a.txt");
+
+            assert_eq!(recent_files.get_paths(), ["/a.txt", "/b.txt"]);
+        }
+
+        #[test]
+        fn recent_files_keep_only_the_newest() {
+            let mut recent_files = RecentFiles::default();
+            for i in 0..MAX_RECENT_FILES + 5 {
+                recent_files.add(&format!("/{}.txt", i));
+            }
+
+            assert_eq!(recent_files.get_paths().len(), MAX_RECENT_FILES);
+            assert_eq!(
+                recent_files.get_paths()[0],
+                format!("/{}.txt", MAX_RECENT_FILES + 4)
+            );
+        }
+    }
+}
@@ -1,404 +1,864 @@
 pub mod tab {
-    use chardet::detect;
-    use egui_extras::syntax_highlighting::CodeTheme;
+    use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
     use rfd::FileDialog;
     use std::{
-        cmp::{max, min},
-        fs,
         ops::Range,
+        path::{Path, PathBuf},
     };
 
     use egui::{
-        text::CursorRange,
-        text_selection::{text_cursor_state::cursor_rect, visuals::paint_text_selection},
-        Align2, Button, Color32, FontFamily, FontId, ImageButton, Key, RichText, ScrollArea,
-        TextBuffer, Vec2, Widget, Window,
+        popup_above_or_below_widget, AboveOrBelow, Align2, Button, Color32, FontFamily, FontId,
+        ImageButton, Key, PopupCloseBehavior, RichText, ScrollArea, Ui, Vec2, Widget, Window,
     };
-    use egui_dock::{TabIndex, TabViewer};
+    use egui_dock::TabViewer;
 
-    use crate::editor::editor::TEXT_EDITOR;
+    use crate::editor::editor::TextEditor;
+    #[cfg(unix)]
+    use crate::file_io::file_io::find_renamed_file;
     use crate::{
-        enums::enums::{FindBarState, GoToState, ReplaceBarState},
+        buffer::buffer::Buffer,
+        diff::diff::{count_changes, diff_lines, show_diff, DiffLine},
+        encoding::encoding::{decode_bytes, encode_text, get_encoding_label},
+        enums::enums::{
+            BackupMode, Command, ExternalChange, FindBarState, GoToState, LineEnding,
+            ReplaceBarState, UnsavedChangesAction,
+        },
+        file_io::file_io::{
+            get_file_name, get_file_stamp, get_path_string, read_file, write_file, FileStamp,
+        },
         get_next_id,
-        utility::utility::{get_line_ending_format, get_next_word_idx},
+        highlight::highlight::Highlighter,
+        history::history::History,
+        keymap::keymap::{get_command_info, CommandOption},
+        language::language::{
+            detect_language, get_language_name, LANGUAGES, MODELINE_LINES, SNIFF_BYTES,
+        },
+        search::search::{apply_replacements, Matcher, Replacement},
+        session::session::TabSession,
+        utility::utility::{
+            convert_buffer_line_endings, convert_line_endings, detect_line_ending,
+            get_default_line_ending, get_line_col_char_idx, get_next_word_idx,
+        },
+        view::view::{CodeView, LineCache},
     };
 
+    /// Id of the list of languages opened from the status bar
+    pub fn get_language_picker_id() -> egui::Id {
+        egui::Id::new("language_picker")
+    }
+
     pub struct TextEditorTab {
         id: usize,
         file_path: String,
         title: String,
-        text: String,
+        text: Buffer,
         pub status: String,
+        /// Id of the language, see [`LANGUAGES`](crate::language::language::LANGUAGES)
         language: String,
+        /// Whether the language was picked by the user rather than detected
+        is_language_picked: bool,
+        line_ending: LineEnding,
+        encoding: &'static Encoding,
+        has_bom: bool,
         dirty: bool,
+        is_closing: bool,
         is_refreshed: bool,
         is_finding: bool,
         is_replacing: bool,
         has_error: bool,
         error_msg: String,
+        history: History,
+        pending_cursor: Option<Range<usize>>,
+        disk_stamp: Option<FileStamp>,
+        external_change: Option<ExternalChange>,
+        external_diff: Option<Vec<DiffLine>>,
+        pending_scroll: Option<Vec2>,
+        cursor: Range<usize>,
+        scroll_offset: Vec2,
+        replace_preview: Option<Vec<Replacement>>,
+        line_cache: LineCache,
+        highlighter: Highlighter,
     }
 
     impl TextEditorTab {
-        pub fn new(title: String, text: String, status: String) -> Self {
-            Self {
+        pub fn new(title: String, text: String) -> Self {
+            let mut tab = Self {
                 id: get_next_id(),
                 file_path: "".into(),
                 title,
-                text,
-                status,
+                text: Buffer::new(text),
+                history: History::new(),
+                status: "".into(),
                 language: "".into(),
+                is_language_picked: false,
+                line_ending: get_default_line_ending(),
+                encoding: UTF_8,
+                has_bom: false,
                 dirty: false,
+                is_closing: false,
                 is_refreshed: false,
                 is_finding: false,
                 is_replacing: false,
                 has_error: false,
                 error_msg: "".into(),
-            }
+                pending_cursor: None,
+                disk_stamp: None,
+                external_change: None,
+                external_diff: None,
+                pending_scroll: None,
+                cursor: 0..0,
+                scroll_offset: Vec2::ZERO,
+                replace_preview: None,
+                line_cache: LineCache::default(),
+                highlighter: Highlighter::new(),
+            };
+            tab.detect_language();
+            tab.update_status();
+            tab
         }
-    }
 
-    pub struct MyTabViewer;
+        pub fn update_status(&mut self) {
+            let line = self.text.char_to_line(self.cursor.end);
+            let column = self.cursor.end.min(self.text.len_chars()) - self.text.line_to_char(line);
+            self.status = format!(
+                "Ln {} Col {} | 100% | {} | {}",
+                line + 1,
+                column + 1,
+                self.line_ending,
+                get_encoding_label(self.encoding, self.has_bom)
+            );
+        }
 
-    impl TabViewer for MyTabViewer {
-        type Tab = TextEditorTab;
+        /// Loads file contents into the tab, normalizing line breaks to `\n` for editing
+        /// unless the file mixes styles, in which case it is kept byte for byte
+        fn load_text(&mut self, text: String) {
+            self.line_ending = detect_line_ending(&text);
+            self.text = Buffer::new(match self.line_ending {
+                LineEnding::Mixed => text,
+                _ => convert_line_endings(&text, LineEnding::Lf),
+            });
+            self.history.reset();
+            self.update_status();
+        }
 
-        fn on_add(&mut self, _surface: egui_dock::SurfaceIndex, _node: egui_dock::NodeIndex) {
-            let surface = unsafe {
-                TEXT_EDITOR
-                    .get_mut()
-                    .unwrap()
-                    .dock_state
-                    .get_surface_mut(_surface)
-                    .unwrap()
+        /// Returns the buffer as it should be written to disk, in the tab's line ending style
+        fn text_for_save(&self) -> String {
+            convert_buffer_line_endings(&self.text, self.line_ending)
+        }
+
+        /// Reads `path` into the tab, decoding it with `forced_encoding` or the detected encoding
+        /// Leaves the tab untouched if the file cannot be read.
+        pub fn open_file(
+            &mut self,
+            path: &Path,
+            forced_encoding: Option<&'static Encoding>,
+        ) -> Result<(), String> {
+            let file_path = get_path_string(path)?;
+            let title = get_file_name(path)?;
+            let bytes = read_file(path)?;
+            let decoded = decode_bytes(&bytes, forced_encoding);
+            self.file_path = file_path;
+            self.title = title;
+            self.encoding = decoded.encoding;
+            self.has_bom = decoded.has_bom;
+            self.load_text(decoded.text);
+            self.detect_language();
+            self.dirty = false;
+            self.is_refreshed = true;
+            self.disk_stamp = get_file_stamp(path);
+            self.external_change = None;
+            self.external_diff = None;
+            Ok(())
+        }
+
+        /// Compares the file with what the tab last read or wrote. Clean tabs are reloaded,
+        /// dirty ones ask the user, and a file renamed within its folder is followed.
+        pub fn check_disk(&mut self) {
+            let Some(disk_stamp) = self.disk_stamp else {
+                return;
             };
-            let node = surface
-                .node_tree_mut()
-                .and_then(|node| node.root_node_mut());
-            node.unwrap().append_tab(TextEditorTab::new(
-                "Untitled".into(),
-                "".into(),
-                format!("Ln 1 Col 1 | 100% | {} | UTF-8", get_line_ending_format()),
-            ));
+            if self.external_change.is_some() {
+                return;
+            }
+
+            let path = PathBuf::from(&self.file_path);
+            match get_file_stamp(&path) {
+                Some(stamp) if stamp == disk_stamp => {}
+                Some(_) if self.dirty => self.external_change = Some(ExternalChange::Modified),
+                Some(_) => self.reload(),
+                None => {
+                    #[cfg(unix)]
+                    if let Some(new_path) = find_renamed_file(&path, &disk_stamp) {
+                        if self.set_file_path(&new_path).is_ok() {
+                            self.disk_stamp = get_file_stamp(&new_path);
+                            return;
+                        }
+                    }
+                    self.external_change = Some(ExternalChange::Deleted);
+                    // The buffer is all that is left, so closing must ask first
+                    self.dirty = true;
+                }
+            }
         }
 
-        fn closeable(&mut self, _tab: &mut Self::Tab) -> bool {
-            if _tab.title == "+" {
-                false
-            } else {
-                true
+        /// Re-reads the file in its current encoding, keeping the cursor where it was
+        pub fn reload(&mut self) {
+            let cursor = self.cursor.clone();
+            let path = PathBuf::from(&self.file_path);
+            let result = self.open_file(&path, Some(self.encoding));
+            if self.report_error(result) {
+                let char_count = self.text.len_chars();
+                self.pending_cursor =
+                    Some(cursor.start.min(char_count)..cursor.end.min(char_count));
             }
         }
 
-        fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-            tab.title.clone().into()
+        /// Keeps the buffer after the file changed or vanished on disk; the next save writes it
+        pub fn keep_mine(&mut self) {
+            self.disk_stamp = get_file_stamp(Path::new(&self.file_path));
+            self.external_change = None;
+            self.external_diff = None;
         }
 
-        fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
-            ui.horizontal(|ui| {
-                ui.menu_button("File", |ui| {
-                    // TODO : Add check for valid file path if file has previously existed
-                    let save_file = |tab: &mut Self::Tab| {
-                        if tab.file_path.clone().is_empty() {
-                            let file = FileDialog::new()
-                                .add_filter("Text documents", &["txt"])
-                                .add_filter("Rust Source", &["rs"])
-                                .add_filter("Python Source", &["py"])
-                                .save_file();
-                            if let Some(file) = file {
-                                tab.file_path = file.as_path().to_str().unwrap().to_string();
-                                tab.title = file.file_name().unwrap().to_str().unwrap().to_string();
-                                tab.language =
-                                    file.extension().unwrap().to_str().unwrap().to_string();
-                                fs::write(file.clone(), tab.text.clone()).unwrap();
+        /// Opens `path`, or starts an empty buffer that will be saved there if it doesn't exist yet
+        pub fn open_or_create_file(&mut self, path: &Path) -> Result<(), String> {
+            if path.exists() {
+                return self.open_file(path, None);
+            }
+            self.set_file_path(path)?;
+            self.load_text(String::new());
+            self.dirty = false;
+            Ok(())
+        }
+
+        /// Loads text piped in on standard input, which has no file to save back to
+        pub fn load_stdin(&mut self, bytes: &[u8]) {
+            let decoded = decode_bytes(bytes, None);
+            self.encoding = decoded.encoding;
+            self.has_bom = decoded.has_bom;
+            self.load_text(decoded.text);
+            self.dirty = !bytes.is_empty();
+        }
+
+        /// Moves the cursor to a 1-based position, clamped to the text
+        pub fn go_to(&mut self, line: usize, column: usize) {
+            let line = line.clamp(1, self.text.len_lines());
+            let column = column.clamp(1, self.text.line_len_chars(line - 1) + 1);
+            let char_idx = get_line_col_char_idx(&self.text, line, column).unwrap_or(0);
+            self.pending_cursor = Some(char_idx..char_idx);
+        }
+
+        /// Describes the tab for the session saved on exit.
+        /// With `discard_changes` unsaved edits are left out and untitled tabs are dropped.
+        pub fn to_session(&self, discard_changes: bool) -> Option<TabSession> {
+            let keep_text = self.file_path.is_empty() || (self.dirty && !discard_changes);
+            if self.file_path.is_empty() && self.dirty && discard_changes {
+                return None;
+            }
+            Some(TabSession {
+                file_path: self.file_path.clone(),
+                title: self.title.clone(),
+                text: keep_text.then(|| self.text.to_string()),
+                encoding: self.encoding.name().to_string(),
+                has_bom: self.has_bom,
+                line_ending: self.line_ending,
+                cursor: self.cursor.clone(),
+                scroll_offset: self.scroll_offset.into(),
+                language: self.is_language_picked.then(|| self.language.clone()),
+                disk_stamp: self.disk_stamp,
+            })
+        }
+
+        /// Recreates a tab saved with [`TextEditorTab::to_session`].
+        /// Returns `None` for a clean file that can no longer be read.
+        pub fn from_session(session: &TabSession) -> Option<Self> {
+            let mut tab = Self::new(session.title.clone(), "".into());
+            match &session.text {
+                Some(text) => {
+                    if !session.file_path.is_empty() {
+                        let path = Path::new(&session.file_path);
+                        tab.set_file_path(path).ok()?;
+                        // Sessions from before stamps were kept take the file as it is now
+                        tab.disk_stamp = session.disk_stamp.or_else(|| get_file_stamp(path));
+                    }
+                    tab.text = Buffer::new(text.clone());
+                    tab.detect_language();
+                    tab.history.reset();
+                    tab.encoding =
+                        Encoding::for_label(session.encoding.as_bytes()).unwrap_or(UTF_8);
+                    tab.has_bom = session.has_bom;
+                    tab.line_ending = session.line_ending;
+                    tab.dirty = !session.file_path.is_empty() || !text.is_empty();
+                    tab.update_status();
+                    // Edits made to the file while the editor was closed must not be saved over
+                    tab.check_disk();
+                }
+                None => tab.open_file(Path::new(&session.file_path), None).ok()?,
+            }
+            if session.language.is_some() {
+                tab.set_language(session.language.as_deref());
+            }
+
+            let char_count = tab.text.len_chars();
+            tab.cursor = session.cursor.start.min(char_count)..session.cursor.end.min(char_count);
+            tab.pending_cursor = Some(tab.cursor.clone());
+            tab.pending_scroll = Some(session.scroll_offset.into());
+            Some(tab)
+        }
+
+        /// Points the tab at a new file, as chosen in a save dialog
+        fn set_file_path(&mut self, path: &Path) -> Result<(), String> {
+            let file_path = get_path_string(path)?;
+            self.title = get_file_name(path)?;
+            self.file_path = file_path;
+            self.detect_language();
+            Ok(())
+        }
+
+        /// Writes the tab to `path` in its encoding and line ending style
+        pub fn write_file(&mut self, path: &Path, backup_mode: BackupMode) -> Result<(), String> {
+            let bytes = encode_text(&self.text_for_save(), self.encoding, self.has_bom)?;
+            write_file(path, &bytes, backup_mode)?;
+            self.dirty = false;
+            self.disk_stamp = get_file_stamp(path);
+            self.external_change = None;
+            self.external_diff = None;
+            Ok(())
+        }
+
+        pub fn get_id(&self) -> usize {
+            self.id
+        }
+
+        pub fn get_title(&self) -> String {
+            self.title.clone()
+        }
+
+        pub fn is_dirty(&self) -> bool {
+            self.dirty
+        }
+
+        #[cfg(test)]
+        pub fn get_text(&self) -> String {
+            self.text.to_string()
+        }
+
+        pub fn get_buffer(&self) -> &Buffer {
+            &self.text
+        }
+
+        pub fn get_file_path(&self) -> &str {
+            &self.file_path
+        }
+
+        pub fn get_encoding(&self) -> &'static Encoding {
+            self.encoding
+        }
+
+        pub fn get_line_ending(&self) -> LineEnding {
+            self.line_ending
+        }
+
+        #[cfg(test)]
+        pub fn get_language(&self) -> &str {
+            &self.language
+        }
+
+        /// Highlights the tab as `language`, or as the language detected from its file name
+        /// and contents when `None`
+        pub fn set_language(&mut self, language: Option<&str>) {
+            self.is_language_picked = language.is_some();
+            match language {
+                Some(language) => self.language = language.to_string(),
+                None => self.detect_language(),
+            }
+        }
+
+        /// Shows the language of the tab in the status bar, as a button that opens a list of
+        /// languages to pick from
+        pub fn show_language_picker(&mut self, ui: &mut Ui) {
+            let popup_id = get_language_picker_id();
+            let response = ui
+                .button(get_language_name(&self.language))
+                .on_hover_text(get_command_info(Command::ChangeLanguage).title);
+            if response.clicked() {
+                ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+            }
+            let picked = popup_above_or_below_widget(
+                ui,
+                popup_id,
+                &response,
+                AboveOrBelow::Above,
+                PopupCloseBehavior::CloseOnClickOutside,
+                |ui| {
+                    ui.set_min_width(160.0);
+                    let mut picked = None;
+                    ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        if ui
+                            .selectable_label(!self.is_language_picked, "Auto Detect")
+                            .clicked()
+                        {
+                            picked = Some(None);
+                        }
+                        ui.separator();
+                        for info in LANGUAGES {
+                            if ui
+                                .selectable_label(self.language == info.id, info.name)
+                                .clicked()
+                            {
+                                picked = Some(Some(info.id));
                             }
-                        } else {
-                            fs::write(tab.file_path.clone(), tab.text.clone()).unwrap();
                         }
-                    };
+                    });
+                    picked
+                },
+            )
+            .flatten();
+            if let Some(language) = picked {
+                self.set_language(language);
+                ui.memory_mut(|mem| mem.close_popup());
+            }
+        }
 
-                    if ui
-                        .button("New Tab")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let node = unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .dock_state
-                                .main_surface_mut()
-                                .root_node_mut()
-                                .unwrap()
-                        };
-                        node.append_tab(TextEditorTab::new(
-                            "Untitled".into(),
-                            "".into(),
-                            format!("Ln 1 Col 1 | 100% | {} | UTF-8", get_line_ending_format()),
+        /// Detects the language unless the user picked one
+        fn detect_language(&mut self) {
+            if self.is_language_picked {
+                return;
+            }
+            let path = (!self.file_path.is_empty()).then(|| Path::new(&self.file_path));
+            // Only the ends of the text are looked at, so only they are read from the rope
+            let mut head_end = SNIFF_BYTES.min(self.text.len_bytes());
+            while !self.text.is_char_boundary(head_end) {
+                head_end -= 1;
+            }
+            let tail_line = self.text.len_lines().saturating_sub(MODELINE_LINES);
+            let mut tail_start = self
+                .text
+                .line_to_byte(tail_line)
+                .max(self.text.len_bytes().saturating_sub(SNIFF_BYTES));
+            while !self.text.is_char_boundary(tail_start) {
+                tail_start += 1;
+            }
+            let head = self.text.slice_bytes(0..head_end);
+            let tail = self.text.slice_bytes(tail_start..self.text.len_bytes());
+            self.language = detect_language(path, &head, &tail).to_string();
+        }
+
+        #[cfg(test)]
+        pub fn get_line_cache(&self) -> &LineCache {
+            &self.line_cache
+        }
+
+        /// The char range selected when the tab was last drawn
+        #[cfg(test)]
+        pub fn get_cursor(&self) -> Range<usize> {
+            self.cursor.clone()
+        }
+
+        /// Saves to the tab's file, asking for a location first if it has none.
+        /// Returns `false` if the save was cancelled or failed.
+        pub fn save(&mut self, backup_mode: BackupMode) -> bool {
+            let result = if self.file_path.is_empty() {
+                return self.save_as(backup_mode);
+            } else {
+                // Don't overwrite changes another program made since the last check
+                self.check_disk();
+                if self.external_change == Some(ExternalChange::Modified) {
+                    return false;
+                }
+                let file_path = self.file_path.clone();
+                self.write_file(Path::new(&file_path), backup_mode)
+            };
+            self.report_error(result)
+        }
+
+        /// Asks for a location and saves the tab there from now on.
+        /// Returns `false` if the save was cancelled or failed.
+        pub fn save_as(&mut self, backup_mode: BackupMode) -> bool {
+            let file = FileDialog::new()
+                .add_filter("Text documents", &["txt"])
+                .add_filter("Rust Source", &["rs"])
+                .add_filter("Python Source", &["py"])
+                .save_file();
+            let Some(file) = file else {
+                return false;
+            };
+            let result = self
+                .set_file_path(&file)
+                .and_then(|()| self.write_file(&file, backup_mode));
+            self.report_error(result)
+        }
+
+        /// Asks for a file and opens it in this tab.
+        /// Returns `false` if the dialog was cancelled or the file could not be read.
+        pub fn open_with_dialog(&mut self) -> bool {
+            match FileDialog::new().pick_file() {
+                Some(file) => {
+                    let result = self.open_file(&file, None);
+                    self.report_error(result)
+                }
+                None => false,
+            }
+        }
+
+        /// Shows `result`'s error, if any, in the tab's error dialog
+        pub fn report_error(&mut self, result: Result<(), String>) -> bool {
+            match result {
+                Ok(()) => true,
+                Err(err) => {
+                    self.has_error = true;
+                    self.error_msg = err;
+                    false
+                }
+            }
+        }
+
+        /// Converts the whole buffer to `line_ending`
+        pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+            if self.line_ending == LineEnding::Mixed {
+                let text = convert_buffer_line_endings(&self.text, LineEnding::Lf);
+                self.text.set_text(text);
+                self.commit_edit();
+            }
+            self.line_ending = line_ending;
+            self.dirty = true;
+            self.update_status();
+        }
+
+        pub fn undo(&mut self) {
+            self.history.commit(self.text.take_changes(), false);
+            if let Some(range) = self.history.undo(&mut self.text) {
+                self.dirty = true;
+                self.pending_cursor = Some(range);
+            }
+        }
+
+        pub fn redo(&mut self) {
+            self.history.commit(self.text.take_changes(), false);
+            if let Some(range) = self.history.redo(&mut self.text) {
+                self.dirty = true;
+                self.pending_cursor = Some(range);
+            }
+        }
+
+        /// Replaces the match at the `selection` char range, if it is one, and returns the
+        /// char range of the following match
+        pub fn replace_next(
+            &mut self,
+            matcher: &Matcher,
+            replace_with: &str,
+            selection: Range<usize>,
+        ) -> Option<Range<usize>> {
+            let start = self.text.char_to_byte(selection.start);
+            let end = self.text.char_to_byte(selection.end);
+            let mut next_start_idx = selection.start;
+            if matcher.match_at(&self.text, start) == Some(start..end) {
+                let replacement = matcher.expand(&self.text, start..end, replace_with);
+                self.text.replace_bytes(start..end, &replacement);
+                self.commit_edit();
+                next_start_idx += replacement.chars().count();
+            }
+            get_next_word_idx(&self.text, matcher, next_start_idx)
+        }
+
+        /// Applies the changes listed by a confirmed Replace All as one undo step
+        pub fn apply_replacements(&mut self, replacements: &[Replacement]) {
+            let (Some(first), Some(last)) = (replacements.first(), replacements.last()) else {
+                return;
+            };
+            let replaced = apply_replacements(&self.text, replacements);
+            self.text
+                .replace_bytes(first.range.start..last.range.end, &replaced);
+            self.commit_edit();
+        }
+
+        /// Selects the `range` of chars the next time the tab is drawn
+        pub fn select(&mut self, range: Range<usize>) {
+            self.pending_cursor = Some(range);
+        }
+
+        /// Runs a command that acts on this tab, see [`TextEditor::run_command`]
+        pub fn run_command(
+            &mut self,
+            editor: &mut TextEditor,
+            command: Command,
+            ctx: &egui::Context,
+        ) {
+            match command {
+                Command::Open => {
+                    if self.dirty {
+                        editor
+                            .state_manager
+                            .set_unsaved_changes_action(Some(UnsavedChangesAction::Open(self.id)));
+                    } else {
+                        editor.open_with_dialog(self);
+                    }
+                }
+                Command::Save => {
+                    editor.save_tab(self);
+                }
+                Command::SaveAs => {
+                    editor.save_tab_as(self);
+                }
+                Command::CloseTab => {
+                    if self.dirty {
+                        editor.state_manager.set_unsaved_changes_action(Some(
+                            UnsavedChangesAction::CloseTab(self.id),
                         ));
+                    } else {
+                        self.is_closing = true;
                     }
-                    if ui
-                        .button("Open")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let file = FileDialog::new().pick_file();
-                        if let Some(file) = file {
-                            let text = std::fs::read_to_string(file.clone()).unwrap();
-                            tab.text = text.clone();
-                            tab.title = file.file_name().unwrap().to_str().unwrap().to_string();
-                            let encoding = detect(text.as_bytes()).0;
-                            tab.status = format!(
-                                "Ln 1 Col 1 | 100% | {} | {}",
-                                get_line_ending_format(),
-                                encoding
-                            );
-                            tab.language = file.extension().unwrap().to_str().unwrap().to_string();
-                            tab.is_refreshed = true;
-                        }
+                }
+                Command::ReplaceAll => match editor.preview_replace_all(self) {
+                    Ok(replacements) => self.replace_preview = Some(replacements),
+                    Err(err) => {
+                        self.has_error = true;
+                        self.error_msg = err;
                     }
-                    if ui
-                        .button("Save")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        save_file(tab);
+                },
+                Command::Undo => self.undo(),
+                Command::Redo => self.redo(),
+                Command::Cut => {
+                    let start_idx = self.cursor.start.min(self.cursor.end);
+                    let end_idx = self.cursor.start.max(self.cursor.end);
+                    if start_idx == end_idx {
+                        return;
                     }
-                    if ui
-                        .button("Save As")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let file = FileDialog::new()
-                            .add_filter("Text documents", &["txt"])
-                            .add_filter("Rust Source", &["rs"])
-                            .add_filter("Python Source", &["py"])
-                            .save_file();
-                        if let Some(file) = file {
-                            tab.file_path = file.as_path().to_str().unwrap().to_string();
-                            tab.title = file.file_name().unwrap().to_str().unwrap().to_string();
-                            tab.language = file.extension().unwrap().to_str().unwrap().to_string();
-                            fs::write(file.clone(), tab.text.clone()).unwrap();
-                        }
+                    ctx.copy_text(self.text.slice_chars(start_idx..end_idx).to_string());
+                    self.text.replace_chars(start_idx..end_idx, "");
+                    self.commit_edit();
+                    self.pending_cursor = Some(start_idx..start_idx);
+                }
+                Command::FindNext => {
+                    // The text area shows the selection only once it has focus again
+                    editor
+                        .state_manager
+                        .set_find_state(FindBarState::NotFocused);
+                    // Start after the current match so that repeating the command moves on
+                    let end_idx = editor.cursor_index_manager.get_end_idx();
+                    editor.cursor_index_manager.set_start_idx(end_idx);
+                    let result = editor.find_next(self).map(|range| self.select(range));
+                    self.report_error(result);
+                }
+                Command::FindPrevious => {
+                    editor
+                        .state_manager
+                        .set_find_state(FindBarState::NotFocused);
+                    let result = editor.find_prev(self).map(|range| self.select(range));
+                    self.report_error(result);
+                }
+                Command::SelectAll => {
+                    let char_count = self.text.len_chars();
+                    editor.cursor_index_manager.set_start_idx(0);
+                    editor.cursor_index_manager.set_end_idx(char_count);
+                    self.select(0..char_count);
+                }
+                _ => {}
+            }
+        }
+
+        /// Runs the tab's part of a command with the option picked for it, see
+        /// [`TextEditor::run_choice`]
+        pub fn run_choice(
+            &mut self,
+            editor: &mut TextEditor,
+            command: Command,
+            option: CommandOption,
+        ) {
+            match (command, option) {
+                (Command::ReopenWithEncoding, CommandOption::Encoding(encoding)) => {
+                    let file_path = self.file_path.clone();
+                    let result = self.open_file(Path::new(&file_path), Some(encoding));
+                    self.report_error(result);
+                }
+                (Command::SaveWithEncoding, CommandOption::Encoding(encoding)) => {
+                    let previous = (self.encoding, self.has_bom);
+                    self.encoding = encoding;
+                    self.has_bom = encoding == UTF_16LE || encoding == UTF_16BE;
+                    if !editor.save_tab(self) {
+                        (self.encoding, self.has_bom) = previous;
                     }
-                    if ui
-                        .button("Save all")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let tabs = unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .dock_state
-                                .main_surface_mut()
-                                .root_node_mut()
-                                .unwrap()
-                                .tabs_mut()
-                                .unwrap()
+                    self.update_status();
+                }
+                (_, CommandOption::LineEnding(line_ending)) => self.set_line_ending(line_ending),
+                _ => {}
+            }
+        }
+
+        /// Records a programmatic change to `text` as its own undo step
+        fn commit_edit(&mut self) {
+            self.history.commit(self.text.take_changes(), false);
+            self.dirty = true;
+        }
+    }
+
+    /// Draws the tabs of the dock area, with access to the rest of the editor
+    pub struct MyTabViewer<'a> {
+        pub editor: &'a mut TextEditor,
+    }
+
+    impl MyTabViewer<'_> {
+        /// Adds a menu button for `command` that shows its key binding and runs it on `tab`
+        fn command_button(
+            &mut self,
+            ui: &mut egui::Ui,
+            tab: &mut TextEditorTab,
+            command: Command,
+            is_enabled: bool,
+        ) {
+            let shortcut = self.editor.keymap.get_shortcut_text(command, ui.ctx());
+            let button = Button::new(get_command_info(command).title).shortcut_text(shortcut);
+            if ui
+                .add_enabled(is_enabled, button)
+                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                .clicked()
+            {
+                ui.close_menu();
+                self.editor.run_command(command, Some(tab), ui.ctx());
+            }
+        }
+
+        /// Adds a submenu listing the options of a command that has choices, followed by
+        /// `extra_commands`
+        fn choice_menu(
+            &mut self,
+            ui: &mut egui::Ui,
+            tab: &mut TextEditorTab,
+            command: Command,
+            extra_commands: &[Command],
+        ) {
+            let choices = self.editor.get_choices(command, Some(tab));
+            // Submenu buttons can't show shortcut text, so the binding goes in the title
+            let title = get_command_info(command).title;
+            let shortcut = self.editor.keymap.get_shortcut_text(command, ui.ctx());
+            let title = if shortcut.is_empty() {
+                title.to_string()
+            } else {
+                format!("{} ({})", title, shortcut)
+            };
+            ui.add_enabled_ui(!choices.is_empty(), |ui| {
+                ui.menu_button(title, |ui| {
+                    for choice in choices {
+                        let response = match choice.is_current {
+                            Some(is_current) => ui.radio(is_current, &choice.label),
+                            None => ui.button(&choice.label),
                         };
-                        for tab in tabs {
-                            save_file(tab);
+                        if response
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            ui.close_menu();
+                            self.editor.run_choice(command, choice.option, Some(tab));
                         }
                     }
-                    if ui
-                        .button("Close tab")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let node = unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .dock_state
-                                .main_surface_mut()
-                                .root_node_mut()
-                                .unwrap()
-                        };
-                        let tab_index = node
-                            .tabs()
-                            .unwrap()
-                            .iter()
-                            .position(|t| t.id == tab.id)
-                            .unwrap();
-                        node.remove_tab(TabIndex(tab_index));
-                    }
-                    if ui
-                        .button("Close window")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        std::process::exit(0);
+                    if !extra_commands.is_empty() {
+                        ui.separator();
+                        for &extra_command in extra_commands {
+                            self.command_button(ui, tab, extra_command, true);
+                        }
                     }
+                });
+            });
+        }
+    }
+
+    impl TabViewer for MyTabViewer<'_> {
+        type Tab = TextEditorTab;
+
+        fn on_add(&mut self, _surface: egui_dock::SurfaceIndex, _node: egui_dock::NodeIndex) {
+            self.editor
+                .open_tab(TextEditorTab::new("Untitled".into(), "".into()));
+        }
+
+        fn closeable(&mut self, _tab: &mut Self::Tab) -> bool {
+            _tab.title != "+"
+        }
+
+        fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
+            if tab.dirty {
+                format!("{} ●", tab.title).into()
+            } else {
+                tab.title.clone().into()
+            }
+        }
+
+        fn id(&mut self, tab: &mut Self::Tab) -> egui::Id {
+            // Titles change with the dirty marker and repeat for untitled tabs
+            egui::Id::new(tab.id)
+        }
+
+        fn on_close(&mut self, tab: &mut Self::Tab) -> bool {
+            if tab.dirty {
+                self.editor
+                    .state_manager
+                    .set_unsaved_changes_action(Some(UnsavedChangesAction::CloseTab(tab.id)));
+                return false;
+            }
+            self.editor.remember_closed_tab(tab);
+            true
+        }
+
+        fn force_close(&mut self, tab: &mut Self::Tab) -> bool {
+            if tab.is_closing {
+                self.editor.remember_closed_tab(tab);
+            }
+            tab.is_closing
+        }
+
+        fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
+            ui.horizontal(|ui| {
+                ui.menu_button("File", |ui| {
+                    self.command_button(ui, tab, Command::NewTab, true);
+                    self.command_button(ui, tab, Command::Open, true);
+                    self.choice_menu(ui, tab, Command::OpenRecent, &[Command::ClearRecentFiles]);
+                    self.choice_menu(ui, tab, Command::ReopenWithEncoding, &[]);
+                    self.command_button(ui, tab, Command::Save, true);
+                    self.command_button(ui, tab, Command::SaveAs, true);
+                    self.choice_menu(ui, tab, Command::SaveWithEncoding, &[]);
+                    self.command_button(ui, tab, Command::SaveAll, true);
+                    self.choice_menu(ui, tab, Command::SetBackupMode, &[]);
+                    self.command_button(ui, tab, Command::CloseTab, true);
+                    let can_reopen_closed_tab = self.editor.can_reopen_closed_tab();
+                    self.command_button(ui, tab, Command::ReopenClosedTab, can_reopen_closed_tab);
+                    self.command_button(ui, tab, Command::CloseWindow, true);
                 })
                 .response
                 .on_hover_cursor(egui::CursorIcon::PointingHand);
 
                 ui.menu_button("Edit", |ui| {
-                    if ui
-                        .button("Cut")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let start_idx = unsafe {
-                            TEXT_EDITOR
-                                .get()
-                                .unwrap()
-                                .cursor_index_manager
-                                .get_start_idx()
-                        };
-                        let end_idx = unsafe {
-                            TEXT_EDITOR
-                                .get()
-                                .unwrap()
-                                .cursor_index_manager
-                                .get_end_idx()
-                        };
-                        ui.ctx().copy_text(tab.text[start_idx..end_idx].to_string());
-                        tab.text.delete_char_range(Range {
-                            start: start_idx,
-                            end: end_idx,
-                        });
-                    }
+                    let (can_undo, can_redo) = (tab.history.can_undo(), tab.history.can_redo());
+                    self.command_button(ui, tab, Command::Undo, can_undo);
+                    self.command_button(ui, tab, Command::Redo, can_redo);
+                    self.command_button(ui, tab, Command::Cut, true);
+                    ui.separator();
+                    self.command_button(ui, tab, Command::Find, true);
+                    self.command_button(ui, tab, Command::FindNext, true);
+                    self.command_button(ui, tab, Command::FindPrevious, true);
+                    self.command_button(ui, tab, Command::Replace, true);
+                    self.command_button(ui, tab, Command::ReplaceAll, true);
+                    self.command_button(ui, tab, Command::GoTo, true);
+                    ui.separator();
 
-                    if ui
-                        .button("Find previous")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let text = tab.text.clone();
-                        let find_str = unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .state_manager
-                                .get_find_val()
-                                .clone()
-                        };
-                        let curr_start_idx = unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .cursor_index_manager
-                                .get_start_idx()
-                        };
-                        let prev_word_idx = text[0..curr_start_idx].rfind(&find_str);
-                        if let Some(idx) = prev_word_idx {
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .cursor_index_manager
-                                    .set_start_idx(idx)
-                            };
-                        } else if let Some(idx) = text.rfind(&find_str) {
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .cursor_index_manager
-                                    .set_start_idx(idx)
-                            };
-                        }
-                        tab.is_refreshed = true;
-                    }
-
-                    if ui
-                        .button("Go to")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .state_manager
-                                .set_is_goto_open(true)
-                        };
-                        unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .state_manager
-                                .set_goto_state(GoToState::Focused)
-                        };
-                    }
-
-                    if ui
-                        .button("Select all")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        ui.close_menu();
-                        let text = tab.text.clone();
-                        let len = text.len();
-                        unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .cursor_index_manager
-                                .set_start_idx(0)
-                        };
-                        unsafe {
-                            TEXT_EDITOR
-                                .get_mut()
-                                .unwrap()
-                                .cursor_index_manager
-                                .set_end_idx(len)
-                        };
-                        tab.is_refreshed = true;
-                    }
+                    self.choice_menu(ui, tab, Command::ConvertLineEndings, &[]);
+                    self.command_button(ui, tab, Command::SelectAll, true);
                 })
                 .response
                 .on_hover_cursor(egui::CursorIcon::PointingHand);
 
                 ui.menu_button("View", |ui| {
-                    if ui
-                        .button("Toggle Fullscreen")
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
-                        println!("Fullscreen toggled");
-                    }
+                    self.command_button(ui, tab, Command::ShowCommandPalette, true);
+                    self.command_button(ui, tab, Command::ToggleFullscreen, true);
+                    self.choice_menu(ui, tab, Command::SelectTheme, &[Command::ReloadThemes]);
                 })
                 .response
                 .on_hover_cursor(egui::CursorIcon::PointingHand);
             });
 
-            let theme = egui_extras::syntax_highlighting::CodeTheme::from_memory(ui.ctx());
-
-            let mut layouter = |ui: &egui::Ui, string: &str, wrap_width: f32| {
-                let mut layout_job = egui_extras::syntax_highlighting::highlight(
-                    ui.ctx(),
-                    &theme,
-                    string,
-                    &tab.language,
-                );
-                layout_job.wrap.max_width = wrap_width;
-                ui.fonts(|f| {
-                    unsafe { TEXT_EDITOR.get_mut().unwrap().row_size = layout_job.font_height(f) };
-                    f.layout_job(layout_job)
-                })
-            };
-
             let _find_window = Window::new("")
                 .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 72.0)) // to change this magic value
                 .fixed_size(Vec2::new(ui.available_width() / 2.0, 16.0))
                 .title_bar(false)
-                .open(
-                    &mut unsafe { TEXT_EDITOR.get_mut().unwrap() }
-                        .state_manager
-                        .get_is_find_open(),
-                )
+                .open(&mut self.editor.state_manager.get_is_find_open())
                 .show(ui.ctx(), |ui| {
                     let ui_visuals = ui.visuals_mut();
                     ui_visuals.selection.stroke = egui::Stroke {
@@ -414,72 +874,60 @@ pub mod tab {
 
                     ui.horizontal(|ui| {
                         let find_bar_response = ui.add(
-                            egui::TextEdit::singleline(
-                                &mut unsafe { TEXT_EDITOR.get_mut().unwrap() }
-                                    .state_manager
-                                    .find_val,
-                            )
-                            .hint_text("Find")
-                            .desired_width(ui.available_width() - 32.0),
+                            egui::TextEdit::singleline(&mut self.editor.state_manager.find_val)
+                                .hint_text("Find")
+                                .desired_width(ui.available_width() - 32.0 - 3.0 * 24.0 - 64.0),
                         );
-                        if let FindBarState::Focused =
-                            unsafe { &TEXT_EDITOR.get().unwrap().state_manager.get_find_state() }
-                        {
+
+                        let match_counter = self.editor.get_match_counter(tab);
+                        ui.add_sized(Vec2::new(64.0, 16.0), egui::Label::new(match_counter));
+                        if let FindBarState::Focused = self.editor.state_manager.get_find_state() {
                             find_bar_response.request_focus();
                         }
 
+                        let state_manager = &mut self.editor.state_manager;
                         if find_bar_response.gained_focus() {
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_find_state(FindBarState::Focused)
-                            };
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_replace_state(ReplaceBarState::NotFocused)
-                            };
+                            state_manager.set_find_state(FindBarState::Focused);
+                            state_manager.set_replace_state(ReplaceBarState::NotFocused);
                             tab.is_finding = true;
                         } else if find_bar_response.clicked_elsewhere() {
                             find_bar_response.surrender_focus();
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_find_state(FindBarState::NotFocused)
-                            };
+                            state_manager.set_find_state(FindBarState::NotFocused);
                             tab.is_finding = false;
                         }
 
-                        if ui.input(|i| i.key_pressed(Key::Enter)) {
-                            if tab.is_finding && !tab.is_replacing {
-                                let find_state = unsafe {
-                                    TEXT_EDITOR
-                                        .get_mut()
-                                        .unwrap()
-                                        .state_manager
-                                        .get_find_state()
-                                };
-                                match find_state {
-                                    FindBarState::Focused => {
-                                        find_bar_response.surrender_focus();
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .state_manager
-                                                .set_find_state(FindBarState::Finding)
-                                        };
-                                    }
-                                    _ => {}
-                                }
+                        if ui.input(|i| i.key_pressed(Key::Enter))
+                            && tab.is_finding
+                            && !tab.is_replacing
+                            && matches!(state_manager.get_find_state(), FindBarState::Focused)
+                        {
+                            find_bar_response.surrender_focus();
+                            state_manager.set_find_state(FindBarState::Finding);
+                        }
+
+                        let mut search_options = state_manager.get_search_options();
+                        let toggles = [
+                            (&mut search_options.match_case, "Aa", "Match case"),
+                            (&mut search_options.whole_word, "ab", "Match whole word"),
+                            (
+                                &mut search_options.use_regex,
+                                ".*",
+                                "Use regular expression",
+                            ),
+                        ];
+                        for (value, label, hover_text) in toggles {
+                            let toggle = ui
+                                .add_sized(
+                                    Vec2::new(24.0, 16.0),
+                                    egui::SelectableLabel::new(*value, label),
+                                )
+                                .on_hover_text(hover_text)
+                                .on_hover_cursor(egui::CursorIcon::PointingHand);
+                            if toggle.clicked() {
+                                *value = !*value;
                             }
                         }
+                        state_manager.set_search_options(search_options);
 
                         let find_button =
                             ImageButton::new(egui::include_image!("../assets/find.png"));
@@ -518,34 +966,11 @@ pub mod tab {
                             .on_hover_cursor(egui::CursorIcon::PointingHand)
                             .clicked()
                         {
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_is_find_open(false)
-                            };
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_find_val("".into())
-                            };
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_is_replace_open(false)
-                            };
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_replace_val("".into())
-                            };
+                            let state_manager = &mut self.editor.state_manager;
+                            state_manager.set_is_find_open(false);
+                            state_manager.set_find_val("".into());
+                            state_manager.set_is_replace_open(false);
+                            state_manager.set_replace_val("".into());
                         }
                     });
                 });
@@ -554,11 +979,7 @@ pub mod tab {
                 .anchor(Align2::CENTER_TOP, Vec2::new(35.0, 108.0)) // to change this magic value
                 .fixed_size(Vec2::new(ui.available_width() / 2.0, 16.0))
                 .title_bar(false)
-                .open(
-                    &mut unsafe { TEXT_EDITOR.get_mut().unwrap() }
-                        .state_manager
-                        .get_is_replace_open(),
-                )
+                .open(&mut self.editor.state_manager.get_is_replace_open())
                 .show(ui.ctx(), |ui| {
                     let ui_visuals = ui.visuals_mut();
                     ui_visuals.selection.stroke = egui::Stroke {
@@ -574,440 +995,239 @@ pub mod tab {
 
                     ui.horizontal(|ui| {
                         let replace_bar_response = ui.add(
-                            egui::TextEdit::singleline(
-                                &mut unsafe { TEXT_EDITOR.get_mut().unwrap() }
-                                    .state_manager
-                                    .replace_val,
-                            )
-                            .hint_text("Replace")
-                            .desired_width(ui.available_width()),
+                            egui::TextEdit::singleline(&mut self.editor.state_manager.replace_val)
+                                .hint_text("Replace")
+                                .desired_width(ui.available_width()),
                         );
-                        if let ReplaceBarState::Focused =
-                            unsafe { &TEXT_EDITOR.get().unwrap().state_manager.get_replace_state() }
-                        {
+                        let state_manager = &mut self.editor.state_manager;
+                        if let ReplaceBarState::Focused = state_manager.get_replace_state() {
                             replace_bar_response.request_focus();
                         }
                         if replace_bar_response.gained_focus() {
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_find_state(FindBarState::NotFocused)
-                            };
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_replace_state(ReplaceBarState::Focused)
-                            };
+                            state_manager.set_find_state(FindBarState::NotFocused);
+                            state_manager.set_replace_state(ReplaceBarState::Focused);
                             tab.is_replacing = true;
                         } else if replace_bar_response.clicked_elsewhere() {
                             replace_bar_response.surrender_focus();
-                            unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .set_replace_state(ReplaceBarState::NotFocused)
-                            };
+                            state_manager.set_replace_state(ReplaceBarState::NotFocused);
                             tab.is_replacing = false;
                         }
 
-                        if ui.input(|i| i.key_pressed(Key::Enter)) {
-                            let replace_state = unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_replace_state()
-                            };
-
-                            if tab.is_replacing {
-                                match replace_state {
-                                    ReplaceBarState::Focused => {
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .state_manager
-                                                .set_replace_state(ReplaceBarState::Replacing)
-                                        };
-                                    }
-                                    _ => {}
-                                }
-                            }
+                        if ui.input(|i| i.key_pressed(Key::Enter))
+                            && tab.is_replacing
+                            && matches!(state_manager.get_replace_state(), ReplaceBarState::Focused)
+                        {
+                            state_manager.set_replace_state(ReplaceBarState::Replacing);
+                            state_manager.set_is_replace_active(true);
                         }
 
                         let replace_all_button = ui
                             .add(Button::new("Replace All"))
                             .on_hover_cursor(egui::CursorIcon::PointingHand);
                         if replace_all_button.clicked() {
-                            let find_str = unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_find_val()
-                                    .clone()
-                            };
-                            let replace_str = unsafe {
-                                TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_replace_val()
-                                    .clone()
-                            };
-                            let text = tab.text.clone();
-                            let new_text = text.replace(&find_str, &replace_str);
-                            tab.text = new_text;
+                            tab.run_command(self.editor, Command::ReplaceAll, ui.ctx());
                         }
                     });
                 });
 
-            /* let goto_window = Window::new("")
-            .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 96.0)) // to change this magic value
-            .fixed_size(Vec2::new(ui.available_width() / 2.0, 16.0))
-            .title_bar(false)
-            .open(&mut unsafe { TEXT_EDITOR.get_mut().unwrap() }.state_manager.get_is_goto_open())
-            .show(ui.ctx(), |ui| {
+            if let Some(replacements) = &tab.replace_preview {
+                let mut is_confirmed = false;
+                let mut is_cancelled = false;
+                Window::new("Replace All")
+                    .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                    .collapsible(false)
+                    .resizable(false)
+                    .min_width(400.0)
+                    .show(ui.ctx(), |ui| {
+                        if replacements.is_empty() {
+                            ui.label("Nothing to replace");
+                        } else {
+                            ui.label(format!("{} replacement(s):", replacements.len()));
+                        }
+                        ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                            for replacement in replacements {
+                                ui.label(
+                                    RichText::new(format!(
+                                        "Ln {}: {:?} -> {:?}",
+                                        replacement.line + 1,
+                                        replacement.found,
+                                        replacement.replace_with
+                                    ))
+                                    .monospace(),
+                                );
+                            }
+                        });
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                            if ui
+                                .button("Cancel")
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                is_cancelled = true;
+                            }
+                            if ui
+                                .add_enabled(!replacements.is_empty(), Button::new("Replace All"))
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                is_confirmed = true;
+                            }
+                        });
+                    });
 
-            }); */
+                if is_confirmed {
+                    let replacements = tab.replace_preview.take().unwrap();
+                    tab.apply_replacements(&replacements);
+                } else if is_cancelled {
+                    tab.replace_preview = None;
+                }
+            }
 
-            ScrollArea::both().show(ui, |ui| {
-                let ui_visuals = ui.visuals_mut();
-                ui_visuals.selection.stroke = egui::Stroke {
-                    width: 2.0,
-                    color: Color32::TRANSPARENT,
-                };
-                ui_visuals.widgets.hovered.bg_stroke = egui::Stroke {
-                    width: 2.0,
-                    color: Color32::TRANSPARENT,
-                };
+            let _goto_window = Window::new("  ")
+                .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 96.0)) // to change this magic value
+                .fixed_size(Vec2::new(ui.available_width() / 2.0, 16.0))
+                .title_bar(false)
+                .open(&mut self.editor.state_manager.get_is_goto_open())
+                .show(ui.ctx(), |ui| {
+                    let ui_visuals = ui.visuals_mut();
+                    ui_visuals.selection.stroke = egui::Stroke {
+                        width: 0.0,
+                        color: Color32::TRANSPARENT,
+                    };
+                    ui_visuals.widgets.hovered.bg_stroke = egui::Stroke {
+                        width: 0.0,
+                        color: Color32::TRANSPARENT,
+                    };
 
-                let mut text = egui::TextEdit::multiline(&mut tab.text)
-                    .code_editor()
-                    .layouter(&mut layouter)
-                    .min_size(ui.available_size())
-                    .desired_width(ui.available_width())
-                    .cursor_at_end(false)
-                    .show(ui);
+                    ui.style_mut().spacing.item_spacing = Vec2::new(0.0, 8.0);
+
+                    ui.horizontal(|ui| {
+                        let goto_bar_response = ui.add(
+                            egui::TextEdit::singleline(&mut self.editor.state_manager.goto_val)
+                                .hint_text("Go to line, line:column, +N or -N")
+                                .desired_width(ui.available_width() - 16.0),
+                        );
+                        // Check before taking focus back, which would hide that Enter released it
+                        if goto_bar_response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter))
+                        {
+                            self.editor.state_manager.set_goto_state(GoToState::GoingTo);
+                        } else if let GoToState::Focused =
+                            self.editor.state_manager.get_goto_state()
+                        {
+                            goto_bar_response.request_focus();
+                        }
+
+                        let close_button =
+                            ImageButton::new(egui::include_image!("../assets/close.png"));
+                        ui.style_mut().spacing.button_padding = Vec2::new(-1.0, -1.0);
+
+                        if close_button
+                            .ui(ui)
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                            || ui.input(|i| i.key_pressed(Key::Escape))
+                        {
+                            self.editor.close_goto();
+                        }
+                    });
+                });
+
+            let restored_scroll = tab.pending_scroll.take();
+            let mut scroll_area = ScrollArea::both();
+            if let Some(offset) = restored_scroll {
+                scroll_area = scroll_area.scroll_offset(offset);
+            }
+            tab.highlighter.update(&mut tab.text, &tab.language);
+            let syntax_theme = self.editor.themes.get_active().syntax.clone();
+            let scroll_output = scroll_area.show(ui, |ui| {
+                let matcher = if self.editor.state_manager.get_is_find_open() {
+                    self.editor.state_manager.get_matcher().ok()
+                } else {
+                    None
+                };
+                let mut view = CodeView::new(
+                    egui::Id::new(("code_view", tab.id)),
+                    &mut tab.text,
+                    &mut tab.line_cache,
+                )
+                .highlighter(&tab.highlighter, &syntax_theme)
+                .matcher(matcher.as_ref());
+                if let Some(range) = tab.pending_cursor.take() {
+                    // A restored session brings its own scroll position
+                    view = view.select(range, restored_scroll.is_none());
+                }
+                let text = view.show(ui);
 
                 if tab.is_refreshed {
                     tab.is_refreshed = false;
                     text.response.request_focus();
                 }
 
-                let mut crange;
-                if let Some(r) = text.cursor_range {
-                    crange = Some(r);
-                } else {
-                    crange = None;
+                if text.selection != tab.cursor {
+                    tab.cursor = text.selection.clone();
+                    tab.update_status();
                 }
+                let has_focus = text.response.has_focus();
 
-                let find_bar_state = unsafe {
-                    &TEXT_EDITOR
-                        .get_mut()
-                        .unwrap()
-                        .state_manager
-                        .get_find_state()
+                if let GoToState::GoingTo = self.editor.state_manager.get_goto_state() {
+                    let current_line = tab.text.char_to_line(text.cursor) + 1;
+                    match self.editor.go_to(tab, current_line) {
+                        Ok(_) => ui.ctx().request_repaint(),
+                        Err(err) => {
+                            tab.has_error = true;
+                            tab.error_msg = err;
+                            self.editor.state_manager.set_goto_state(GoToState::Focused);
+                        }
+                    }
+                }
+
+                // Remembers a selection made with the mouse or keyboard as the find position
+                let selection = text.selection.clone();
+                let remember_selection = |editor: &mut TextEditor| {
+                    if has_focus && !selection.is_empty() {
+                        let cursor_index_manager = &mut editor.cursor_index_manager;
+                        cursor_index_manager.set_start_idx(selection.start);
+                        cursor_index_manager.set_end_idx(selection.end);
+                    }
                 };
 
-                match find_bar_state {
+                match self.editor.state_manager.get_find_state() {
                     FindBarState::Finding => {
                         text.response.request_focus();
-                        if crange.is_some() {
-                            let curr_start_idx = unsafe {
-                                TEXT_EDITOR
-                                    .get()
-                                    .unwrap()
-                                    .cursor_index_manager
-                                    .get_start_idx()
-                            };
-                            let text_str = tab.text.clone();
-                            let find_str = unsafe {
-                                TEXT_EDITOR
-                                    .get()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_find_val()
-                                    .clone()
-                            };
-                            let find_result = get_next_word_idx(
-                                &text_str,
-                                unsafe {
-                                    TEXT_EDITOR
-                                        .get()
-                                        .unwrap()
-                                        .state_manager
-                                        .get_find_val()
-                                        .clone()
-                                },
-                                curr_start_idx,
-                                ui.available_width() as usize,
-                            );
-                            if find_result.is_some() {
-                                let next_word_idx = find_result.unwrap();
-                                let mut new_range =
-                                    CursorRange::one(text.cursor_range.clone().unwrap().primary);
-                                new_range.primary.ccursor.index = next_word_idx.0 + find_str.len();
-                                new_range.primary.rcursor.row = next_word_idx.1;
-                                new_range.primary.rcursor.column = next_word_idx.2 + find_str.len();
-                                new_range.primary.pcursor.paragraph = next_word_idx.3;
-                                new_range.primary.pcursor.offset = next_word_idx.4 + find_str.len();
-                                new_range.secondary.ccursor.index = next_word_idx.0;
-                                new_range.secondary.rcursor.row = next_word_idx.1;
-                                new_range.secondary.rcursor.column = next_word_idx.2;
-                                new_range.secondary.pcursor.paragraph = next_word_idx.3;
-                                new_range.secondary.pcursor.offset = next_word_idx.4;
-                                let mut crange = text.cursor_range.unwrap();
-                                crange.primary = new_range.primary;
-                                crange.secondary = new_range.secondary;
-                                text.cursor_range = Some(crange);
-                                text.state.cursor.set_range(Some(crange));
-                                text.state.store(ui.ctx(), text.response.id);
-                                unsafe {
-                                    TEXT_EDITOR
-                                        .get_mut()
-                                        .unwrap()
-                                        .cursor_index_manager
-                                        .set_start_idx(next_word_idx.0)
-                                };
-                                unsafe {
-                                    TEXT_EDITOR
-                                        .get_mut()
-                                        .unwrap()
-                                        .cursor_index_manager
-                                        .set_end_idx(next_word_idx.0 + find_str.len())
-                                };
-                                let row_height = unsafe { TEXT_EDITOR.get().unwrap().row_size };
-
-                                let crect = cursor_rect(
-                                    text.galley_pos,
-                                    &text.galley,
-                                    &text.cursor_range.unwrap().primary,
-                                    row_height,
-                                );
-                                ui.scroll_to_rect(crect, None);
-
-                                paint_text_selection(
-                                    ui.painter(),
-                                    ui.visuals(),
-                                    text.galley_pos,
-                                    &text.galley,
-                                    &text.cursor_range.unwrap(),
-                                    None,
-                                );
-                            } else {
-                                tab.has_error = true;
-                                tab.error_msg = format!("Cannot find \"{}\"", find_str).into();
+                        if has_focus {
+                            match self.editor.find_next(tab) {
+                                Ok(next_word_range) => {
+                                    if next_word_range != tab.cursor {
+                                        tab.select(next_word_range);
+                                        ui.ctx().request_repaint();
+                                    }
+                                }
+                                Err(err) => {
+                                    tab.has_error = true;
+                                    tab.error_msg = err;
+                                }
                             }
                         }
                     }
                     FindBarState::NotFocused => {
-                        if !unsafe {
-                            TEXT_EDITOR
-                                .get()
-                                .unwrap()
-                                .state_manager
-                                .get_is_replace_open()
-                        } {
-                            text.response.request_focus();
-                            if crange.is_some() {
-                                let primary_idx = crange.unwrap().primary.ccursor.index;
-                                let secondary_idx = crange.unwrap().secondary.ccursor.index;
-                                if primary_idx != secondary_idx {
-                                    unsafe {
-                                        TEXT_EDITOR
-                                            .get_mut()
-                                            .unwrap()
-                                            .cursor_index_manager
-                                            .set_start_idx(min(primary_idx, secondary_idx))
-                                    };
-                                    unsafe {
-                                        TEXT_EDITOR
-                                            .get_mut()
-                                            .unwrap()
-                                            .cursor_index_manager
-                                            .set_end_idx(max(primary_idx, secondary_idx))
-                                    };
-                                }
+                        if !self.editor.state_manager.get_is_replace_open() {
+                            if !self.editor.state_manager.get_is_goto_open() {
+                                text.response.request_focus();
                             }
+                            remember_selection(self.editor);
                         } else {
-                            let replace_bar_state = unsafe {
-                                &TEXT_EDITOR
-                                    .get_mut()
-                                    .unwrap()
-                                    .state_manager
-                                    .get_replace_state()
-                            };
-
-                            match replace_bar_state {
+                            match self.editor.state_manager.get_replace_state() {
                                 ReplaceBarState::Replacing => {
                                     text.response.request_focus();
-                                    if let Some(r) = text.cursor_range {
-                                        crange = Some(r);
-                                    } else {
-                                        crange = None;
-                                    }
-                                    if crange.is_some() {
-                                        let curr_start_idx = unsafe {
-                                            TEXT_EDITOR
-                                                .get()
-                                                .unwrap()
-                                                .cursor_index_manager
-                                                .get_start_idx()
-                                        };
-                                        if curr_start_idx == 0 {
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get_mut()
-                                                    .unwrap()
-                                                    .state_manager
-                                                    .set_is_replace_active(true)
-                                            };
-                                        }
-                                        if !unsafe {
-                                            TEXT_EDITOR
-                                                .get()
-                                                .unwrap()
-                                                .state_manager
-                                                .get_is_replace_active()
-                                        } {
-                                            return;
-                                        }
-                                        let find_str = unsafe {
-                                            TEXT_EDITOR
-                                                .get()
-                                                .unwrap()
-                                                .state_manager
-                                                .get_find_val()
-                                                .clone()
-                                        };
-                                        let replace_str = unsafe {
-                                            TEXT_EDITOR
-                                                .get()
-                                                .unwrap()
-                                                .state_manager
-                                                .get_replace_val()
-                                                .clone()
-                                        };
-                                        if curr_start_idx > 0 {
-                                            let before = tab.text
-                                                [0..curr_start_idx - 1 - find_str.len()]
-                                                .to_string();
-                                            let after = tab.text[curr_start_idx - 1..].to_string();
-                                            tab.text = before + &replace_str + &after;
-                                        }
-                                        let text_str = tab.text.clone();
-                                        let find_result = get_next_word_idx(
-                                            &text_str,
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get()
-                                                    .unwrap()
-                                                    .state_manager
-                                                    .get_find_val()
-                                                    .clone()
-                                            },
-                                            curr_start_idx,
-                                            ui.available_width() as usize,
-                                        );
-                                        if find_result.is_some() {
-                                            let next_word_idx = find_result.unwrap();
-                                            let mut new_range = CursorRange::one(
-                                                text.cursor_range.clone().unwrap().primary,
-                                            );
-                                            new_range.primary.ccursor.index =
-                                                next_word_idx.0 + find_str.len();
-                                            new_range.primary.rcursor.row = next_word_idx.1;
-                                            new_range.primary.rcursor.column =
-                                                next_word_idx.2 + find_str.len();
-                                            new_range.primary.pcursor.paragraph = next_word_idx.3;
-                                            new_range.primary.pcursor.offset =
-                                                next_word_idx.4 + find_str.len();
-                                            new_range.secondary.ccursor.index = next_word_idx.0;
-                                            new_range.secondary.rcursor.row = next_word_idx.1;
-                                            new_range.secondary.rcursor.column = next_word_idx.2;
-                                            new_range.secondary.pcursor.paragraph = next_word_idx.3;
-                                            new_range.secondary.pcursor.offset = next_word_idx.4;
-                                            let mut crange = text.cursor_range.unwrap();
-                                            crange.primary = new_range.primary;
-                                            crange.secondary = new_range.secondary;
-                                            text.cursor_range = Some(crange);
-                                            text.state.cursor.set_range(Some(crange));
-                                            text.state.store(ui.ctx(), text.response.id);
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get_mut()
-                                                    .unwrap()
-                                                    .cursor_index_manager
-                                                    .set_start_idx(next_word_idx.0)
-                                            };
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get_mut()
-                                                    .unwrap()
-                                                    .cursor_index_manager
-                                                    .set_end_idx(next_word_idx.0 + find_str.len())
-                                            };
-                                            let row_height =
-                                                unsafe { TEXT_EDITOR.get().unwrap().row_size };
-
-                                            let crect = cursor_rect(
-                                                text.galley_pos,
-                                                &text.galley,
-                                                &text.cursor_range.unwrap().primary,
-                                                row_height,
-                                            );
-                                            ui.scroll_to_rect(crect, None);
-
-                                            paint_text_selection(
-                                                ui.painter(),
-                                                ui.visuals(),
-                                                text.galley_pos,
-                                                &text.galley,
-                                                &text.cursor_range.unwrap(),
-                                                None,
-                                            );
-                                        } else {
-                                            tab.has_error = true;
-                                            tab.error_msg =
-                                                format!("Cannot find \"{}\"", find_str).into();
-                                        }
-                                        unsafe {
-                                            TEXT_EDITOR
-                                                .get_mut()
-                                                .unwrap()
-                                                .state_manager
-                                                .set_is_replace_active(false)
-                                        };
+                                    if self.editor.state_manager.get_is_replace_active() {
+                                        self.editor.state_manager.set_is_replace_active(false);
+                                        let result = self.editor.replace_next(tab).map(|_| ());
+                                        tab.report_error(result);
                                     }
                                 }
                                 ReplaceBarState::NotFocused => {
                                     text.response.request_focus();
-                                    if crange.is_some() {
-                                        let primary_idx = crange.unwrap().primary.ccursor.index;
-                                        let secondary_idx = crange.unwrap().secondary.ccursor.index;
-                                        if primary_idx != secondary_idx {
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get_mut()
-                                                    .unwrap()
-                                                    .cursor_index_manager
-                                                    .set_start_idx(min(primary_idx, secondary_idx))
-                                            };
-                                            unsafe {
-                                                TEXT_EDITOR
-                                                    .get_mut()
-                                                    .unwrap()
-                                                    .cursor_index_manager
-                                                    .set_end_idx(max(primary_idx, secondary_idx))
-                                            };
-                                        }
-                                    }
+                                    remember_selection(self.editor);
                                 }
                                 _ => {}
                             }
@@ -1018,17 +1238,16 @@ pub mod tab {
 
                 if text.response.changed() {
                     tab.dirty = true;
+                    tab.history.commit(tab.text.take_changes(), true);
                 }
             });
+            tab.scroll_offset = scroll_output.state.offset;
+
+            external_change_dialog(ui, tab);
 
             if tab.has_error {
-                let text_color;
-                if theme == CodeTheme::dark() {
-                    text_color = Color32::WHITE;
-                } else {
-                    text_color = Color32::BLACK;
-                }
-                let _error_window = Window::new(RichText::new("Reditor").color(text_color))
+                let text_color = ui.visuals().strong_text_color();
+                Window::new(RichText::new("Reditor").color(text_color))
                     .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
                     .collapsible(false)
                     .resizable(false)
@@ -1060,4 +1279,264 @@ pub mod tab {
             }
         }
     }
+
+    /// Asks what to do about a dirty tab whose file another program changed or removed
+    fn external_change_dialog(ui: &mut egui::Ui, tab: &mut TextEditorTab) {
+        let Some(external_change) = tab.external_change else {
+            return;
+        };
+        let message = match external_change {
+            ExternalChange::Modified => format!(
+                "\"{}\" was changed by another program, and you have unsaved changes.",
+                tab.title
+            ),
+            ExternalChange::Deleted => format!(
+                "\"{}\" was deleted or moved. Save to write your version back, or close the tab.",
+                tab.title
+            ),
+        };
+
+        Window::new("File changed on disk")
+            .id(egui::Id::new(("external_change", tab.id)))
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .min_width(300.0)
+            .show(ui.ctx(), |ui| {
+                ui.label(message);
+                if let Some(lines) = &tab.external_diff {
+                    let (added, removed) = count_changes(lines);
+                    ui.label(format!(
+                        "The file on disk has {} lines added and {} removed compared to yours:",
+                        added, removed
+                    ));
+                    show_diff(ui, lines);
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    match external_change {
+                        ExternalChange::Modified => {
+                            if ui
+                                .button("Keep Mine")
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                tab.keep_mine();
+                            }
+                            if ui
+                                .button("Reload")
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                tab.reload();
+                            }
+                            if tab.external_diff.is_none()
+                                && ui
+                                    .button("Compare")
+                                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                    .clicked()
+                            {
+                                let disk_text = read_file(Path::new(&tab.file_path))
+                                    .map(|bytes| decode_bytes(&bytes, Some(tab.encoding)).text)
+                                    .map(|text| convert_line_endings(&text, LineEnding::Lf));
+                                let text = convert_buffer_line_endings(&tab.text, LineEnding::Lf);
+                                let diff = disk_text.and_then(|disk_text| {
+                                    diff_lines(&text, &disk_text).ok_or_else(|| {
+                                        "The file is too large to compare.".to_string()
+                                    })
+                                });
+                                match diff {
+                                    Ok(lines) => tab.external_diff = Some(lines),
+                                    Err(err) => {
+                                        tab.has_error = true;
+                                        tab.error_msg = err;
+                                    }
+                                }
+                            }
+                        }
+                        ExternalChange::Deleted => {
+                            if ui
+                                .button("Close Tab")
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                tab.is_closing = true;
+                            }
+                            if ui
+                                .button("Keep Mine")
+                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                .clicked()
+                            {
+                                tab.keep_mine();
+                            }
+                        }
+                    }
+                });
+            });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn session_keeps_unsaved_text_of_untitled_and_dirty_tabs() {
+            let mut tab = TextEditorTab::new("Untitled".into(), "draft".into());
+            tab.dirty = true;
+            tab.cursor = 2..4;
+
+            let restored = TextEditorTab::from_session(&tab.to_session(false).unwrap()).unwrap();
+
+            assert_eq!(restored.get_text(), "draft");
+            assert!(restored.dirty);
+            assert_eq!(restored.pending_cursor, Some(2..4));
+        }
+
+        #[test]
+        fn session_rereads_clean_files_and_drops_missing_ones() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            std::fs::write(&path, "on disk").unwrap();
+            let mut tab = TextEditorTab::new("".into(), "".into());
+            tab.open_file(&path, None).unwrap();
+
+            let session = tab.to_session(false).unwrap();
+            assert!(session.text.is_none());
+            assert_eq!(
+                TextEditorTab::from_session(&session).unwrap().get_text(),
+                "on disk"
+            );
+
+            std::fs::remove_file(&path).unwrap();
+            assert!(TextEditorTab::from_session(&session).is_none());
+        }
+
+        fn open(path: &Path, text: &str) -> TextEditorTab {
+            std::fs::write(path, text).unwrap();
+            let mut tab = TextEditorTab::new("".into(), "".into());
+            tab.open_file(path, None).unwrap();
+            tab
+        }
+
+        #[test]
+        fn restored_dirty_tab_flags_changes_made_while_closed() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            let mut tab = open(&path, "old");
+            tab.text = Buffer::new("mine".into());
+            tab.dirty = true;
+            let session = tab.to_session(false).unwrap();
+
+            let restored = TextEditorTab::from_session(&session).unwrap();
+            assert!(restored.external_change.is_none());
+
+            std::fs::write(&path, "changed by git").unwrap();
+            let mut restored = TextEditorTab::from_session(&session).unwrap();
+
+            assert_eq!(restored.get_text(), "mine");
+            assert!(restored.external_change == Some(ExternalChange::Modified));
+            assert!(!restored.save(BackupMode::None));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "changed by git");
+        }
+
+        #[test]
+        fn saving_keeps_the_line_endings_of_the_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            let mut tab = open(&path, "a\r\nb");
+            assert_eq!(tab.get_text(), "a\nb");
+
+            let end = tab.text.len_chars();
+            tab.text.replace_chars(end..end, "\nc");
+            tab.write_file(&path, BackupMode::None).unwrap();
+
+            assert_eq!(std::fs::read(&path).unwrap(), b"a\r\nb\r\nc");
+        }
+
+        #[test]
+        fn picked_language_outlasts_save_as_and_sessions() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut tab = open(&dir.path().join("build"), "#!/bin/sh\nmake");
+            assert_eq!(tab.get_language(), "shell");
+
+            tab.set_language(Some("makefile"));
+            tab.dirty = true;
+            tab.set_file_path(&dir.path().join("build.py")).unwrap();
+            assert_eq!(tab.get_language(), "makefile");
+            let restored = TextEditorTab::from_session(&tab.to_session(false).unwrap()).unwrap();
+            assert_eq!(restored.get_language(), "makefile");
+
+            tab.set_language(None);
+            assert_eq!(tab.get_language(), "python");
+            assert_eq!(tab.to_session(false).unwrap().language, None);
+        }
+
+        #[test]
+        fn clean_tab_reloads_when_the_file_changes() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            let mut tab = open(&path, "old");
+
+            std::fs::write(&path, "changed by git").unwrap();
+            tab.check_disk();
+
+            assert_eq!(tab.get_text(), "changed by git");
+            assert!(tab.external_change.is_none());
+        }
+
+        #[test]
+        fn dirty_tab_asks_and_refuses_to_overwrite() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            let mut tab = open(&path, "old");
+            tab.text = Buffer::new("mine".into());
+            tab.dirty = true;
+
+            std::fs::write(&path, "theirs").unwrap();
+            assert!(!tab.save(BackupMode::None));
+
+            assert!(tab.external_change == Some(ExternalChange::Modified));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "theirs");
+
+            tab.keep_mine();
+            assert!(tab.save(BackupMode::None));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "mine");
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn renamed_file_is_followed() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("old.txt");
+            let mut tab = open(&path, "text");
+
+            std::fs::rename(&path, dir.path().join("new.txt")).unwrap();
+            tab.check_disk();
+
+            assert_eq!(tab.title, "new.txt");
+            assert!(tab.external_change.is_none());
+        }
+
+        #[test]
+        fn deleted_file_keeps_the_buffer_as_unsaved() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file.txt");
+            let mut tab = open(&path, "text");
+
+            std::fs::remove_file(&path).unwrap();
+            tab.check_disk();
+
+            assert!(tab.external_change == Some(ExternalChange::Deleted));
+            assert!(tab.dirty);
+            assert_eq!(tab.get_text(), "text");
+        }
+
+        #[test]
+        fn discarding_changes_drops_untitled_drafts() {
+            let mut tab = TextEditorTab::new("Untitled".into(), "draft".into());
+            tab.dirty = true;
+
+            assert!(tab.to_session(true).is_none());
+        }
+    }
 }
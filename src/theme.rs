@@ -0,0 +1,628 @@
+pub mod theme {
+    use std::{collections::HashMap, fs, path::Path, path::PathBuf};
+
+    use egui::{Color32, Visuals};
+    use serde::{de::IgnoredAny, Deserialize};
+
+    use crate::highlight::highlight::SyntaxTheme;
+
+    /// Key of the theme picked in the View menu in eframe's storage
+    pub const THEME_KEY: &str = "theme";
+
+    /// Names of the built-in themes, used when following the system setting
+    const DARK: &str = "Dark";
+    const LIGHT: &str = "Light";
+
+    /// Where users put their own themes, as TOML or VS Code JSON files
+    pub fn get_themes_dir() -> Option<PathBuf> {
+        eframe::storage_dir("Reditor").map(|dir| dir.join("themes"))
+    }
+
+    /// TextMate scopes of VS Code themes and the highlight captures they color
+    const SCOPE_CAPTURES: &[(&str, &str)] = &[
+        ("comment", "comment"),
+        ("string", "string"),
+        ("constant.character.escape", "string.escape"),
+        ("constant.numeric", "number"),
+        ("constant.language", "constant.builtin"),
+        ("constant.language.boolean", "boolean"),
+        ("constant", "constant"),
+        ("keyword.operator", "operator"),
+        ("keyword", "keyword"),
+        ("storage", "keyword"),
+        ("entity.name.function", "function"),
+        ("support.function", "function.builtin"),
+        ("entity.name.type", "type"),
+        ("entity.name.class", "type"),
+        ("support.class", "type"),
+        ("support.type", "type.builtin"),
+        ("support.type.property-name", "property"),
+        ("entity.name.namespace", "module"),
+        ("entity.name.tag", "tag"),
+        ("entity.name.label", "label"),
+        ("entity.other.attribute-name", "attribute"),
+        ("variable.parameter", "variable.parameter"),
+        ("variable.language", "variable.builtin"),
+        ("variable.other.property", "property"),
+        ("meta.object-literal.key", "property"),
+        ("variable", "variable"),
+        ("punctuation", "punctuation"),
+        ("markup.heading", "text.title"),
+        ("markup.bold", "text.strong"),
+        ("markup.italic", "text.emphasis"),
+        ("markup.underline.link", "text.uri"),
+        ("markup.inline.raw", "text.literal"),
+        ("markup.raw", "text.literal"),
+    ];
+
+    /// Colors of the window and the text area. Colors a theme leaves out are egui's.
+    #[derive(Clone, Default)]
+    struct UiColors {
+        background: Option<Color32>,
+        foreground: Option<Color32>,
+        selection: Option<Color32>,
+        cursor: Option<Color32>,
+        panel: Option<Color32>,
+    }
+
+    impl UiColors {
+        fn set(&mut self, name: &str, color: Color32) -> Result<(), String> {
+            let field = match name {
+                "background" => &mut self.background,
+                "foreground" => &mut self.foreground,
+                "selection" => &mut self.selection,
+                "cursor" => &mut self.cursor,
+                "panel" => &mut self.panel,
+                _ => return Err(format!("Unknown color \"{}\"", name)),
+            };
+            *field = Some(color);
+            Ok(())
+        }
+    }
+
+    /// UI and syntax colors, built in or read from a theme file
+    #[derive(Clone)]
+    pub struct Theme {
+        pub name: String,
+        pub is_dark: bool,
+        colors: UiColors,
+        pub syntax: SyntaxTheme,
+    }
+
+    /// A theme file of our own: `name`, `type = "dark"` or `"light"`, a `[colors]` table
+    /// with `background`, `foreground`, `selection`, `cursor` and `panel`, and a `[syntax]`
+    /// table from highlight captures such as `keyword` or `function.method` to colors
+    #[derive(Deserialize)]
+    struct TomlTheme {
+        name: Option<String>,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        #[serde(default)]
+        colors: HashMap<String, String>,
+        #[serde(default)]
+        syntax: HashMap<String, String>,
+    }
+
+    /// The parts of a VS Code color theme that map onto ours
+    #[derive(Deserialize)]
+    struct VsCodeTheme {
+        name: Option<String>,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        #[serde(default)]
+        colors: HashMap<String, Option<String>>,
+        #[serde(default, rename = "tokenColors")]
+        token_colors: TokenColors,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TokenColors {
+        Rules(Vec<TokenColor>),
+        /// A path to a TextMate theme, which isn't followed
+        File(IgnoredAny),
+    }
+
+    impl Default for TokenColors {
+        fn default() -> Self {
+            Self::Rules(Vec::new())
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct TokenColor {
+        scope: Option<Scopes>,
+        #[serde(default)]
+        settings: TokenSettings,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scopes {
+        /// One scope, or several separated by commas
+        One(String),
+        Many(Vec<String>),
+    }
+
+    #[derive(Default, Deserialize)]
+    struct TokenSettings {
+        foreground: Option<String>,
+        background: Option<String>,
+    }
+
+    impl Theme {
+        pub fn dark() -> Self {
+            Self {
+                name: DARK.into(),
+                is_dark: true,
+                colors: UiColors::default(),
+                syntax: SyntaxTheme::dark(),
+            }
+        }
+
+        pub fn light() -> Self {
+            Self {
+                name: LIGHT.into(),
+                is_dark: false,
+                colors: UiColors::default(),
+                syntax: SyntaxTheme::light(),
+            }
+        }
+
+        /// A theme with the look of the built-in dark or light theme, depending on `kind`
+        /// or else on how dark `background` is
+        fn base(name: String, kind: Option<&str>, background: Option<Color32>) -> Self {
+            let is_dark = match kind {
+                Some(kind) => !kind.to_lowercase().contains("light"),
+                None => background.is_none_or(|color| get_luminance(color) < 0.5),
+            };
+            Self {
+                name,
+                ..if is_dark { Self::dark() } else { Self::light() }
+            }
+        }
+
+        /// Reads a `.toml` or VS Code `.json` theme, named after the file unless it names
+        /// itself
+        pub fn load(path: &Path) -> Result<Self, String> {
+            let text = fs::read_to_string(path)
+                .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+            let file_name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let theme = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => Self::from_vscode_json(file_name, &text),
+                _ => Self::from_toml(file_name, &text),
+            };
+            theme.map_err(|err| format!("{}: {}", path.display(), err))
+        }
+
+        pub fn from_toml(name: String, text: &str) -> Result<Self, String> {
+            let file = toml::from_str::<TomlTheme>(text).map_err(|err| err.to_string())?;
+            let background = file
+                .colors
+                .get("background")
+                .map(|color| parse_color(color));
+            let mut theme = Self::base(
+                file.name.unwrap_or(name),
+                file.kind.as_deref(),
+                background.transpose()?,
+            );
+            for (name, color) in &file.colors {
+                theme.colors.set(name, parse_color(color)?)?;
+            }
+            for (capture, color) in &file.syntax {
+                theme.syntax.set_color(capture, parse_color(color)?);
+            }
+            Ok(theme)
+        }
+
+        /// Reads a VS Code color theme. Token colors are matched to highlight captures by
+        /// their TextMate scope, and a rule for a more general scope wins over one for a
+        /// more specific scope, since a capture such as `keyword` covers all keywords.
+        pub fn from_vscode_json(name: String, text: &str) -> Result<Self, String> {
+            let file = serde_json::from_str::<VsCodeTheme>(&strip_json_comments(text))
+                .map_err(|err| err.to_string())?;
+            let rules = match file.token_colors {
+                TokenColors::Rules(rules) => rules,
+                TokenColors::File(_) => Vec::new(),
+            };
+            let get_color = |key: &str| match file.colors.get(key) {
+                Some(Some(color)) => parse_color(color).map(Some),
+                _ => Ok(None),
+            };
+            // Old themes keep the editor colors in a rule without a scope
+            let global = rules.iter().find(|rule| rule.scope.is_none());
+            let get_global = |color: Option<&String>| color.map(|color| parse_color(color));
+
+            let background = match get_color("editor.background")? {
+                Some(color) => Some(color),
+                None => get_global(global.and_then(|rule| rule.settings.background.as_ref()))
+                    .transpose()?,
+            };
+            let mut theme = Self::base(
+                file.name.clone().unwrap_or(name),
+                file.kind.as_deref(),
+                background,
+            );
+            theme.colors = UiColors {
+                background,
+                foreground: match get_color("editor.foreground")? {
+                    Some(color) => Some(color),
+                    None => get_global(global.and_then(|rule| rule.settings.foreground.as_ref()))
+                        .transpose()?,
+                },
+                selection: get_color("editor.selectionBackground")?,
+                cursor: get_color("editorCursor.foreground")?,
+                panel: match get_color("sideBar.background")? {
+                    Some(color) => Some(color),
+                    None => get_color("panel.background")?,
+                },
+            };
+
+            // Captures with the number of scope parts the rule coloring them went past
+            let mut colored = HashMap::new();
+            for rule in &rules {
+                let (Some(scopes), Some(foreground)) = (&rule.scope, &rule.settings.foreground)
+                else {
+                    continue;
+                };
+                let color = parse_color(foreground)?;
+                let scopes = match scopes {
+                    Scopes::One(scopes) => scopes.split(',').collect::<Vec<_>>(),
+                    Scopes::Many(scopes) => scopes.iter().map(String::as_str).collect(),
+                };
+                // Scopes under other scopes, such as `meta.tag string`, are left out
+                for scope in scopes.iter().map(|scope| scope.trim()) {
+                    if scope.contains(' ') {
+                        continue;
+                    }
+                    let Some((prefix, capture)) = SCOPE_CAPTURES
+                        .iter()
+                        .filter(|(prefix, _)| {
+                            scope == *prefix || scope.starts_with(&format!("{}.", prefix))
+                        })
+                        .max_by_key(|(prefix, _)| prefix.len())
+                    else {
+                        continue;
+                    };
+                    let extra_parts = scope[prefix.len()..].matches('.').count();
+                    if colored
+                        .get(capture)
+                        .is_some_and(|&parts| parts < extra_parts)
+                    {
+                        continue;
+                    }
+                    colored.insert(capture, extra_parts);
+                    theme.syntax.set_color(capture, color);
+                }
+            }
+            Ok(theme)
+        }
+
+        /// egui's look with the colors of this theme
+        pub fn get_visuals(&self) -> Visuals {
+            let mut visuals = if self.is_dark {
+                Visuals::dark()
+            } else {
+                Visuals::light()
+            };
+            let colors = &self.colors;
+            if let Some(color) = colors.background {
+                visuals.extreme_bg_color = color;
+            }
+            if let Some(color) = colors.foreground {
+                visuals.override_text_color = Some(color);
+            }
+            if let Some(color) = colors.selection {
+                visuals.selection.bg_fill = color;
+            }
+            if let Some(color) = colors.cursor {
+                visuals.text_cursor.stroke.color = color;
+            }
+            if let Some(color) = colors.panel {
+                visuals.panel_fill = color;
+                visuals.window_fill = color;
+            }
+            visuals
+        }
+    }
+
+    /// Reads `#rgb`, `#rrggbb` or `#rrggbbaa`
+    fn parse_color(text: &str) -> Result<Color32, String> {
+        let invalid = || format!("Invalid color \"{}\"", text);
+        let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let hex = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+            6 | 8 => hex.to_string(),
+            _ => return Err(invalid()),
+        };
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap_or(255);
+        let alpha = if hex.len() == 8 { channel(6) } else { 255 };
+        Ok(Color32::from_rgba_unmultiplied(
+            channel(0),
+            channel(2),
+            channel(4),
+            alpha,
+        ))
+    }
+
+    fn get_luminance(color: Color32) -> f32 {
+        (0.299 * color.r() as f32 + 0.587 * color.g() as f32 + 0.114 * color.b() as f32) / 255.0
+    }
+
+    /// Removes the comments and trailing commas VS Code allows in its JSON files
+    fn strip_json_comments(text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        let mut in_string = false;
+        while let Some(c) = chars.next() {
+            if in_string {
+                result.push(c);
+                match c {
+                    '\\' => result.extend(chars.next()),
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match (c, chars.peek()) {
+                ('"', _) => {
+                    in_string = true;
+                    result.push(c);
+                }
+                ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+                ('/', Some('*')) => {
+                    chars.next();
+                    let mut last = ' ';
+                    for c in chars.by_ref() {
+                        if last == '*' && c == '/' {
+                            break;
+                        }
+                        last = c;
+                    }
+                }
+                ('}' | ']', _) => {
+                    let content_end = result.trim_end().len();
+                    if result[..content_end].ends_with(',') {
+                        result.remove(content_end - 1);
+                    }
+                    result.push(c);
+                }
+                _ => result.push(c),
+            }
+        }
+        result
+    }
+
+    /// The built-in themes and the ones in the themes folder, and which of them is in use
+    pub struct Themes {
+        themes: Vec<Theme>,
+        /// Name of the theme picked in the View menu, or `None` to follow the system
+        picked: Option<String>,
+        /// Whether the system asks for dark colors, if it says
+        is_system_dark: Option<bool>,
+        /// The theme last set on the window, and the system setting at the time
+        applied: Option<(String, Option<bool>)>,
+    }
+
+    impl Default for Themes {
+        fn default() -> Self {
+            Self {
+                themes: vec![Theme::dark(), Theme::light()],
+                picked: None,
+                is_system_dark: None,
+                applied: None,
+            }
+        }
+    }
+
+    impl Themes {
+        /// Reads the `.toml` and `.json` themes in `dir` next to the built-in ones. Returns
+        /// the problems found along the way for the user to fix.
+        pub fn load(&mut self, dir: &Path) -> Vec<String> {
+            self.themes = vec![Theme::dark(), Theme::light()];
+            // Force the theme to be set again, as it may have changed on disk
+            self.applied = None;
+            let mut paths = match fs::read_dir(dir) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.extension()
+                            .is_some_and(|extension| extension == "toml" || extension == "json")
+                    })
+                    .collect::<Vec<_>>(),
+                Err(_) => return Vec::new(),
+            };
+            paths.sort();
+
+            let mut problems = Vec::new();
+            for path in paths {
+                match Theme::load(&path) {
+                    Ok(theme) => match self.themes.iter_mut().find(|t| t.name == theme.name) {
+                        Some(existing) => *existing = theme,
+                        None => self.themes.push(theme),
+                    },
+                    Err(err) => problems.push(err),
+                }
+            }
+            problems
+        }
+
+        pub fn get_themes(&self) -> &[Theme] {
+            &self.themes
+        }
+
+        pub fn get_picked(&self) -> Option<&str> {
+            self.picked.as_deref()
+        }
+
+        /// Switches to the theme named `name`, or back to following the system with `None`
+        pub fn pick(&mut self, name: Option<String>) {
+            self.picked = name;
+        }
+
+        pub fn set_system_dark(&mut self, is_system_dark: Option<bool>) {
+            self.is_system_dark = is_system_dark;
+        }
+
+        /// The picked theme, or the built-in one that goes with the system setting when no
+        /// theme is picked or the picked one is gone
+        pub fn get_active(&self) -> &Theme {
+            let system_name = if self.is_system_dark.unwrap_or(true) {
+                DARK
+            } else {
+                LIGHT
+            };
+            let find = |name: &str| self.themes.iter().find(|theme| theme.name == name);
+            self.picked
+                .as_deref()
+                .and_then(find)
+                .or_else(|| find(system_name))
+                .expect("built-in themes are always there")
+        }
+
+        /// Sets the look of the active theme on the window when it changed since the last
+        /// frame. eframe resets the look when the system setting changes, so that counts too.
+        pub fn apply(&mut self, ctx: &egui::Context) {
+            let theme = self.get_active();
+            let state = (theme.name.clone(), self.is_system_dark);
+            if self.applied.as_ref() != Some(&state) {
+                ctx.set_visuals(theme.get_visuals());
+                self.applied = Some(state);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn toml_themes_set_ui_and_syntax_colors() {
+            let theme = Theme::from_toml(
+                "file".into(),
+                r##"
+                name = "Paper"
+                type = "light"
+                [colors]
+                background = "#fafafa"
+                selection = "#0000ff40"
+                [syntax]
+                keyword = "#a00"
+                "##,
+            )
+            .unwrap();
+
+            assert_eq!(theme.name, "Paper");
+            assert!(!theme.is_dark);
+            assert_eq!(
+                theme.get_visuals().extreme_bg_color,
+                Color32::from_rgb(250, 250, 250)
+            );
+            assert_eq!(
+                theme.get_visuals().selection.bg_fill,
+                Color32::from_rgba_unmultiplied(0, 0, 255, 64)
+            );
+            assert_eq!(
+                theme.syntax.get_color("keyword"),
+                Some(Color32::from_rgb(170, 0, 0))
+            );
+            // Captures the theme leaves out keep the light colors
+            assert_eq!(
+                theme.syntax.get_color("string"),
+                SyntaxTheme::light().get_color("string")
+            );
+        }
+
+        #[test]
+        fn toml_theme_problems_are_reported() {
+            let bad_color = Theme::from_toml("t".into(), "[syntax]\nkeyword = \"red\"");
+            assert_eq!(bad_color.err().unwrap(), "Invalid color \"red\"");
+            let unknown = Theme::from_toml("t".into(), "[colors]\ngutter = \"#000\"");
+            assert_eq!(unknown.err().unwrap(), "Unknown color \"gutter\"");
+        }
+
+        #[test]
+        fn vscode_themes_map_scopes_to_captures() {
+            let theme = Theme::from_vscode_json(
+                "my-theme".into(),
+                r##"{
+                    // Comments and trailing commas are allowed
+                    "type": "dark",
+                    "colors": {
+                        "editor.background": "#1e1e1e", /* inline */
+                        "editor.foreground": "#d4d4d4",
+                        "editorCursor.foreground": null,
+                    },
+                    "tokenColors": [
+                        { "settings": { "foreground": "#ffffff" } },
+                        { "scope": "keyword.control", "settings": { "foreground": "#c586c0" } },
+                        { "scope": ["keyword", "storage.type"], "settings": { "foreground": "#569cd6" } },
+                        { "scope": "string, meta.tag string", "settings": { "foreground": "#ce9178" } },
+                        { "scope": "keyword.operator", "settings": { "foreground": "#d4d4d4" } },
+                        { "scope": "markup.bold", "settings": { "fontStyle": "bold" } },
+                    ],
+                }"##,
+            )
+            .unwrap();
+
+            assert_eq!(theme.name, "my-theme");
+            assert!(theme.is_dark);
+            let visuals = theme.get_visuals();
+            assert_eq!(visuals.extreme_bg_color, Color32::from_rgb(30, 30, 30));
+            assert_eq!(
+                visuals.override_text_color,
+                Some(Color32::from_rgb(212, 212, 212))
+            );
+            // The general `keyword` rule wins over the earlier, more specific one
+            assert_eq!(
+                theme.syntax.get_color("keyword"),
+                Some(Color32::from_rgb(86, 156, 214))
+            );
+            assert_eq!(
+                theme.syntax.get_color("string"),
+                Some(Color32::from_rgb(206, 145, 120))
+            );
+            assert_eq!(
+                theme.syntax.get_color("operator"),
+                Some(Color32::from_rgb(212, 212, 212))
+            );
+        }
+
+        #[test]
+        fn comments_are_stripped_outside_strings_only() {
+            assert_eq!(
+                strip_json_comments("{\"a\": \"//not\\\"/*\", // yes\n\"b\": [1,],}"),
+                "{\"a\": \"//not\\\"/*\", \n\"b\": [1]}"
+            );
+        }
+
+        #[test]
+        fn themes_follow_the_system_until_one_is_picked() {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("solar.toml"), "type = \"light\"").unwrap();
+            fs::write(dir.path().join("broken.json"), "{").unwrap();
+            let mut themes = Themes::default();
+            let problems = themes.load(dir.path());
+            assert_eq!(problems.len(), 1);
+            assert!(problems[0].contains("broken.json"));
+
+            assert_eq!(themes.get_active().name, "Dark");
+            themes.set_system_dark(Some(false));
+            assert_eq!(themes.get_active().name, "Light");
+
+            themes.pick(Some("solar".into()));
+            assert_eq!(themes.get_active().name, "solar");
+            themes.set_system_dark(Some(true));
+            assert_eq!(themes.get_active().name, "solar");
+
+            themes.pick(Some("deleted".into()));
+            assert_eq!(themes.get_active().name, "Dark");
+        }
+    }
+}
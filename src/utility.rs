@@ -1,95 +1,201 @@
 pub mod utility {
-    use std::cmp;
     use std::env;
+    use std::ops::Range;
     use std::sync::atomic::{AtomicUsize, Ordering};
 
-    pub fn get_line_ending_format() -> String {
-        let os = env::consts::OS;
+    use crate::buffer::buffer::Buffer;
+    use crate::enums::enums::LineEnding;
+    use crate::search::search::Matcher;
 
-        let line_ending = match os {
-            "windows" => "CRLF",
-            "macos" => "CR",
-            "linux" => "LF",
-            _ => "LF",
-        };
+    pub fn get_default_line_ending() -> LineEnding {
+        match env::consts::OS {
+            "windows" => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        }
+    }
 
-        let formal_os = match os {
-            "windows" => "Windows",
-            "macos" => "macOS",
-            "linux" => "Linux",
-            _ => "Unknown",
-        };
+    /// Detects the line ending style of `text`, falling back to the platform default
+    /// when the text has no line breaks
+    pub fn detect_line_ending(text: &str) -> LineEnding {
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    crlf += 1;
+                }
+                '\r' => cr += 1,
+                '\n' => lf += 1,
+                _ => {}
+            }
+        }
 
-        format!(
-            "{} ({})",
-            if formal_os == "Unknown" {
-                os
-            } else {
-                formal_os
-            },
-            line_ending
-        )
+        match (lf > 0, crlf > 0, cr > 0) {
+            (false, false, false) => get_default_line_ending(),
+            (true, false, false) => LineEnding::Lf,
+            (false, true, false) => LineEnding::Crlf,
+            (false, false, true) => LineEnding::Cr,
+            _ => LineEnding::Mixed,
+        }
     }
 
+    /// Rewrites every line break in `text` as `line_ending`. `Mixed` leaves the text untouched.
+    pub fn convert_line_endings(text: &str, line_ending: LineEnding) -> String {
+        let normalized = text.replace("\r\n", "\n").replace('\r', "\n");
+        match line_ending {
+            LineEnding::Lf => normalized,
+            LineEnding::Crlf => normalized.replace('\n', "\r\n"),
+            LineEnding::Cr => normalized.replace('\n', "\r"),
+            LineEnding::Mixed => text.to_string(),
+        }
+    }
+
+    /// Converts the text of `buffer` a line at a time, see [`convert_line_endings`]
+    pub fn convert_buffer_line_endings(buffer: &Buffer, line_ending: LineEnding) -> String {
+        buffer
+            .lines()
+            .map(|line| convert_line_endings(&line, line_ending))
+            .collect()
+    }
+
+    /// Finds the next match at or after char index `curr_start_idx` as a char range
     pub fn get_next_word_idx(
-        text: &str,
-        find_str: String,
+        buffer: &Buffer,
+        matcher: &Matcher,
         curr_start_idx: usize,
-        ui_width: usize,
-    ) -> Option<(usize, usize, usize, usize, usize)> {
-        let ccursor_index;
-        let pcursor_offset;
-        let pcursor_paragraph;
-        let rcursor_column;
-        let mut rcursor_row = 0;
-
-        // Find pure offset
-        let mut word_idx = text[curr_start_idx..].find(&find_str);
-        if word_idx.is_none() {
-            word_idx = text[0..curr_start_idx].find(&find_str);
-        } else {
-            word_idx = Some(word_idx.unwrap() + curr_start_idx);
-        }
+    ) -> Option<Range<usize>> {
+        let found = matcher.find_next(buffer, buffer.char_to_byte(curr_start_idx))?;
+        Some(buffer.byte_to_char(found.start)..buffer.byte_to_char(found.end))
+    }
 
-        if let Some(idx) = word_idx {
-            ccursor_index = idx;
+    /// Finds the closest match ending at or before char index `curr_start_idx` as a char range
+    pub fn get_prev_word_idx(
+        buffer: &Buffer,
+        matcher: &Matcher,
+        curr_start_idx: usize,
+    ) -> Option<Range<usize>> {
+        let found = matcher.find_prev(buffer, buffer.char_to_byte(curr_start_idx))?;
+        Some(buffer.byte_to_char(found.start)..buffer.byte_to_char(found.end))
+    }
+
+    /// Returns the 1-based position of the first of `matches`, in text order, that starts at
+    /// or after byte `start`, together with the total number of matches
+    pub fn get_match_position(matches: &[Range<usize>], start: usize) -> (usize, usize) {
+        let total = matches.len();
+        let before = matches.partition_point(|found| found.start < start);
+        let current = if before < total {
+            before + 1
         } else {
-            return None;
-        }
+            total.min(1)
+        };
+        (current, total)
+    }
+
+    /// Parses Go To input of the form `line`, `line:column`, `+N` or `-N`, where the relative
+    /// forms move from `current_line`. Lines and columns are 1-based.
+    pub fn parse_goto_target(input: &str, current_line: usize) -> Result<(usize, usize), String> {
+        let input = input.trim();
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("\"{}\" is not a valid line or column number", value.trim()))
+        };
 
-        // Find paragraph idx and offset within paragraph
-        pcursor_paragraph = text
-            .chars()
-            .take(ccursor_index)
-            .filter(|&c| c == '\n')
-            .count();
-        let paragraphs = text.split("\n").collect::<Vec<&str>>();
-        let cur_paragraph = paragraphs[pcursor_paragraph].to_string();
-        if let Some(offset) = cur_paragraph.find(&find_str) {
-            pcursor_offset = offset;
+        let (line, column) = if let Some(offset) = input.strip_prefix('+') {
+            (current_line + parse(offset)?, 1)
+        } else if let Some(offset) = input.strip_prefix('-') {
+            let offset = parse(offset)?;
+            if offset >= current_line {
+                return Err(format!(
+                    "Cannot go {} line(s) up from line {}",
+                    offset, current_line
+                ));
+            }
+            (current_line - offset, 1)
+        } else if let Some((line, column)) = input.split_once(':') {
+            (parse(line)?, parse(column)?)
         } else {
-            pcursor_offset = 0;
+            (parse(input)?, 1)
+        };
+
+        if line == 0 || column == 0 {
+            return Err("Lines and columns start at 1".into());
         }
+        Ok((line, column))
+    }
 
-        // Find row idx and col idx
-        rcursor_column = pcursor_offset % ui_width;
-        for i in 0..pcursor_paragraph {
-            rcursor_row += cmp::max(1, paragraphs[i].len() / ui_width);
+    /// Converts a 1-based line and column into a char index, checking both are in range
+    pub fn get_line_col_char_idx(
+        buffer: &Buffer,
+        line: usize,
+        column: usize,
+    ) -> Result<usize, String> {
+        let line_count = buffer.len_lines();
+        if line > line_count {
+            return Err(format!("Line {} is out of range (1-{})", line, line_count));
+        }
+        let column_count = buffer.line_len_chars(line - 1) + 1;
+        if column > column_count {
+            return Err(format!(
+                "Column {} is out of range for line {} (1-{})",
+                column, line, column_count
+            ));
         }
-        rcursor_row += cmp::max(1, pcursor_offset / ui_width) - 1;
-
-        Some((
-            ccursor_index,
-            rcursor_row,
-            rcursor_column,
-            pcursor_paragraph,
-            pcursor_offset,
-        ))
+        Ok(buffer.line_to_char(line - 1) + column - 1)
     }
 
-    static mut COUNTER: AtomicUsize = AtomicUsize::new(0);
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
     pub fn get_next_id() -> usize {
-        unsafe { COUNTER.fetch_add(1, Ordering::Relaxed) }
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn detects_each_line_ending_style() {
+            assert!(detect_line_ending("a\nb\n") == LineEnding::Lf);
+            assert!(detect_line_ending("a\r\nb\r\n") == LineEnding::Crlf);
+            assert!(detect_line_ending("a\rb\r") == LineEnding::Cr);
+            assert!(detect_line_ending("a\r\nb\nc") == LineEnding::Mixed);
+            assert!(detect_line_ending("a\rb\r\n") == LineEnding::Mixed);
+        }
+
+        #[test]
+        fn text_without_line_breaks_uses_the_platform_default() {
+            assert!(detect_line_ending("") == get_default_line_ending());
+            assert!(detect_line_ending("one line") == get_default_line_ending());
+        }
+
+        #[test]
+        fn converts_every_line_break_except_for_mixed() {
+            let text = "a\r\nb\rc\nd";
+
+            assert_eq!(convert_line_endings(text, LineEnding::Lf), "a\nb\nc\nd");
+            assert_eq!(
+                convert_line_endings(text, LineEnding::Crlf),
+                "a\r\nb\r\nc\r\nd"
+            );
+            assert_eq!(convert_line_endings(text, LineEnding::Cr), "a\rb\rc\rd");
+            assert_eq!(convert_line_endings(text, LineEnding::Mixed), text);
+        }
+
+        #[test]
+        fn converts_a_buffer_line_by_line() {
+            let buffer = Buffer::new("one\ntwo\r\nthree\n".into());
+
+            assert_eq!(
+                convert_buffer_line_endings(&buffer, LineEnding::Crlf),
+                "one\r\ntwo\r\nthree\r\n"
+            );
+            assert_eq!(
+                convert_buffer_line_endings(&buffer, LineEnding::Mixed),
+                "one\ntwo\r\nthree\n"
+            );
+        }
     }
 }
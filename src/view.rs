@@ -0,0 +1,696 @@
+pub mod view {
+    use std::collections::HashMap;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    use egui::{
+        output::IMEOutput,
+        text::{CCursor, LayoutJob, TextFormat},
+        Color32, CursorIcon, Event, EventFilter, FontId, Galley, Id, ImeEvent, Key, Modifiers,
+        Pos2, Rect, Response, Sense, Ui, Vec2,
+    };
+
+    use crate::buffer::buffer::Buffer;
+    use crate::highlight::highlight::{Highlighter, SyntaxTheme};
+    use crate::search::search::Matcher;
+
+    /// Space between the edge of the view and the text
+    const MARGIN: Vec2 = Vec2::new(4.0, 2.0);
+
+    const FONT_SIZE: f32 = 10.0;
+
+    /// Cursor and selection of a [`CodeView`] as char indices, kept in egui's memory
+    #[derive(Clone, Copy, Default)]
+    struct ViewState {
+        anchor: usize,
+        head: usize,
+        /// Where Up and Down aim on the next line, kept while moving across shorter lines
+        preferred_x: Option<f32>,
+    }
+
+    impl ViewState {
+        fn range(&self) -> Range<usize> {
+            self.anchor.min(self.head)..self.anchor.max(self.head)
+        }
+    }
+
+    /// Galleys of the lines drawn in the last frame. Lines are keyed by their text and
+    /// colors, so an edit only lays out the lines it touched again, and everything is laid
+    /// out anew when the font or scale changes.
+    #[derive(Default)]
+    pub struct LineCache {
+        settings: u64,
+        font_id: FontId,
+        text_color: Color32,
+        row_height: f32,
+        /// Width of the longest line laid out so far, which sets how far the view scrolls
+        max_width: f32,
+        galleys: HashMap<u64, Arc<Galley>>,
+        unused: HashMap<u64, Arc<Galley>>,
+    }
+
+    impl LineCache {
+        /// Drops the lines that were not drawn in the last frame
+        fn begin_frame(&mut self, ui: &Ui) {
+            let font_id = FontId::monospace(FONT_SIZE);
+            let text_color = ui.visuals().text_color();
+            let pixels_per_point = ui.ctx().pixels_per_point().to_bits();
+            let settings = get_hash(&(&font_id, text_color, pixels_per_point));
+            if settings != self.settings || self.row_height == 0.0 {
+                *self = Self {
+                    settings,
+                    row_height: ui.fonts(|f| f.row_height(&font_id)),
+                    font_id,
+                    text_color,
+                    ..Self::default()
+                };
+            }
+            self.unused = std::mem::take(&mut self.galleys);
+        }
+
+        /// Lays out `line`, painting the byte ranges in `colors` in their color
+        fn get(&mut self, ui: &Ui, line: &str, colors: &[(Range<usize>, Color32)]) -> Arc<Galley> {
+            let key = get_hash(&(line, colors));
+            if let Some(galley) = self.galleys.get(&key) {
+                return galley.clone();
+            }
+            let galley = match self.unused.remove(&key) {
+                Some(galley) => galley,
+                None => self.layout(ui, line, colors),
+            };
+            self.max_width = self.max_width.max(galley.size().x);
+            self.galleys.insert(key, galley.clone());
+            galley
+        }
+
+        /// How many lines are laid out
+        #[cfg(test)]
+        pub fn len(&self) -> usize {
+            self.galleys.len()
+        }
+
+        fn layout(&self, ui: &Ui, line: &str, colors: &[(Range<usize>, Color32)]) -> Arc<Galley> {
+            let mut job = LayoutJob::default();
+            let append = |job: &mut LayoutJob, range: Range<usize>, color| {
+                if !range.is_empty() {
+                    job.append(
+                        &line[range],
+                        0.0,
+                        TextFormat::simple(self.font_id.clone(), color),
+                    );
+                }
+            };
+            let mut last_end = 0;
+            for (range, color) in colors {
+                append(&mut job, last_end..range.start, self.text_color);
+                append(&mut job, range.clone(), *color);
+                last_end = range.end;
+            }
+            append(&mut job, last_end..line.len(), self.text_color);
+            if job.sections.is_empty() {
+                // An empty line still needs a font for its height
+                job.append(
+                    "",
+                    0.0,
+                    TextFormat::simple(self.font_id.clone(), self.text_color),
+                );
+            }
+            ui.fonts(|f| f.layout_job(job))
+        }
+    }
+
+    fn get_hash(value: &(impl Hash + ?Sized)) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// What a [`CodeView`] did in a frame
+    pub struct ViewOutput {
+        /// Marked as changed when the text was edited
+        pub response: Response,
+        /// The selected chars, empty where the cursor is when nothing is selected
+        pub selection: Range<usize>,
+        /// The char index of the cursor, at either end of the selection
+        pub cursor: usize,
+    }
+
+    /// A text area for code that lays out and paints only the lines in view, so that a frame
+    /// takes as long for a huge file as for a small one. Meant to be put in a
+    /// [`egui::ScrollArea`], whose visible part it reads from the clip rect.
+    pub struct CodeView<'a> {
+        id: Id,
+        buffer: &'a mut Buffer,
+        cache: &'a mut LineCache,
+        highlighter: Option<&'a Highlighter>,
+        syntax_theme: Option<&'a SyntaxTheme>,
+        matcher: Option<&'a Matcher>,
+        selection: Option<Range<usize>>,
+        scroll_to_selection: bool,
+    }
+
+    impl<'a> CodeView<'a> {
+        pub fn new(id: Id, buffer: &'a mut Buffer, cache: &'a mut LineCache) -> Self {
+            Self {
+                id,
+                buffer,
+                cache,
+                highlighter: None,
+                syntax_theme: None,
+                matcher: None,
+                selection: None,
+                scroll_to_selection: false,
+            }
+        }
+
+        /// Colors the text with the syntax tree of `highlighter`
+        pub fn highlighter(mut self, highlighter: &'a Highlighter, theme: &'a SyntaxTheme) -> Self {
+            self.highlighter = Some(highlighter);
+            self.syntax_theme = Some(theme);
+            self
+        }
+
+        /// Marks every match of `matcher` that is in view
+        pub fn matcher(mut self, matcher: Option<&'a Matcher>) -> Self {
+            self.matcher = matcher;
+            self
+        }
+
+        /// Selects the `range` of chars and focuses the view, scrolling to the selection
+        /// if `scroll` is set
+        pub fn select(mut self, range: Range<usize>, scroll: bool) -> Self {
+            self.selection = Some(range);
+            self.scroll_to_selection = scroll;
+            self
+        }
+
+        pub fn show(self, ui: &mut Ui) -> ViewOutput {
+            let Self {
+                id,
+                buffer,
+                cache,
+                highlighter,
+                syntax_theme,
+                matcher,
+                selection,
+                scroll_to_selection,
+            } = self;
+            cache.begin_frame(ui);
+
+            let mut state = ui.data(|d| d.get_temp::<ViewState>(id)).unwrap_or_default();
+            // The text may have been changed elsewhere, e.g. by undo
+            let len = buffer.len_chars();
+            state.anchor = state.anchor.min(len);
+            state.head = state.head.min(len);
+            let mut scroll_to_cursor = false;
+            if let Some(range) = selection {
+                state = ViewState {
+                    anchor: range.start.min(len),
+                    head: range.end.min(len),
+                    preferred_x: None,
+                };
+                ui.memory_mut(|mem| mem.request_focus(id));
+                scroll_to_cursor = scroll_to_selection;
+            }
+
+            let size = Vec2::new(
+                cache.max_width,
+                buffer.len_lines() as f32 * cache.row_height,
+            ) + 2.0 * MARGIN;
+            let (_, rect) = ui.allocate_space(size.max(ui.available_size()));
+            let mut response = ui.interact(rect, id, Sense::click_and_drag());
+            let mut frame = Frame {
+                ui,
+                buffer,
+                cache,
+                highlighter: highlighter.zip(syntax_theme),
+                galleys: HashMap::new(),
+                origin: rect.min + MARGIN,
+                state,
+            };
+
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(CursorIcon::Text);
+            }
+            if let Some(pos) = response.interact_pointer_pos() {
+                frame.on_pointer(&response, pos);
+            }
+
+            if response.has_focus() {
+                ui.memory_mut(|mem| {
+                    mem.set_focus_lock_filter(
+                        id,
+                        EventFilter {
+                            tab: true,
+                            horizontal_arrows: true,
+                            vertical_arrows: true,
+                            escape: false,
+                        },
+                    )
+                });
+                let page_lines = (ui.clip_rect().height() / frame.cache.row_height) as usize;
+                let head = frame.state.head;
+                let events = ui.input(|i| i.events.clone());
+                for event in events {
+                    let changed = match event {
+                        Event::Text(text) if text != "\n" && text != "\r" => {
+                            frame.replace_selection(&text)
+                        }
+                        Event::Paste(text) => frame.replace_selection(&text),
+                        Event::Ime(ImeEvent::Commit(text)) => frame.replace_selection(&text),
+                        Event::Copy => {
+                            frame.copy_selection();
+                            false
+                        }
+                        Event::Cut => {
+                            frame.copy_selection();
+                            frame.replace_selection("")
+                        }
+                        Event::Key {
+                            key: Key::Escape,
+                            pressed: true,
+                            ..
+                        } => {
+                            response.surrender_focus();
+                            false
+                        }
+                        Event::Key {
+                            key,
+                            pressed: true,
+                            modifiers,
+                            ..
+                        } => frame.on_key(key, modifiers, page_lines.max(1)),
+                        _ => false,
+                    };
+                    if changed {
+                        response.mark_changed();
+                    }
+                }
+                scroll_to_cursor |= response.changed() || frame.state.head != head;
+            }
+
+            let cursor_top = frame.cursor_pos(frame.state.head);
+            let cursor_rect =
+                Rect::from_min_size(cursor_top, Vec2::new(1.0, frame.cache.row_height));
+            if scroll_to_cursor {
+                ui.scroll_to_rect(cursor_rect.expand2(MARGIN), None);
+            }
+            if response.has_focus() {
+                ui.ctx().output_mut(|o| {
+                    o.ime = Some(IMEOutput {
+                        rect: ui.clip_rect(),
+                        cursor_rect,
+                    })
+                });
+            }
+            frame.paint(rect, matcher, response.has_focus());
+
+            let state = frame.state;
+            ui.data_mut(|d| d.insert_temp(id, state));
+            ViewOutput {
+                response,
+                selection: state.range(),
+                cursor: state.head,
+            }
+        }
+    }
+
+    /// A [`CodeView`] while it handles the input of a frame and paints it
+    struct Frame<'a> {
+        ui: &'a Ui,
+        buffer: &'a mut Buffer,
+        cache: &'a mut LineCache,
+        highlighter: Option<(&'a Highlighter, &'a SyntaxTheme)>,
+        /// Lines laid out in this frame
+        galleys: HashMap<usize, Arc<Galley>>,
+        /// Where the first line starts
+        origin: Pos2,
+        state: ViewState,
+    }
+
+    impl Frame<'_> {
+        fn get_galley(&mut self, line: usize) -> Arc<Galley> {
+            if let Some(galley) = self.galleys.get(&line) {
+                return galley.clone();
+            }
+            let text = self.buffer.line(line);
+            let colors = match self.highlighter {
+                Some((highlighter, theme)) => {
+                    let start = self.buffer.line_to_byte(line);
+                    highlighter.get_colors(self.buffer, start..start + text.len(), theme)
+                }
+                None => Vec::new(),
+            };
+            let galley = self.cache.get(self.ui, &text, &colors);
+            self.galleys.insert(line, galley.clone());
+            galley
+        }
+
+        fn char_at(&mut self, pos: Pos2) -> usize {
+            let line = ((pos.y - self.origin.y) / self.cache.row_height).max(0.0) as usize;
+            let line = line.min(self.buffer.len_lines() - 1);
+            let galley = self.get_galley(line);
+            let column = galley
+                .cursor_from_pos(Vec2::new(pos.x - self.origin.x, 0.0))
+                .ccursor
+                .index;
+            self.buffer.line_to_char(line) + column.min(self.buffer.line_len_chars(line))
+        }
+
+        /// The top of the cursor placed before char `char_idx`
+        fn cursor_pos(&mut self, char_idx: usize) -> Pos2 {
+            let line = self.buffer.char_to_line(char_idx);
+            let column = char_idx - self.buffer.line_to_char(line);
+            let galley = self.get_galley(line);
+            let x = galley.pos_from_ccursor(CCursor::new(column)).min.x;
+            self.origin + Vec2::new(x, line as f32 * self.cache.row_height)
+        }
+
+        fn move_head(&mut self, char_idx: usize, is_selecting: bool) {
+            self.state.head = char_idx;
+            if !is_selecting {
+                self.state.anchor = char_idx;
+            }
+            self.state.preferred_x = None;
+        }
+
+        /// Moves the cursor `delta` lines up or down, keeping to the column it started on
+        fn move_lines(&mut self, delta: isize, is_selecting: bool) {
+            let x = match self.state.preferred_x {
+                Some(x) => x,
+                None => self.cursor_pos(self.state.head).x - self.origin.x,
+            };
+            let line = self.buffer.char_to_line(self.state.head) as isize + delta;
+            let char_idx = if line < 0 {
+                0
+            } else if line as usize >= self.buffer.len_lines() {
+                self.buffer.len_chars()
+            } else {
+                let line = line as usize;
+                let galley = self.get_galley(line);
+                let column = galley.cursor_from_pos(Vec2::new(x, 0.0)).ccursor.index;
+                self.buffer.line_to_char(line) + column.min(self.buffer.line_len_chars(line))
+            };
+            self.move_head(char_idx, is_selecting);
+            self.state.preferred_x = Some(x);
+        }
+
+        /// Replaces the selection with `text`, leaving the cursor after it.
+        /// Returns whether the text changed.
+        fn replace_selection(&mut self, text: &str) -> bool {
+            let range = self.state.range();
+            if range.is_empty() && text.is_empty() {
+                return false;
+            }
+            self.buffer.replace_chars(range.clone(), text);
+            self.galleys.clear();
+            self.move_head(range.start + text.chars().count(), false);
+            true
+        }
+
+        fn copy_selection(&self) {
+            let range = self.state.range();
+            if !range.is_empty() {
+                self.ui
+                    .ctx()
+                    .copy_text(self.buffer.slice_chars(range).to_string());
+            }
+        }
+
+        fn on_pointer(&mut self, response: &Response, pos: Pos2) {
+            let char_idx = self.char_at(pos);
+            let (is_pressed, is_shift) = self
+                .ui
+                .input(|i| (i.pointer.any_pressed(), i.modifiers.shift));
+            if response.double_clicked() {
+                let word = get_word_range(self.buffer, char_idx);
+                self.state.anchor = word.start;
+                self.move_head(word.end, true);
+            } else if response.is_pointer_button_down_on() {
+                // Pressing starts a selection that dragging extends
+                self.move_head(char_idx, !is_pressed || is_shift);
+            }
+            if is_pressed {
+                response.request_focus();
+            }
+        }
+
+        /// Handles a key press, returning whether the text changed
+        fn on_key(&mut self, key: Key, modifiers: Modifiers, page_lines: usize) -> bool {
+            let head = self.state.head;
+            let range = self.state.range();
+            let is_selecting = modifiers.shift;
+            let by_word = modifiers.alt || modifiers.ctrl;
+            let line = self.buffer.char_to_line(head);
+            let line_start = self.buffer.line_to_char(line);
+            let line_end = line_start + self.buffer.line_len_chars(line);
+            match key {
+                Key::ArrowLeft | Key::ArrowRight
+                    if !is_selecting && !by_word && !modifiers.mac_cmd && !range.is_empty() =>
+                {
+                    let char_idx = if key == Key::ArrowLeft {
+                        range.start
+                    } else {
+                        range.end
+                    };
+                    self.move_head(char_idx, false);
+                }
+                Key::ArrowLeft => {
+                    let char_idx = if modifiers.mac_cmd {
+                        line_start
+                    } else if by_word {
+                        get_prev_word_start(self.buffer, head)
+                    } else {
+                        head.saturating_sub(1)
+                    };
+                    self.move_head(char_idx, is_selecting);
+                }
+                Key::ArrowRight => {
+                    let char_idx = if modifiers.mac_cmd {
+                        line_end
+                    } else if by_word {
+                        get_next_word_end(self.buffer, head)
+                    } else {
+                        (head + 1).min(self.buffer.len_chars())
+                    };
+                    self.move_head(char_idx, is_selecting);
+                }
+                Key::ArrowUp if modifiers.mac_cmd => self.move_head(0, is_selecting),
+                Key::ArrowDown if modifiers.mac_cmd => {
+                    self.move_head(self.buffer.len_chars(), is_selecting)
+                }
+                Key::ArrowUp => self.move_lines(-1, is_selecting),
+                Key::ArrowDown => self.move_lines(1, is_selecting),
+                Key::PageUp => self.move_lines(-(page_lines as isize), is_selecting),
+                Key::PageDown => self.move_lines(page_lines as isize, is_selecting),
+                Key::Home if modifiers.command => self.move_head(0, is_selecting),
+                Key::End if modifiers.command => {
+                    self.move_head(self.buffer.len_chars(), is_selecting)
+                }
+                Key::Home => self.move_head(line_start, is_selecting),
+                Key::End => self.move_head(line_end, is_selecting),
+                Key::A if modifiers.command => {
+                    self.state.anchor = 0;
+                    self.move_head(self.buffer.len_chars(), true);
+                }
+                Key::Backspace => {
+                    if range.is_empty() {
+                        self.state.anchor = if by_word {
+                            get_prev_word_start(self.buffer, head)
+                        } else {
+                            head.saturating_sub(1)
+                        };
+                    }
+                    return self.replace_selection("");
+                }
+                Key::Delete => {
+                    if range.is_empty() {
+                        self.state.anchor = if by_word {
+                            get_next_word_end(self.buffer, head)
+                        } else {
+                            (head + 1).min(self.buffer.len_chars())
+                        };
+                    }
+                    return self.replace_selection("");
+                }
+                Key::Enter => return self.replace_selection("\n"),
+                Key::Tab if modifiers.is_none() => return self.replace_selection("\t"),
+                _ => {}
+            }
+            false
+        }
+
+        fn paint(&mut self, rect: Rect, matcher: Option<&Matcher>, has_focus: bool) {
+            let ui = self.ui;
+            let visuals = ui.visuals();
+            let clip_rect = ui.clip_rect().intersect(rect);
+            ui.painter()
+                .rect_filled(clip_rect, 0.0, visuals.extreme_bg_color);
+
+            let row_height = self.cache.row_height;
+            let line_count = self.buffer.len_lines();
+            let first_line = ((clip_rect.top() - self.origin.y) / row_height).max(0.0) as usize;
+            let last_line = ((clip_rect.bottom() - self.origin.y) / row_height)
+                .ceil()
+                .max(0.0) as usize;
+            let lines = first_line.min(line_count)..last_line.min(line_count);
+            let visible = self.buffer.line_to_char(lines.start)..if lines.end < line_count {
+                self.buffer.line_to_char(lines.end)
+            } else {
+                self.buffer.len_chars()
+            };
+
+            if let Some(matcher) = matcher {
+                // Only the text in view is searched, a line at a time at most
+                let start = self.buffer.char_to_byte(visible.start);
+                let end = self.buffer.char_to_byte(visible.end);
+                let found = matcher
+                    .find_in(self.buffer, start..end)
+                    .map(|found| {
+                        self.buffer.byte_to_char(found.start)..self.buffer.byte_to_char(found.end)
+                    })
+                    .collect::<Vec<_>>();
+                let color = visuals.selection.bg_fill.gamma_multiply(0.4);
+                for range in found {
+                    self.paint_range(range, color);
+                }
+            }
+
+            let selection = self.state.range();
+            let selection = selection.start.max(visible.start)..selection.end.min(visible.end);
+            self.paint_range(selection, visuals.selection.bg_fill);
+
+            for line in lines {
+                let galley = self.get_galley(line);
+                let pos = self.origin + Vec2::new(0.0, line as f32 * row_height);
+                ui.painter().galley(pos, galley, visuals.text_color());
+            }
+
+            if has_focus {
+                let top = self.cursor_pos(self.state.head);
+                ui.painter().line_segment(
+                    [top, top + Vec2::new(0.0, row_height)],
+                    visuals.text_cursor.stroke,
+                );
+            }
+        }
+
+        /// Paints a highlight behind the chars in `range`, one line at a time
+        fn paint_range(&mut self, range: Range<usize>, color: Color32) {
+            if range.is_empty() {
+                return;
+            }
+            let row_height = self.cache.row_height;
+            let first_line = self.buffer.char_to_line(range.start);
+            let last_line = self.buffer.char_to_line(range.end);
+            for line in first_line..=last_line {
+                let start = if line == first_line {
+                    self.cursor_pos(range.start)
+                } else {
+                    self.origin + Vec2::new(0.0, line as f32 * row_height)
+                };
+                let end_x = if line == last_line {
+                    self.cursor_pos(range.end).x
+                } else {
+                    // The line break is shown as a bit of extra space
+                    let line_end =
+                        self.buffer.line_to_char(line) + self.buffer.line_len_chars(line);
+                    self.cursor_pos(line_end).x + row_height / 2.0
+                };
+                let rect = Rect::from_min_max(start, Pos2::new(end_x, start.y + row_height));
+                self.ui.painter().rect_filled(rect, 0.0, color);
+            }
+        }
+    }
+
+    /// Sorts chars into words, whitespace and runs of other chars for moving by word
+    fn get_char_class(c: char) -> u8 {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Skips whitespace and then one word forwards from char `char_idx`
+    fn get_next_word_end(buffer: &Buffer, char_idx: usize) -> usize {
+        let mut end = char_idx;
+        let mut class = None;
+        for c in buffer.chars_at(char_idx) {
+            match (class, get_char_class(c)) {
+                (None, 1) => {}
+                (None, c_class) => class = Some(c_class),
+                (Some(class), c_class) if class != c_class => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        end
+    }
+
+    /// Skips whitespace and then one word backwards from char `char_idx`
+    fn get_prev_word_start(buffer: &Buffer, char_idx: usize) -> usize {
+        let mut start = char_idx.min(buffer.len_chars());
+        let mut class = None;
+        let mut chars = buffer.chars_at(start);
+        while let Some(c) = chars.prev() {
+            match (class, get_char_class(c)) {
+                (None, 1) => {}
+                (None, c_class) => class = Some(c_class),
+                (Some(class), c_class) if class != c_class => break,
+                _ => {}
+            }
+            start -= 1;
+        }
+        start
+    }
+
+    /// The word, whitespace or run of other chars around char `char_idx`, as selected by a
+    /// double click
+    fn get_word_range(buffer: &Buffer, char_idx: usize) -> Range<usize> {
+        let mut chars = buffer.chars_at(char_idx);
+        let Some(class) = chars.next().or_else(|| chars.prev()).map(get_char_class) else {
+            return char_idx..char_idx;
+        };
+        let mut start = char_idx.min(buffer.len_chars());
+        let mut chars = buffer.chars_at(start);
+        while chars.prev().is_some_and(|c| get_char_class(c) == class) {
+            start -= 1;
+        }
+        let end = char_idx
+            + buffer
+                .chars_at(char_idx)
+                .take_while(|&c| get_char_class(c) == class)
+                .count();
+        start..end
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn words_are_skipped_with_the_whitespace_before_them() {
+            let buffer = Buffer::new("let x = foo_bar();".into());
+
+            assert_eq!(get_next_word_end(&buffer, 0), 3);
+            assert_eq!(get_next_word_end(&buffer, 3), 5);
+            assert_eq!(get_next_word_end(&buffer, 7), 15);
+            assert_eq!(get_prev_word_start(&buffer, 15), 8);
+            assert_eq!(get_prev_word_start(&buffer, 8), 6);
+            assert_eq!(get_prev_word_start(&buffer, 0), 0);
+        }
+
+        #[test]
+        fn double_click_selects_the_word_under_the_pointer() {
+            let buffer = Buffer::new("one two".into());
+
+            assert_eq!(get_word_range(&buffer, 5), 4..7);
+            assert_eq!(get_word_range(&buffer, 7), 4..7);
+            assert_eq!(get_word_range(&buffer, 3), 3..4);
+        }
+    }
+}